@@ -5,9 +5,11 @@
 // and using https://illumos.org/man/3nvpair
 
 
-use std::os::raw::{c_char, c_int, c_uint};
+use std::os::raw::{c_char, c_int, c_uint, c_uchar};
 pub type uint_t = c_uint;
 pub type int = c_int;
+pub type uchar_t = c_uchar;
+pub type hrtime_t = i64;
 
 #[repr(C)]
 #[derive(PartialEq, Debug, Clone)]
@@ -67,16 +69,25 @@ pub struct nvlist_t {
 	pub nvl_pad:     i32,	/* currently not used, for alignment */
 }
 
-const NV_UNIQUE_NAME: uint_t = 1;
-const NV_UNIQUE_NAME_TYPE: uint_t = 2;
+pub const NV_UNIQUE_NAME: uint_t = 1;
+pub const NV_UNIQUE_NAME_TYPE: uint_t = 2;
+
+pub const NV_ENCODE_NATIVE: int = 0;
+pub const NV_ENCODE_XDR: int = 1;
 
 #[link(name="nvpair", kind="dylib")]
 extern {
     pub fn nvlist_alloc(target: *mut *mut nvlist_t, nvflag: uint_t, flag: int) -> c_int;
     pub fn nvlist_free(list: *mut nvlist_t);
+    pub fn nvlist_nvflag(list: *mut nvlist_t) -> uint_t;
+    pub fn nvlist_size(list: *mut nvlist_t, size: *mut usize, encoding: int) -> c_int;
+    pub fn nvlist_pack(list: *mut nvlist_t, buf: *mut *mut c_char, size: *mut usize, encoding: int, flag: int) -> c_int;
+    pub fn nvlist_unpack(buf: *mut c_char, size: usize, target: *mut *mut nvlist_t, flag: int) -> c_int;
+    pub fn nvlist_dup(list: *mut nvlist_t, target: *mut *mut nvlist_t, flag: int) -> c_int;
     pub fn nvlist_empty(list: *mut nvlist_t) -> boolean_t;
     pub fn nvlist_exists(list: *mut nvlist_t, name: *const c_char) -> boolean_t;
     pub fn nvlist_remove(list: *mut nvlist_t, name: *const c_char, data_type: data_type_t) -> c_int;
+    pub fn nvlist_remove_all(list: *mut nvlist_t, name: *const c_char) -> c_int;
 
     pub fn nvlist_next_nvpair(list: *mut nvlist_t, pair: *mut nvpair_t) -> *mut nvpair_t;
     pub fn nvpair_name(pair: *const nvpair_t) -> *const c_char;
@@ -91,6 +102,82 @@ extern {
 
     pub fn nvlist_add_boolean_array(list: *const nvlist_t, name: *const c_char, vals: *mut boolean_t, nr_elem: uint_t) -> c_int;
     pub fn nvpair_value_boolean_array(pair: *const nvpair_t, vals: *mut *mut boolean_t, nr_elem: *mut uint_t) -> c_int;
+
+    pub fn nvlist_add_byte(list: *mut nvlist_t, name: *const c_char, val: uchar_t) -> c_int;
+    pub fn nvlist_add_int8(list: *mut nvlist_t, name: *const c_char, val: i8) -> c_int;
+    pub fn nvlist_add_uint8(list: *mut nvlist_t, name: *const c_char, val: u8) -> c_int;
+    pub fn nvlist_add_int16(list: *mut nvlist_t, name: *const c_char, val: i16) -> c_int;
+    pub fn nvlist_add_uint16(list: *mut nvlist_t, name: *const c_char, val: u16) -> c_int;
+    pub fn nvlist_add_int32(list: *mut nvlist_t, name: *const c_char, val: i32) -> c_int;
+    pub fn nvlist_add_uint32(list: *mut nvlist_t, name: *const c_char, val: u32) -> c_int;
+    pub fn nvlist_add_int64(list: *mut nvlist_t, name: *const c_char, val: i64) -> c_int;
+    pub fn nvlist_add_uint64(list: *mut nvlist_t, name: *const c_char, val: u64) -> c_int;
+    pub fn nvlist_add_string(list: *mut nvlist_t, name: *const c_char, val: *const c_char) -> c_int;
+    pub fn nvlist_add_hrtime(list: *mut nvlist_t, name: *const c_char, val: hrtime_t) -> c_int;
+    pub fn nvlist_add_nvlist(list: *mut nvlist_t, name: *const c_char, val: *mut nvlist_t) -> c_int;
+
+    pub fn nvlist_add_byte_array(list: *mut nvlist_t, name: *const c_char, vals: *mut uchar_t, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_int8_array(list: *mut nvlist_t, name: *const c_char, vals: *mut i8, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_uint8_array(list: *mut nvlist_t, name: *const c_char, vals: *mut u8, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_int16_array(list: *mut nvlist_t, name: *const c_char, vals: *mut i16, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_uint16_array(list: *mut nvlist_t, name: *const c_char, vals: *mut u16, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_int32_array(list: *mut nvlist_t, name: *const c_char, vals: *mut i32, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_uint32_array(list: *mut nvlist_t, name: *const c_char, vals: *mut u32, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_int64_array(list: *mut nvlist_t, name: *const c_char, vals: *mut i64, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_uint64_array(list: *mut nvlist_t, name: *const c_char, vals: *mut u64, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_string_array(list: *mut nvlist_t, name: *const c_char, vals: *const *const c_char, nr_elem: uint_t) -> c_int;
+    pub fn nvlist_add_nvlist_array(list: *mut nvlist_t, name: *const c_char, vals: *mut *mut nvlist_t, nr_elem: uint_t) -> c_int;
+
+    pub fn nvlist_lookup_byte(list: *const nvlist_t, name: *const c_char, val: *mut uchar_t) -> c_int;
+    pub fn nvlist_lookup_int8(list: *const nvlist_t, name: *const c_char, val: *mut i8) -> c_int;
+    pub fn nvlist_lookup_uint8(list: *const nvlist_t, name: *const c_char, val: *mut u8) -> c_int;
+    pub fn nvlist_lookup_int16(list: *const nvlist_t, name: *const c_char, val: *mut i16) -> c_int;
+    pub fn nvlist_lookup_uint16(list: *const nvlist_t, name: *const c_char, val: *mut u16) -> c_int;
+    pub fn nvlist_lookup_int32(list: *const nvlist_t, name: *const c_char, val: *mut i32) -> c_int;
+    pub fn nvlist_lookup_uint32(list: *const nvlist_t, name: *const c_char, val: *mut u32) -> c_int;
+    pub fn nvlist_lookup_int64(list: *const nvlist_t, name: *const c_char, val: *mut i64) -> c_int;
+    pub fn nvlist_lookup_uint64(list: *const nvlist_t, name: *const c_char, val: *mut u64) -> c_int;
+    pub fn nvlist_lookup_string(list: *const nvlist_t, name: *const c_char, val: *mut *mut c_char) -> c_int;
+    pub fn nvlist_lookup_hrtime(list: *const nvlist_t, name: *const c_char, val: *mut hrtime_t) -> c_int;
+    pub fn nvlist_lookup_nvlist(list: *const nvlist_t, name: *const c_char, val: *mut *mut nvlist_t) -> c_int;
+
+    pub fn nvlist_lookup_boolean_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut boolean_t, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_byte_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut uchar_t, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_int8_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut i8, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_uint8_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut u8, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_int16_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut i16, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_uint16_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut u16, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_int32_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut i32, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_uint32_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut u32, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_int64_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut i64, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_uint64_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut u64, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_string_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut *mut c_char, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvlist_lookup_nvlist_array(list: *const nvlist_t, name: *const c_char, vals: *mut *mut *mut nvlist_t, nr_elem: *mut uint_t) -> c_int;
+
+    pub fn nvpair_value_byte(pair: *const nvpair_t, val: *mut uchar_t) -> c_int;
+    pub fn nvpair_value_int8(pair: *const nvpair_t, val: *mut i8) -> c_int;
+    pub fn nvpair_value_uint8(pair: *const nvpair_t, val: *mut u8) -> c_int;
+    pub fn nvpair_value_int16(pair: *const nvpair_t, val: *mut i16) -> c_int;
+    pub fn nvpair_value_uint16(pair: *const nvpair_t, val: *mut u16) -> c_int;
+    pub fn nvpair_value_int32(pair: *const nvpair_t, val: *mut i32) -> c_int;
+    pub fn nvpair_value_uint32(pair: *const nvpair_t, val: *mut u32) -> c_int;
+    pub fn nvpair_value_int64(pair: *const nvpair_t, val: *mut i64) -> c_int;
+    pub fn nvpair_value_uint64(pair: *const nvpair_t, val: *mut u64) -> c_int;
+    pub fn nvpair_value_string(pair: *const nvpair_t, val: *mut *mut c_char) -> c_int;
+    pub fn nvpair_value_hrtime(pair: *const nvpair_t, val: *mut hrtime_t) -> c_int;
+    pub fn nvpair_value_nvlist(pair: *const nvpair_t, val: *mut *mut nvlist_t) -> c_int;
+
+    pub fn nvpair_value_byte_array(pair: *const nvpair_t, vals: *mut *mut uchar_t, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_int8_array(pair: *const nvpair_t, vals: *mut *mut i8, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_uint8_array(pair: *const nvpair_t, vals: *mut *mut u8, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_int16_array(pair: *const nvpair_t, vals: *mut *mut i16, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_uint16_array(pair: *const nvpair_t, vals: *mut *mut u16, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_int32_array(pair: *const nvpair_t, vals: *mut *mut i32, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_uint32_array(pair: *const nvpair_t, vals: *mut *mut u32, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_int64_array(pair: *const nvpair_t, vals: *mut *mut i64, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_uint64_array(pair: *const nvpair_t, vals: *mut *mut u64, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_string_array(pair: *const nvpair_t, vals: *mut *mut *mut c_char, nr_elem: *mut uint_t) -> c_int;
+    pub fn nvpair_value_nvlist_array(pair: *const nvpair_t, vals: *mut *mut *mut nvlist_t, nr_elem: *mut uint_t) -> c_int;
 }
 
 #[cfg(test)]
@@ -0,0 +1,326 @@
+// Deserializes any `serde::Deserialize` value out of a `List`.
+//
+// Lists map to structs and maps, arrays to sequences and bare
+// `DATA_TYPE_BOOLEAN` flags to unit values. A flag can also be read as a
+// `bool`, which makes `#[serde(default)] flag: bool` true when the flag is
+// present.
+
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::de::value::{BorrowedStrDeserializer, SeqDeserializer};
+
+use super::{Error, List, PairValue};
+
+/// Deserializes a struct or map out of a list.
+pub fn from_list<'de, T: Deserialize<'de>>(list: &'de List) -> Result<T, Error> {
+    T::deserialize(ListDeserializer { list: list })
+}
+
+impl de::Error for Error {
+    fn custom<T: ::std::fmt::Display>(msg: T) -> Error {
+        Error::Serde(msg.to_string())
+    }
+}
+
+/// Deserializes from a borrowed `List`.
+pub struct ListDeserializer<'de, 'l: 'de> {
+    list: &'de List<'l>
+}
+
+/// Deserializes from a single borrowed `PairValue`.
+pub struct ValueDeserializer<'de, 'v: 'de> {
+    value: &'de PairValue<'v>
+}
+
+impl<'de, 'l> IntoDeserializer<'de, Error> for &'de List<'l> {
+    type Deserializer = ListDeserializer<'de, 'l>;
+
+    fn into_deserializer(self) -> ListDeserializer<'de, 'l> {
+        ListDeserializer { list: self }
+    }
+}
+
+impl<'de, 'v> IntoDeserializer<'de, Error> for &'de PairValue<'v> {
+    type Deserializer = ValueDeserializer<'de, 'v>;
+
+    fn into_deserializer(self) -> ValueDeserializer<'de, 'v> {
+        ValueDeserializer { value: self }
+    }
+}
+
+struct MapAccess<'de, 'l: 'de> {
+    list: &'de List<'l>,
+    next: usize
+}
+
+impl<'de, 'l> de::MapAccess<'de> for MapAccess<'de, 'l> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.list.pairs.get(self.next) {
+            Some(pair) => {
+                let key: &'de str = &pair.0;
+                seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let pair = &self.list.pairs[self.next];
+        self.next += 1;
+        seed.deserialize(ValueDeserializer { value: &pair.1 })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.list.pairs.len() - self.next)
+    }
+}
+
+impl<'de, 'l> de::Deserializer<'de> for ListDeserializer<'de, 'l> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(MapAccess { list: self.list, next: 0 })
+    }
+
+    // An enum is stored as a list with a single pair named after the
+    // variant.
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        if self.list.pairs.len() != 1 {
+            return Err(Error::Serde("an enum must be stored as a list with exactly one pair".to_owned()));
+        }
+        visitor.visit_enum(EnumAccess { list: self.list })
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumAccess<'de, 'l: 'de> {
+    list: &'de List<'l>
+}
+
+impl<'de, 'l> de::EnumAccess<'de> for EnumAccess<'de, 'l> {
+    type Error = Error;
+    type Variant = ValueDeserializer<'de, 'l>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Error> {
+        let pair = &self.list.pairs[0];
+        let key: &'de str = &pair.0;
+        let variant = try!(seed.deserialize(BorrowedStrDeserializer::<Error>::new(key)));
+        Ok((variant, ValueDeserializer { value: &pair.1 }))
+    }
+}
+
+impl<'de, 'v> de::VariantAccess<'de> for ValueDeserializer<'de, 'v> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+fn visit_seq<'de, V, I>(visitor: V, iter: I) -> Result<V::Value, Error>
+    where V: Visitor<'de>,
+          I: Iterator,
+          I::Item: IntoDeserializer<'de, Error>
+{
+    let mut seq = SeqDeserializer::new(iter);
+    let value = try!(visitor.visit_seq(&mut seq));
+    try!(seq.end());
+    Ok(value)
+}
+
+impl<'de, 'v> de::Deserializer<'de> for ValueDeserializer<'de, 'v> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match *self.value {
+            PairValue::Boolean => visitor.visit_unit(),
+            PairValue::BooleanValue(v) => visitor.visit_bool(v),
+            PairValue::Byte(v) => visitor.visit_u8(v),
+            PairValue::Int8(v) => visitor.visit_i8(v),
+            PairValue::Uint8(v) => visitor.visit_u8(v),
+            PairValue::Int16(v) => visitor.visit_i16(v),
+            PairValue::Uint16(v) => visitor.visit_u16(v),
+            PairValue::Int32(v) => visitor.visit_i32(v),
+            PairValue::Uint32(v) => visitor.visit_u32(v),
+            PairValue::Int64(v) => visitor.visit_i64(v),
+            PairValue::Uint64(v) => visitor.visit_u64(v),
+            PairValue::HrTime(v) => visitor.visit_i64(v),
+            PairValue::String(ref v) => visitor.visit_borrowed_str(v),
+            PairValue::NvList(ref v) => ListDeserializer { list: v }.deserialize_any(visitor),
+            PairValue::BooleanValueArray(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::ByteArray(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Int8Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Uint8Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Int16Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Uint16Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Int32Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Uint32Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Int64Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::Uint64Array(ref v) => visit_seq(visitor, v.iter().cloned()),
+            PairValue::StringArray(ref v) => visit_seq(visitor, v.iter().map(|s| BorrowedStr(s))),
+            PairValue::NvListArray(ref v) => visit_seq(visitor, v.iter()),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match *self.value {
+            PairValue::Boolean => visitor.visit_bool(true),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match *self.value {
+            PairValue::ByteArray(ref v) | PairValue::Uint8Array(ref v) => visitor.visit_borrowed_bytes(v),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Unit variants are stored as strings, all others as single pair lists.
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        match *self.value {
+            PairValue::String(ref v) => visitor.visit_enum(BorrowedStrDeserializer::new(v)),
+            PairValue::NvList(ref v) => ListDeserializer { list: v }.deserialize_enum(name, variants, visitor),
+            _ => Err(Error::Serde("an enum must be stored as a string or a list".to_owned()))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+// Lets string array elements be borrowed instead of copied.
+struct BorrowedStr<'de>(&'de str);
+
+impl<'de> IntoDeserializer<'de, Error> for BorrowedStr<'de> {
+    type Deserializer = BorrowedStrDeserializer<'de, Error>;
+
+    fn into_deserializer(self) -> BorrowedStrDeserializer<'de, Error> {
+        BorrowedStrDeserializer::new(self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use super::super::{List, Pair, PairValue, Uniqueness, to_list};
+    use super::from_list;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Vdev {
+        id: u64,
+        path: String,
+        #[serde(default)]
+        is_log: bool
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum State {
+        Active,
+        Exported
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config<'a> {
+        name: &'a str,
+        version: u64,
+        state: State,
+        txg: Option<u64>,
+        comment: Option<String>,
+        children: Vec<Vdev>,
+        features: BTreeMap<String, u64>,
+        hole_array: Vec<u64>
+    }
+
+    fn sample() -> List<'static> {
+        let mut child = List::new(Uniqueness::Name);
+        child.add(Pair("id".into(), PairValue::Uint64(0)));
+        child.add(Pair("path".into(), PairValue::String("/dev/sda".to_owned())));
+        child.add(Pair("is_log".into(), PairValue::Boolean));
+        let mut features = List::new(Uniqueness::Name);
+        features.add(Pair("com.delphix:hole_birth".into(), PairValue::Uint64(1)));
+
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("name".into(), PairValue::String("tank".to_owned())));
+        l.add(Pair("version".into(), PairValue::Uint64(5000)));
+        l.add(Pair("state".into(), PairValue::String("active".to_owned())));
+        l.add(Pair("txg".into(), PairValue::Uint32(4)));
+        l.add(Pair("children".into(), PairValue::NvListArray(vec![child])));
+        l.add(Pair("features".into(), PairValue::NvList(features)));
+        l.add(Pair("hole_array".into(), PairValue::Uint64Array(vec![2, 3])));
+        l
+    }
+
+    #[test]
+    fn struct_from_list() {
+        let l = sample();
+        let config: Config = from_list(&l).unwrap();
+        let mut features = BTreeMap::new();
+        features.insert("com.delphix:hole_birth".to_owned(), 1);
+        assert_eq!(config, Config {
+            name: "tank",
+            version: 5000,
+            state: State::Active,
+            txg: Some(4),
+            comment: None,
+            children: vec![Vdev { id: 0, path: "/dev/sda".to_owned(), is_log: true }],
+            features: features,
+            hole_array: vec![2, 3]
+        });
+    }
+
+    #[test]
+    fn missing_field_is_an_error() {
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("id".into(), PairValue::Uint64(0)));
+        assert!(from_list::<Vdev>(&l).is_err());
+    }
+
+    #[test]
+    fn to_list_from_list_roundtrip() {
+        let vdev = Vdev { id: 3, path: "/dev/sdb".to_owned(), is_log: false };
+        let l = to_list(&vdev).unwrap();
+        assert_eq!(from_list::<Vdev>(&l).unwrap(), vdev);
+    }
+}
@@ -0,0 +1,265 @@
+// A lossless JSON representation of a `List`.
+//
+// Every list is an object with its uniqueness and an ordered array of
+// pairs, and every pair records its data type next to its value, so int8
+// and uint64 values, flags and duplicate names all survive a round trip:
+//
+//     {"uniqueness": "name",
+//      "pairs": [{"name": "version", "type": "uint64", "value": 5000},
+//                {"name": "is_log", "type": "boolean"}]}
+
+use serde_json::{self, Map, Value};
+
+use super::{Error, List, Pair, PairValue, Uniqueness};
+
+fn invalid<T>(reason: String) -> Result<T, Error> {
+    Err(Error::InvalidJson(reason))
+}
+
+fn uniqueness_name(u: &Uniqueness) -> &'static str {
+    match *u {
+        Uniqueness::Unknown => "unknown",
+        Uniqueness::None => "none",
+        Uniqueness::Name => "name",
+        Uniqueness::Pair => "pair",
+    }
+}
+
+fn type_name(value: &PairValue) -> &'static str {
+    match *value {
+        PairValue::Boolean => "boolean",
+        PairValue::BooleanValue(_) => "boolean_value",
+        PairValue::Byte(_) => "byte",
+        PairValue::Int8(_) => "int8",
+        PairValue::Uint8(_) => "uint8",
+        PairValue::Int16(_) => "int16",
+        PairValue::Uint16(_) => "uint16",
+        PairValue::Int32(_) => "int32",
+        PairValue::Uint32(_) => "uint32",
+        PairValue::Int64(_) => "int64",
+        PairValue::Uint64(_) => "uint64",
+        PairValue::String(_) => "string",
+        PairValue::HrTime(_) => "hrtime",
+        PairValue::NvList(_) => "nvlist",
+        PairValue::BooleanValueArray(_) => "boolean_array",
+        PairValue::ByteArray(_) => "byte_array",
+        PairValue::Int8Array(_) => "int8_array",
+        PairValue::Uint8Array(_) => "uint8_array",
+        PairValue::Int16Array(_) => "int16_array",
+        PairValue::Uint16Array(_) => "uint16_array",
+        PairValue::Int32Array(_) => "int32_array",
+        PairValue::Uint32Array(_) => "uint32_array",
+        PairValue::Int64Array(_) => "int64_array",
+        PairValue::Uint64Array(_) => "uint64_array",
+        PairValue::StringArray(_) => "string_array",
+        PairValue::NvListArray(_) => "nvlist_array",
+    }
+}
+
+fn value_to_json(value: &PairValue) -> Option<Value> {
+    Some(match *value {
+        PairValue::Boolean => return None,
+        PairValue::BooleanValue(v) => Value::from(v),
+        PairValue::Byte(v) => Value::from(v),
+        PairValue::Int8(v) => Value::from(v),
+        PairValue::Uint8(v) => Value::from(v),
+        PairValue::Int16(v) => Value::from(v),
+        PairValue::Uint16(v) => Value::from(v),
+        PairValue::Int32(v) => Value::from(v),
+        PairValue::Uint32(v) => Value::from(v),
+        PairValue::Int64(v) => Value::from(v),
+        PairValue::Uint64(v) => Value::from(v),
+        PairValue::String(ref v) => Value::from(v.clone()),
+        PairValue::HrTime(v) => Value::from(v),
+        PairValue::NvList(ref v) => to_value(v),
+        PairValue::BooleanValueArray(ref v) => Value::from(v.clone()),
+        PairValue::ByteArray(ref v) => Value::from(v.clone()),
+        PairValue::Int8Array(ref v) => Value::from(v.clone()),
+        PairValue::Uint8Array(ref v) => Value::from(v.clone()),
+        PairValue::Int16Array(ref v) => Value::from(v.clone()),
+        PairValue::Uint16Array(ref v) => Value::from(v.clone()),
+        PairValue::Int32Array(ref v) => Value::from(v.clone()),
+        PairValue::Uint32Array(ref v) => Value::from(v.clone()),
+        PairValue::Int64Array(ref v) => Value::from(v.clone()),
+        PairValue::Uint64Array(ref v) => Value::from(v.clone()),
+        PairValue::StringArray(ref v) => Value::from(v.clone()),
+        PairValue::NvListArray(ref v) => Value::Array(v.iter().map(to_value).collect()),
+    })
+}
+
+/// Converts the list into its JSON representation.
+pub fn to_value(list: &List) -> Value {
+    let pairs = list.pairs.iter().map(|pair| {
+        let mut object = Map::new();
+        object.insert("name".to_owned(), Value::from(&*pair.0));
+        object.insert("type".to_owned(), Value::from(type_name(&pair.1)));
+        if let Some(value) = value_to_json(&pair.1) {
+            object.insert("value".to_owned(), value);
+        }
+        Value::Object(object)
+    }).collect();
+
+    let mut object = Map::new();
+    object.insert("uniqueness".to_owned(), Value::from(uniqueness_name(&list.uniqueness)));
+    object.insert("pairs".to_owned(), Value::Array(pairs));
+    Value::Object(object)
+}
+
+/// Renders the list as pretty-printed JSON.
+pub fn to_string(list: &List) -> String {
+    serde_json::to_string_pretty(&to_value(list)).unwrap()
+}
+
+fn integer<T, F>(value: &Value, convert: F) -> Result<T, Error>
+    where F: Fn(&Value) -> Option<T>
+{
+    match convert(value) {
+        Some(v) => Ok(v),
+        None => invalid(format!("{} is not a valid value for this type", value))
+    }
+}
+
+fn array<T, F>(value: &Value, convert: F) -> Result<Vec<T>, Error>
+    where F: Fn(&Value) -> Result<T, Error>
+{
+    match *value {
+        Value::Array(ref values) => values.iter().map(convert).collect(),
+        _ => invalid(format!("{} is not an array", value))
+    }
+}
+
+fn as_i8(v: &Value) -> Option<i8> { v.as_i64().and_then(|v| if v as i8 as i64 == v { Some(v as i8) } else { None }) }
+fn as_i16(v: &Value) -> Option<i16> { v.as_i64().and_then(|v| if v as i16 as i64 == v { Some(v as i16) } else { None }) }
+fn as_i32(v: &Value) -> Option<i32> { v.as_i64().and_then(|v| if v as i32 as i64 == v { Some(v as i32) } else { None }) }
+fn as_u8(v: &Value) -> Option<u8> { v.as_u64().and_then(|v| if v as u8 as u64 == v { Some(v as u8) } else { None }) }
+fn as_u16(v: &Value) -> Option<u16> { v.as_u64().and_then(|v| if v as u16 as u64 == v { Some(v as u16) } else { None }) }
+fn as_u32(v: &Value) -> Option<u32> { v.as_u64().and_then(|v| if v as u32 as u64 == v { Some(v as u32) } else { None }) }
+
+fn string(value: &Value) -> Result<String, Error> {
+    match value.as_str() {
+        Some(s) => Ok(s.to_owned()),
+        None => invalid(format!("{} is not a string", value))
+    }
+}
+
+fn value_from_json(type_name: &str, value: Option<&Value>) -> Result<PairValue<'static>, Error> {
+    if type_name == "boolean" {
+        return Ok(PairValue::Boolean);
+    }
+    let value = match value {
+        Some(v) => v,
+        None => return invalid(format!("missing value for {} pair", type_name))
+    };
+
+    Ok(match type_name {
+        "boolean_value" => PairValue::BooleanValue(try!(integer(value, Value::as_bool))),
+        "byte" => PairValue::Byte(try!(integer(value, as_u8))),
+        "int8" => PairValue::Int8(try!(integer(value, as_i8))),
+        "uint8" => PairValue::Uint8(try!(integer(value, as_u8))),
+        "int16" => PairValue::Int16(try!(integer(value, as_i16))),
+        "uint16" => PairValue::Uint16(try!(integer(value, as_u16))),
+        "int32" => PairValue::Int32(try!(integer(value, as_i32))),
+        "uint32" => PairValue::Uint32(try!(integer(value, as_u32))),
+        "int64" => PairValue::Int64(try!(integer(value, Value::as_i64))),
+        "uint64" => PairValue::Uint64(try!(integer(value, Value::as_u64))),
+        "string" => PairValue::String(try!(string(value))),
+        "hrtime" => PairValue::HrTime(try!(integer(value, Value::as_i64))),
+        "nvlist" => PairValue::NvList(try!(from_value(value))),
+        "boolean_array" => PairValue::BooleanValueArray(try!(array(value, |v| integer(v, Value::as_bool)))),
+        "byte_array" => PairValue::ByteArray(try!(array(value, |v| integer(v, as_u8)))),
+        "int8_array" => PairValue::Int8Array(try!(array(value, |v| integer(v, as_i8)))),
+        "uint8_array" => PairValue::Uint8Array(try!(array(value, |v| integer(v, as_u8)))),
+        "int16_array" => PairValue::Int16Array(try!(array(value, |v| integer(v, as_i16)))),
+        "uint16_array" => PairValue::Uint16Array(try!(array(value, |v| integer(v, as_u16)))),
+        "int32_array" => PairValue::Int32Array(try!(array(value, |v| integer(v, as_i32)))),
+        "uint32_array" => PairValue::Uint32Array(try!(array(value, |v| integer(v, as_u32)))),
+        "int64_array" => PairValue::Int64Array(try!(array(value, |v| integer(v, Value::as_i64)))),
+        "uint64_array" => PairValue::Uint64Array(try!(array(value, |v| integer(v, Value::as_u64)))),
+        "string_array" => PairValue::StringArray(try!(array(value, string))),
+        "nvlist_array" => PairValue::NvListArray(try!(array(value, from_value))),
+        other => return Err(Error::UnkownDataType(other.to_owned()))
+    })
+}
+
+/// Converts the JSON representation produced by `to_value` back into a
+/// list.
+pub fn from_value(value: &Value) -> Result<List<'static>, Error> {
+    let uniqueness = match value.get("uniqueness").and_then(Value::as_str) {
+        Some("unknown") => Uniqueness::Unknown,
+        Some("none") => Uniqueness::None,
+        Some("name") => Uniqueness::Name,
+        Some("pair") => Uniqueness::Pair,
+        _ => return invalid(format!("{} has no valid uniqueness", value))
+    };
+    let pairs = match value.get("pairs").and_then(Value::as_array) {
+        Some(pairs) => pairs,
+        None => return invalid(format!("{} has no pairs array", value))
+    };
+
+    let mut list = List::new(uniqueness);
+    for pair in pairs {
+        let name = match pair.get("name").and_then(Value::as_str) {
+            Some(name) => name.to_owned(),
+            None => return invalid(format!("{} has no name", pair))
+        };
+        let type_name = match pair.get("type").and_then(Value::as_str) {
+            Some(t) => t,
+            None => return invalid(format!("{} has no type", pair))
+        };
+        let value = try!(value_from_json(type_name, pair.get("value")));
+        list.add(Pair(name.into(), value));
+    }
+    Ok(list)
+}
+
+/// Parses a list from its JSON representation.
+pub fn from_str(s: &str) -> Result<List<'static>, Error> {
+    match serde_json::from_str(s) {
+        Ok(value) => from_value(&value),
+        Err(e) => invalid(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Error, List, Pair, PairValue, Uniqueness};
+    use super::{from_str, to_string};
+
+    #[test]
+    fn roundtrip_keeps_types() {
+        let mut inner = List::new(Uniqueness::Pair);
+        inner.add(Pair("int8".into(), PairValue::Int8(-1)));
+        inner.add(Pair("uint64".into(), PairValue::Uint64(u64::max_value())));
+        let mut l = List::new(Uniqueness::None);
+        l.add(Pair("flag".into(), PairValue::Boolean));
+        l.add(Pair("flag".into(), PairValue::Boolean));
+        l.add(Pair("hrtime".into(), PairValue::HrTime(-5)));
+        l.add(Pair("bytes".into(), PairValue::ByteArray(vec![0, 255])));
+        l.add(Pair("inner".into(), PairValue::NvList(inner.clone())));
+        l.add(Pair("inners".into(), PairValue::NvListArray(vec![inner])));
+        l.add(Pair("strings".into(), PairValue::StringArray(vec!["a".to_owned()])));
+
+        assert_eq!(from_str(&to_string(&l)), Ok(l));
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let json = r#"{"uniqueness": "name", "pairs": [{"name": "a", "type": "int8", "value": 128}]}"#;
+        match from_str(json) {
+            Err(Error::InvalidJson(_)) => (),
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_golden_json() {
+        let json = r#"{"uniqueness": "name", "pairs": [
+            {"name": "version", "type": "uint64", "value": 5000},
+            {"name": "is_log", "type": "boolean"}
+        ]}"#;
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("version".into(), PairValue::Uint64(5000)));
+        l.add(Pair("is_log".into(), PairValue::Boolean));
+        assert_eq!(from_str(json), Ok(l));
+    }
+}
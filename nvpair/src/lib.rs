@@ -1,31 +1,105 @@
 extern crate nvpair_sys;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(test)]
+extern crate quickcheck;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
 pub use nvpair_sys as ffi;
 
+mod nvlist;
+pub use nvlist::{NvList, NvListRef, PairRef, Pairs};
+pub mod pack;
+pub use pack::Encoding;
+mod print;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "serde")]
+mod ser;
+#[cfg(feature = "serde")]
+mod de;
+#[cfg(feature = "serde")]
+pub use ser::to_list;
+#[cfg(feature = "serde")]
+pub use de::{from_list, ListDeserializer, ValueDeserializer};
+
 use std::borrow::Cow;
+use std::fmt;
 use std::ptr;
 use ffi::*;
 use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
 
 pub type Key<'k> = Cow<'k,str>;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
     UnkownDataType(String),
-    IllegalName(std::str::Utf8Error)
+    IllegalName(std::str::Utf8Error),
+    IllegalString(std::str::Utf8Error),
+    InteriorNul(std::ffi::NulError),
+    Errno(c_int),
+    InvalidPacked(String),
+    Serde(String),
+    InvalidJson(String)
 }
 
-#[derive(Debug, PartialEq)]
-pub enum PairValue {
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnkownDataType(ref t) => write!(f, "unknown nvpair data type {}", t),
+            Error::IllegalName(ref e) => write!(f, "pair name is not valid UTF-8: {}", e),
+            Error::IllegalString(ref e) => write!(f, "string value is not valid UTF-8: {}", e),
+            Error::InteriorNul(ref e) => write!(f, "{}", e),
+            Error::Errno(e) => write!(f, "libnvpair returned errno {}", e),
+            Error::InvalidPacked(ref reason) => write!(f, "invalid packed nvlist: {}", reason),
+            Error::Serde(ref msg) => write!(f, "{}", msg),
+            Error::InvalidJson(ref reason) => write!(f, "invalid nvlist JSON: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PairValue<'v> {
     Boolean,
     BooleanValue(bool),
+    Byte(u8),
+    Int8(i8),
+    Uint8(u8),
+    Int16(i16),
+    Uint16(u16),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    String(String),
+    HrTime(i64),
+    NvList(List<'v>),
     BooleanValueArray(Vec<bool>),
+    ByteArray(Vec<u8>),
+    Int8Array(Vec<i8>),
+    Uint8Array(Vec<u8>),
+    Int16Array(Vec<i16>),
+    Uint16Array(Vec<u16>),
+    Int32Array(Vec<i32>),
+    Uint32Array(Vec<u32>),
+    Int64Array(Vec<i64>),
+    Uint64Array(Vec<u64>),
+    StringArray(Vec<String>),
+    NvListArray(Vec<List<'v>>),
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Pair<'k>(Key<'k>,PairValue);
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pair<'k>(Key<'k>,PairValue<'k>);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Uniqueness {
     Unknown,
     None,
@@ -33,6 +107,37 @@ pub enum Uniqueness {
     Pair
 }
 
+impl Uniqueness {
+    fn from_nvflag(nvflag: uint_t) -> Uniqueness {
+        if nvflag & NV_UNIQUE_NAME_TYPE != 0 {
+            Uniqueness::Pair
+        } else if nvflag & NV_UNIQUE_NAME != 0 {
+            Uniqueness::Name
+        } else {
+            Uniqueness::None
+        }
+    }
+
+    fn to_nvflag(&self) -> uint_t {
+        match *self {
+            Uniqueness::Unknown | Uniqueness::None => 0,
+            Uniqueness::Name => NV_UNIQUE_NAME,
+            Uniqueness::Pair => NV_UNIQUE_NAME_TYPE,
+        }
+    }
+}
+
+fn errno_to_result(errno: c_int) -> Result<(), Error> {
+    match errno {
+        0 => Ok(()),
+        e => Err(Error::Errno(e))
+    }
+}
+
+fn to_c_string(s: &str) -> Result<CString, Error> {
+    CString::new(s).map_err(Error::InteriorNul)
+}
+
 fn nvpair_name_to_string(pair: *const ffi::nvpair_t) -> Result<String, Error> {
     let name_str = unsafe { CStr::from_ptr(nvpair_name(pair)) }.to_str();
     match name_str {
@@ -41,45 +146,233 @@ fn nvpair_name_to_string(pair: *const ffi::nvpair_t) -> Result<String, Error> {
     }
 }
 
+fn c_string_to_string(s: *const c_char) -> Result<String, Error> {
+    match unsafe { CStr::from_ptr(s) }.to_str() {
+        Ok(s) => Ok(s.to_owned()),
+        Err(e) => Err(Error::IllegalString(e))
+    }
+}
+
+fn bool_to_boolean(b: bool) -> boolean_t {
+    if b { boolean_t::B_TRUE } else { boolean_t::B_FALSE }
+}
+
+fn boolean_to_bool(b: &boolean_t) -> bool {
+    match b {
+        &boolean_t::B_TRUE  => true,
+        &boolean_t::B_FALSE => false,
+    }
+}
+
+// Fetches a scalar value with the matching nvpair_value_* function.
+macro_rules! pair_value {
+    ($pair:expr, $getter:ident, $ty:ty, $init:expr) => {{
+        let mut value: $ty = $init;
+        assert_eq!(unsafe { $getter($pair, &mut value) }, 0);
+        value
+    }}
+}
+
+// Fetches an array value with the matching nvpair_value_*_array function.
+// The returned slice points into the nvpair and lives as long as the pair.
+macro_rules! pair_array {
+    ($pair:expr, $getter:ident, $ty:ty) => {{
+        let mut n_elem: ffi::uint_t = 0;
+        let mut output_ptr: *mut $ty = ptr::null_mut();
+        assert_eq!(unsafe { $getter($pair, &mut output_ptr, &mut n_elem) }, 0);
+        if n_elem == 0 {
+            &[][..]
+        } else {
+            unsafe { ::std::slice::from_raw_parts(output_ptr as *const $ty, n_elem as usize) }
+        }
+    }}
+}
+
+impl<'v> PairValue<'v> {
+    pub fn data_type(&self) -> data_type_t {
+        match *self {
+            PairValue::Boolean => data_type_t::DATA_TYPE_BOOLEAN,
+            PairValue::BooleanValue(_) => data_type_t::DATA_TYPE_BOOLEAN_VALUE,
+            PairValue::Byte(_) => data_type_t::DATA_TYPE_BYTE,
+            PairValue::Int8(_) => data_type_t::DATA_TYPE_INT8,
+            PairValue::Uint8(_) => data_type_t::DATA_TYPE_UINT8,
+            PairValue::Int16(_) => data_type_t::DATA_TYPE_INT16,
+            PairValue::Uint16(_) => data_type_t::DATA_TYPE_UINT16,
+            PairValue::Int32(_) => data_type_t::DATA_TYPE_INT32,
+            PairValue::Uint32(_) => data_type_t::DATA_TYPE_UINT32,
+            PairValue::Int64(_) => data_type_t::DATA_TYPE_INT64,
+            PairValue::Uint64(_) => data_type_t::DATA_TYPE_UINT64,
+            PairValue::String(_) => data_type_t::DATA_TYPE_STRING,
+            PairValue::HrTime(_) => data_type_t::DATA_TYPE_HRTIME,
+            PairValue::NvList(_) => data_type_t::DATA_TYPE_NVLIST,
+            PairValue::BooleanValueArray(_) => data_type_t::DATA_TYPE_BOOLEAN_ARRAY,
+            PairValue::ByteArray(_) => data_type_t::DATA_TYPE_BYTE_ARRAY,
+            PairValue::Int8Array(_) => data_type_t::DATA_TYPE_INT8_ARRAY,
+            PairValue::Uint8Array(_) => data_type_t::DATA_TYPE_UINT8_ARRAY,
+            PairValue::Int16Array(_) => data_type_t::DATA_TYPE_INT16_ARRAY,
+            PairValue::Uint16Array(_) => data_type_t::DATA_TYPE_UINT16_ARRAY,
+            PairValue::Int32Array(_) => data_type_t::DATA_TYPE_INT32_ARRAY,
+            PairValue::Uint32Array(_) => data_type_t::DATA_TYPE_UINT32_ARRAY,
+            PairValue::Int64Array(_) => data_type_t::DATA_TYPE_INT64_ARRAY,
+            PairValue::Uint64Array(_) => data_type_t::DATA_TYPE_UINT64_ARRAY,
+            PairValue::StringArray(_) => data_type_t::DATA_TYPE_STRING_ARRAY,
+            PairValue::NvListArray(_) => data_type_t::DATA_TYPE_NVLIST_ARRAY,
+        }
+    }
+}
+
 impl<'a> Pair<'a> {
+    pub fn new<K: Into<Key<'a>>>(name: K, value: PairValue<'a>) -> Pair<'a> {
+        Pair(name.into(), value)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    pub fn value(&self) -> &PairValue<'a> {
+        &self.1
+    }
+
     pub fn from_ffi(pair: *const ffi::nvpair_t) -> Result<Pair<'a>, Error> {
         let data_type = unsafe { nvpair_type(pair) };
         let name = try!(nvpair_name_to_string(pair));
-        match data_type {
+        let value = match data_type {
+            data_type_t::DATA_TYPE_BOOLEAN => PairValue::Boolean,
             data_type_t::DATA_TYPE_BOOLEAN_VALUE => {
-                let mut target_bool: boolean_t = boolean_t::B_FALSE;
-                assert_eq!(unsafe { nvpair_value_boolean_value(pair, &mut target_bool) }, 0);
-                let bool_value = match target_bool {
-                    boolean_t::B_TRUE  => true,
-                    boolean_t::B_FALSE => false,
-                };
-                Ok(Pair(name.into(), PairValue::BooleanValue(bool_value)))
+                let value = pair_value!(pair, nvpair_value_boolean_value, boolean_t, boolean_t::B_FALSE);
+                PairValue::BooleanValue(boolean_to_bool(&value))
+            },
+            data_type_t::DATA_TYPE_BYTE   => PairValue::Byte(pair_value!(pair, nvpair_value_byte, uchar_t, 0)),
+            data_type_t::DATA_TYPE_INT8   => PairValue::Int8(pair_value!(pair, nvpair_value_int8, i8, 0)),
+            data_type_t::DATA_TYPE_UINT8  => PairValue::Uint8(pair_value!(pair, nvpair_value_uint8, u8, 0)),
+            data_type_t::DATA_TYPE_INT16  => PairValue::Int16(pair_value!(pair, nvpair_value_int16, i16, 0)),
+            data_type_t::DATA_TYPE_UINT16 => PairValue::Uint16(pair_value!(pair, nvpair_value_uint16, u16, 0)),
+            data_type_t::DATA_TYPE_INT32  => PairValue::Int32(pair_value!(pair, nvpair_value_int32, i32, 0)),
+            data_type_t::DATA_TYPE_UINT32 => PairValue::Uint32(pair_value!(pair, nvpair_value_uint32, u32, 0)),
+            data_type_t::DATA_TYPE_INT64  => PairValue::Int64(pair_value!(pair, nvpair_value_int64, i64, 0)),
+            data_type_t::DATA_TYPE_UINT64 => PairValue::Uint64(pair_value!(pair, nvpair_value_uint64, u64, 0)),
+            data_type_t::DATA_TYPE_HRTIME => PairValue::HrTime(pair_value!(pair, nvpair_value_hrtime, hrtime_t, 0)),
+            data_type_t::DATA_TYPE_STRING => {
+                let value = pair_value!(pair, nvpair_value_string, *mut c_char, ptr::null_mut());
+                PairValue::String(try!(c_string_to_string(value)))
+            },
+            data_type_t::DATA_TYPE_NVLIST => {
+                let value = pair_value!(pair, nvpair_value_nvlist, *mut nvlist_t, ptr::null_mut());
+                PairValue::NvList(try!(List::from_ffi(value)))
             },
             data_type_t::DATA_TYPE_BOOLEAN_ARRAY => {
-                let mut n_elem: ffi::uint_t =  0;
-                let mut output_ptr: *mut boolean_t =  unsafe { ::std::mem::uninitialized() };
-                assert_eq!(unsafe { nvpair_value_boolean_array(pair, &mut output_ptr, &mut n_elem) }, 0);
-                let output_slice = unsafe { ::std::slice::from_raw_parts(output_ptr, n_elem as usize) };
-
-                let mut output_vec = Vec::with_capacity(output_slice.len());
-
-                for element in output_slice {
-                    let bool_value = match element {
-                        &boolean_t::B_TRUE  => true,
-                        &boolean_t::B_FALSE => false,
-                    };
-                    output_vec.push(bool_value);
+                let values = pair_array!(pair, nvpair_value_boolean_array, boolean_t);
+                PairValue::BooleanValueArray(values.iter().map(boolean_to_bool).collect())
+            },
+            data_type_t::DATA_TYPE_BYTE_ARRAY   => PairValue::ByteArray(pair_array!(pair, nvpair_value_byte_array, uchar_t).to_vec()),
+            data_type_t::DATA_TYPE_INT8_ARRAY   => PairValue::Int8Array(pair_array!(pair, nvpair_value_int8_array, i8).to_vec()),
+            data_type_t::DATA_TYPE_UINT8_ARRAY  => PairValue::Uint8Array(pair_array!(pair, nvpair_value_uint8_array, u8).to_vec()),
+            data_type_t::DATA_TYPE_INT16_ARRAY  => PairValue::Int16Array(pair_array!(pair, nvpair_value_int16_array, i16).to_vec()),
+            data_type_t::DATA_TYPE_UINT16_ARRAY => PairValue::Uint16Array(pair_array!(pair, nvpair_value_uint16_array, u16).to_vec()),
+            data_type_t::DATA_TYPE_INT32_ARRAY  => PairValue::Int32Array(pair_array!(pair, nvpair_value_int32_array, i32).to_vec()),
+            data_type_t::DATA_TYPE_UINT32_ARRAY => PairValue::Uint32Array(pair_array!(pair, nvpair_value_uint32_array, u32).to_vec()),
+            data_type_t::DATA_TYPE_INT64_ARRAY  => PairValue::Int64Array(pair_array!(pair, nvpair_value_int64_array, i64).to_vec()),
+            data_type_t::DATA_TYPE_UINT64_ARRAY => PairValue::Uint64Array(pair_array!(pair, nvpair_value_uint64_array, u64).to_vec()),
+            data_type_t::DATA_TYPE_STRING_ARRAY => {
+                let values = pair_array!(pair, nvpair_value_string_array, *mut c_char);
+                let mut strings = Vec::with_capacity(values.len());
+                for value in values {
+                    strings.push(try!(c_string_to_string(*value)));
                 }
-
-
-                Ok(Pair(name.into(), PairValue::BooleanValueArray(output_vec)))
+                PairValue::StringArray(strings)
+            },
+            data_type_t::DATA_TYPE_NVLIST_ARRAY => {
+                let values = pair_array!(pair, nvpair_value_nvlist_array, *mut nvlist_t);
+                let mut lists = Vec::with_capacity(values.len());
+                for value in values {
+                    lists.push(try!(List::from_ffi(*value)));
+                }
+                PairValue::NvListArray(lists)
             },
             _ => { return Err(Error::UnkownDataType(format!("{:?}", data_type))) }
+        };
+
+        Ok(Pair(name.into(), value))
+    }
+
+    /// Adds a copy of this pair to a native nvlist.
+    pub fn add_to_ffi(&self, nvlist: *mut ffi::nvlist_t) -> Result<(), Error> {
+        let name = try!(to_c_string(&self.0));
+        let name = name.as_ptr();
+
+        macro_rules! add_array {
+            ($adder:ident, $values:expr) => {{
+                let mut values = $values;
+                unsafe { $adder(nvlist, name, values.as_mut_ptr(), values.len() as uint_t) }
+            }}
         }
+
+        let errno = match self.1 {
+            PairValue::Boolean => unsafe { nvlist_add_boolean(nvlist, name) },
+            PairValue::BooleanValue(v) => unsafe { nvlist_add_boolean_value(nvlist, name, bool_to_boolean(v)) },
+            PairValue::Byte(v)   => unsafe { nvlist_add_byte(nvlist, name, v) },
+            PairValue::Int8(v)   => unsafe { nvlist_add_int8(nvlist, name, v) },
+            PairValue::Uint8(v)  => unsafe { nvlist_add_uint8(nvlist, name, v) },
+            PairValue::Int16(v)  => unsafe { nvlist_add_int16(nvlist, name, v) },
+            PairValue::Uint16(v) => unsafe { nvlist_add_uint16(nvlist, name, v) },
+            PairValue::Int32(v)  => unsafe { nvlist_add_int32(nvlist, name, v) },
+            PairValue::Uint32(v) => unsafe { nvlist_add_uint32(nvlist, name, v) },
+            PairValue::Int64(v)  => unsafe { nvlist_add_int64(nvlist, name, v) },
+            PairValue::Uint64(v) => unsafe { nvlist_add_uint64(nvlist, name, v) },
+            PairValue::HrTime(v) => unsafe { nvlist_add_hrtime(nvlist, name, v) },
+            PairValue::String(ref v) => {
+                let value = try!(to_c_string(v));
+                unsafe { nvlist_add_string(nvlist, name, value.as_ptr()) }
+            },
+            PairValue::NvList(ref v) => {
+                let value = try!(v.to_ffi());
+                let errno = unsafe { nvlist_add_nvlist(nvlist, name, value) };
+                unsafe { nvlist_free(value) };
+                errno
+            },
+            PairValue::BooleanValueArray(ref v) => add_array!(nvlist_add_boolean_array, v.iter().map(|b| bool_to_boolean(*b)).collect::<Vec<_>>()),
+            PairValue::ByteArray(ref v)   => add_array!(nvlist_add_byte_array, v.clone()),
+            PairValue::Int8Array(ref v)   => add_array!(nvlist_add_int8_array, v.clone()),
+            PairValue::Uint8Array(ref v)  => add_array!(nvlist_add_uint8_array, v.clone()),
+            PairValue::Int16Array(ref v)  => add_array!(nvlist_add_int16_array, v.clone()),
+            PairValue::Uint16Array(ref v) => add_array!(nvlist_add_uint16_array, v.clone()),
+            PairValue::Int32Array(ref v)  => add_array!(nvlist_add_int32_array, v.clone()),
+            PairValue::Uint32Array(ref v) => add_array!(nvlist_add_uint32_array, v.clone()),
+            PairValue::Int64Array(ref v)  => add_array!(nvlist_add_int64_array, v.clone()),
+            PairValue::Uint64Array(ref v) => add_array!(nvlist_add_uint64_array, v.clone()),
+            PairValue::StringArray(ref v) => {
+                let mut strings = Vec::with_capacity(v.len());
+                for string in v {
+                    strings.push(try!(to_c_string(string)));
+                }
+                let pointers: Vec<*const c_char> = strings.iter().map(|s| s.as_ptr()).collect();
+                unsafe { nvlist_add_string_array(nvlist, name, pointers.as_ptr(), pointers.len() as uint_t) }
+            },
+            PairValue::NvListArray(ref v) => {
+                let mut lists: Vec<*mut nvlist_t> = Vec::with_capacity(v.len());
+                let mut result = Ok(());
+                for list in v {
+                    match list.to_ffi() {
+                        Ok(l) => lists.push(l),
+                        Err(e) => { result = Err(e); break; }
+                    }
+                }
+                if result.is_ok() {
+                    result = errno_to_result(add_array!(nvlist_add_nvlist_array, lists.clone()));
+                }
+                for list in lists {
+                    unsafe { nvlist_free(list) };
+                }
+                return result;
+            },
+        };
+
+        errno_to_result(errno)
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct List<'l> {
     uniqueness: Uniqueness,
     pairs: Vec<Pair<'l>>
@@ -108,15 +401,55 @@ impl<'l> List<'l> {
         }
 
         Ok(List {
-            uniqueness: Uniqueness::Unknown,
+            uniqueness: Uniqueness::from_nvflag(unsafe { nvlist_nvflag(nvlist) }),
             pairs: pairs
         })
     }
 
+    /// Allocates a new native nvlist holding a copy of every pair in this
+    /// list. The caller owns the result and must release it with
+    /// `nvlist_free`.
+    pub fn to_ffi(&self) -> Result<*mut ffi::nvlist_t, Error> {
+        let mut nvlist: *mut nvlist_t = ptr::null_mut();
+        try!(errno_to_result(unsafe { nvlist_alloc(&mut nvlist, self.uniqueness.to_nvflag(), 0) }));
+
+        for pair in &self.pairs {
+            if let Err(e) = pair.add_to_ffi(nvlist) {
+                unsafe { nvlist_free(nvlist) };
+                return Err(e);
+            }
+        }
+
+        Ok(nvlist)
+    }
+
+    /// Packs the list like `nvlist_pack` does, see `pack::pack`.
+    pub fn pack(&self, encoding: Encoding) -> Result<Vec<u8>, Error> {
+        pack::pack(self, encoding)
+    }
+
+    /// Unpacks a list packed by `nvlist_pack` or `List::pack`.
+    pub fn unpack(buf: &[u8]) -> Result<List<'static>, Error> {
+        pack::unpack(buf)
+    }
+
     pub fn add(&mut self, pair: Pair<'l>) -> &mut List<'l>{
         self.pairs.push(pair);
         self
     }
+
+    pub fn uniqueness(&self) -> &Uniqueness {
+        &self.uniqueness
+    }
+
+    pub fn pairs(&self) -> &[Pair<'l>] {
+        &self.pairs
+    }
+
+    /// The value of the first pair with the given name.
+    pub fn get(&self, name: &str) -> Option<&PairValue<'l>> {
+        self.pairs.iter().find(|p| &*p.0 == name).map(|p| &p.1)
+    }
 }
 
 #[cfg(test)]
@@ -124,7 +457,8 @@ mod test {
     use std::ptr;
     use super::ffi::*;
     use std::ffi::{CStr, CString};
-    use super::{List,Pair,PairValue,Uniqueness};
+    use super::{Error,List,Pair,PairValue,Uniqueness};
+    use quickcheck::{quickcheck, Arbitrary, Gen};
 
     #[test]
     fn from_ffi_one_bool() {
@@ -134,7 +468,7 @@ mod test {
         assert_eq!(unsafe { nvlist_add_boolean_value(ptr, name1.as_ptr(), boolean_t::B_TRUE) }, 0);
         let nvlist = List::from_ffi(ptr).unwrap();
         assert_eq!(nvlist.pairs.len(), 1);
-        let mut l2 = List::new(Uniqueness::Unknown);
+        let mut l2 = List::new(Uniqueness::None);
         l2.add(Pair("bool1".into(), PairValue::BooleanValue(true)));
         assert_eq!(nvlist, l2);
         unsafe { nvlist_free(ptr); }
@@ -150,7 +484,7 @@ mod test {
         assert_eq!(unsafe { nvlist_add_boolean_value(ptr, name2.as_ptr(), boolean_t::B_FALSE) }, 0);
         let nvlist = List::from_ffi(ptr).unwrap();
         assert_eq!(nvlist.pairs.len(), 2);
-        let mut l2 = List::new(Uniqueness::Unknown);
+        let mut l2 = List::new(Uniqueness::None);
         l2.add(Pair("bool1".into(), PairValue::BooleanValue(true)));
         l2.add(Pair("bool2".into(), PairValue::BooleanValue(false)));
         assert_eq!(nvlist, l2);
@@ -174,9 +508,148 @@ mod test {
 
         let nvlist = List::from_ffi(ptr).unwrap();
         assert_eq!(nvlist.pairs.len(), 1);
-        let mut l2 = List::new(Uniqueness::Unknown);
+        let mut l2 = List::new(Uniqueness::None);
         l2.add(Pair("bool_array".into(), PairValue::BooleanValueArray(vec![true, false, false, true])));
         assert_eq!(nvlist, l2);
         unsafe { nvlist_free(ptr); }
     }
+
+    #[test]
+    fn from_ffi_scalars_and_string() {
+        let mut ptr : *mut nvlist_t  = ptr::null_mut();
+        assert_eq!(unsafe { nvlist_alloc(&mut ptr, 0, 0) }, 0);
+        let name1 = CString::new("int32").unwrap();
+        let name2 = CString::new("uint64").unwrap();
+        let name3 = CString::new("string").unwrap();
+        let value3 = CString::new("hello").unwrap();
+        assert_eq!(unsafe { nvlist_add_int32(ptr, name1.as_ptr(), -42) }, 0);
+        assert_eq!(unsafe { nvlist_add_uint64(ptr, name2.as_ptr(), 1 << 40) }, 0);
+        assert_eq!(unsafe { nvlist_add_string(ptr, name3.as_ptr(), value3.as_ptr()) }, 0);
+        let nvlist = List::from_ffi(ptr).unwrap();
+        let mut l2 = List::new(Uniqueness::None);
+        l2.add(Pair("int32".into(), PairValue::Int32(-42)));
+        l2.add(Pair("uint64".into(), PairValue::Uint64(1 << 40)));
+        l2.add(Pair("string".into(), PairValue::String("hello".to_owned())));
+        assert_eq!(nvlist, l2);
+        unsafe { nvlist_free(ptr); }
+    }
+
+    #[test]
+    fn from_ffi_nested_nvlist() {
+        let mut inner : *mut nvlist_t  = ptr::null_mut();
+        let mut outer : *mut nvlist_t  = ptr::null_mut();
+        assert_eq!(unsafe { nvlist_alloc(&mut inner, 0, 0) }, 0);
+        assert_eq!(unsafe { nvlist_alloc(&mut outer, 0, 0) }, 0);
+        let name1 = CString::new("bool1").unwrap();
+        let name2 = CString::new("inner").unwrap();
+        assert_eq!(unsafe { nvlist_add_boolean_value(inner, name1.as_ptr(), boolean_t::B_TRUE) }, 0);
+        assert_eq!(unsafe { nvlist_add_nvlist(outer, name2.as_ptr(), inner) }, 0);
+        let nvlist = List::from_ffi(outer).unwrap();
+        let mut l_inner = List::new(Uniqueness::None);
+        l_inner.add(Pair("bool1".into(), PairValue::BooleanValue(true)));
+        let mut l2 = List::new(Uniqueness::None);
+        l2.add(Pair("inner".into(), PairValue::NvList(l_inner)));
+        assert_eq!(nvlist, l2);
+        unsafe { nvlist_free(inner); }
+        unsafe { nvlist_free(outer); }
+    }
+
+    #[test]
+    fn to_ffi_unique_name() {
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("uint64".into(), PairValue::Uint64(7)));
+        l.add(Pair("strings".into(), PairValue::StringArray(vec!["a".to_owned(), "b".to_owned()])));
+        let ptr = l.to_ffi().unwrap();
+        assert_eq!(unsafe { nvlist_nvflag(ptr) }, NV_UNIQUE_NAME);
+        let name = CString::new("uint64").unwrap();
+        assert_eq!(unsafe { nvlist_exists(ptr, name.as_ptr()) }, boolean_t::B_TRUE);
+        assert_eq!(List::from_ffi(ptr).unwrap(), l);
+        unsafe { nvlist_free(ptr); }
+    }
+
+    #[test]
+    fn to_ffi_rejects_interior_nul() {
+        let mut l = List::new(Uniqueness::None);
+        l.add(Pair("bad\0name".into(), PairValue::Boolean));
+        match l.to_ffi() {
+            Err(Error::InteriorNul(_)) => (),
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    fn arbitrary_string(g: &mut Gen) -> String {
+        String::arbitrary(g).chars().filter(|c| *c != '\0').collect()
+    }
+
+    fn arbitrary_value(g: &mut Gen, depth: usize) -> PairValue<'static> {
+        let choices = if depth == 0 { 24 } else { 26 };
+        match u8::arbitrary(g) % choices {
+            0  => PairValue::Boolean,
+            1  => PairValue::BooleanValue(bool::arbitrary(g)),
+            2  => PairValue::Byte(u8::arbitrary(g)),
+            3  => PairValue::Int8(i8::arbitrary(g)),
+            4  => PairValue::Uint8(u8::arbitrary(g)),
+            5  => PairValue::Int16(i16::arbitrary(g)),
+            6  => PairValue::Uint16(u16::arbitrary(g)),
+            7  => PairValue::Int32(i32::arbitrary(g)),
+            8  => PairValue::Uint32(u32::arbitrary(g)),
+            9  => PairValue::Int64(i64::arbitrary(g)),
+            10 => PairValue::Uint64(u64::arbitrary(g)),
+            11 => PairValue::String(arbitrary_string(g)),
+            12 => PairValue::HrTime(i64::arbitrary(g)),
+            13 => PairValue::BooleanValueArray(Vec::arbitrary(g)),
+            14 => PairValue::ByteArray(Vec::arbitrary(g)),
+            15 => PairValue::Int8Array(Vec::arbitrary(g)),
+            16 => PairValue::Uint8Array(Vec::arbitrary(g)),
+            17 => PairValue::Int16Array(Vec::arbitrary(g)),
+            18 => PairValue::Uint16Array(Vec::arbitrary(g)),
+            19 => PairValue::Int32Array(Vec::arbitrary(g)),
+            20 => PairValue::Uint32Array(Vec::arbitrary(g)),
+            21 => PairValue::Int64Array(Vec::arbitrary(g)),
+            22 => PairValue::Uint64Array(Vec::arbitrary(g)),
+            23 => {
+                let len = usize::arbitrary(g) % 4;
+                PairValue::StringArray((0..len).map(|_| arbitrary_string(g)).collect())
+            },
+            24 => PairValue::NvList(arbitrary_list(g, depth - 1)),
+            _  => {
+                let len = usize::arbitrary(g) % 3;
+                PairValue::NvListArray((0..len).map(|_| arbitrary_list(g, depth - 1)).collect())
+            },
+        }
+    }
+
+    fn arbitrary_list(g: &mut Gen, depth: usize) -> List<'static> {
+        let uniqueness = g.choose(&[Uniqueness::None, Uniqueness::Name, Uniqueness::Pair]).unwrap().clone();
+        let mut list = List::new(uniqueness);
+        let len = usize::arbitrary(g) % 8;
+        for i in 0..len {
+            // Unique lists replace pairs with an existing name, so only
+            // non-unique lists may contain duplicates.
+            let name = match list.uniqueness {
+                Uniqueness::None => format!("key{}", u8::arbitrary(g) % 4),
+                _ => format!("key{}", i),
+            };
+            let value = arbitrary_value(g, depth);
+            list.add(Pair(name.into(), value));
+        }
+        list
+    }
+
+    impl Arbitrary for List<'static> {
+        fn arbitrary(g: &mut Gen) -> List<'static> {
+            arbitrary_list(g, 2)
+        }
+    }
+
+    #[test]
+    fn to_ffi_from_ffi_roundtrip() {
+        fn prop(list: List<'static>) -> bool {
+            let ptr = list.to_ffi().unwrap();
+            let result = List::from_ffi(ptr);
+            unsafe { nvlist_free(ptr); }
+            result == Ok(list)
+        }
+        quickcheck(prop as fn(List<'static>) -> bool);
+    }
 }
@@ -0,0 +1,400 @@
+use std::fmt;
+use std::ptr;
+use std::slice;
+use std::marker::PhantomData;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use ffi::*;
+use super::{Error, List, Pair, PairValue, Uniqueness};
+use super::{to_c_string, errno_to_result, boolean_to_bool, nvpair_name_to_string};
+
+const ENOENT: i32 = 2;
+
+/// An owned native nvlist. The list is released with `nvlist_free` when
+/// this value is dropped.
+pub struct NvList {
+    raw: *mut nvlist_t
+}
+
+/// A native nvlist that is owned by someone else, such as libzfs or an
+/// enclosing `NvList`.
+#[derive(Clone, Copy)]
+pub struct NvListRef<'a> {
+    raw: *mut nvlist_t,
+    marker: PhantomData<&'a nvlist_t>
+}
+
+/// A pair inside a native nvlist. Nothing is copied until the value is
+/// decoded with `value()` or `to_pair()`.
+#[derive(Clone, Copy)]
+pub struct PairRef<'a> {
+    raw: *mut nvpair_t,
+    marker: PhantomData<&'a nvpair_t>
+}
+
+/// Iterates over the pairs of a native nvlist without copying them.
+pub struct Pairs<'a> {
+    list: *mut nvlist_t,
+    prev: *mut nvpair_t,
+    marker: PhantomData<&'a nvlist_t>
+}
+
+// Looks up a scalar value with the matching nvlist_lookup_* function.
+macro_rules! lookup_value {
+    ($list:expr, $name:expr, $getter:ident, $ty:ty, $init:expr) => {{
+        let name = try!(to_c_string($name));
+        let mut value: $ty = $init;
+        match unsafe { $getter($list, name.as_ptr(), &mut value) } {
+            0 => Some(value),
+            ENOENT => None,
+            e => return Err(Error::Errno(e))
+        }
+    }}
+}
+
+// Looks up an array value with the matching nvlist_lookup_*_array function.
+// The returned slice points into the nvlist.
+macro_rules! lookup_array {
+    ($list:expr, $name:expr, $getter:ident, $ty:ty) => {{
+        let name = try!(to_c_string($name));
+        let mut n_elem: uint_t = 0;
+        let mut output_ptr: *mut $ty = ptr::null_mut();
+        match unsafe { $getter($list, name.as_ptr(), &mut output_ptr, &mut n_elem) } {
+            0 if n_elem == 0 => Some(&[][..]),
+            0 => Some(unsafe { slice::from_raw_parts(output_ptr as *const $ty, n_elem as usize) }),
+            ENOENT => None,
+            e => return Err(Error::Errno(e))
+        }
+    }}
+}
+
+macro_rules! lookup_fn {
+    ($fn_name:ident, $getter:ident, $ty:ty) => {
+        pub fn $fn_name(&self, name: &str) -> Result<Option<$ty>, Error> {
+            Ok(lookup_value!(self.as_ptr(), name, $getter, $ty, 0))
+        }
+    }
+}
+
+macro_rules! lookup_array_fn {
+    ($fn_name:ident, $getter:ident, $ty:ty) => {
+        pub fn $fn_name(&self, name: &str) -> Result<Option<&[$ty]>, Error> {
+            Ok(lookup_array!(self.as_ptr(), name, $getter, $ty))
+        }
+    }
+}
+
+fn c_str<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    unsafe { CStr::from_ptr(s) }.to_str().map_err(Error::IllegalString)
+}
+
+// The read-only operations shared by `NvList` and `NvListRef`.
+macro_rules! nvlist_reader {
+    () => {
+        pub fn is_empty(&self) -> bool {
+            unsafe { nvlist_empty(self.as_ptr()) == boolean_t::B_TRUE }
+        }
+
+        pub fn exists(&self, name: &str) -> Result<bool, Error> {
+            let name = try!(to_c_string(name));
+            Ok(unsafe { nvlist_exists(self.as_ptr(), name.as_ptr()) == boolean_t::B_TRUE })
+        }
+
+        pub fn pairs(&self) -> Pairs {
+            Pairs {
+                list: self.as_ptr(),
+                prev: ptr::null_mut(),
+                marker: PhantomData
+            }
+        }
+
+        /// Copies the native list into a `List`.
+        pub fn to_list(&self) -> Result<List<'static>, Error> {
+            List::from_ffi(self.as_ptr())
+        }
+
+        /// Returns true if the list contains a `DATA_TYPE_BOOLEAN` pair with
+        /// this name.
+        pub fn lookup_boolean(&self, name: &str) -> Result<bool, Error> {
+            let name = try!(to_c_string(name));
+            match unsafe { nvlist_lookup_boolean(self.as_ptr(), name.as_ptr()) } {
+                0 => Ok(true),
+                ENOENT => Ok(false),
+                e => Err(Error::Errno(e))
+            }
+        }
+
+        pub fn lookup_boolean_value(&self, name: &str) -> Result<Option<bool>, Error> {
+            let value = lookup_value!(self.as_ptr(), name, nvlist_lookup_boolean_value, boolean_t, boolean_t::B_FALSE);
+            Ok(value.as_ref().map(boolean_to_bool))
+        }
+
+        lookup_fn!(lookup_byte, nvlist_lookup_byte, uchar_t);
+        lookup_fn!(lookup_int8, nvlist_lookup_int8, i8);
+        lookup_fn!(lookup_uint8, nvlist_lookup_uint8, u8);
+        lookup_fn!(lookup_int16, nvlist_lookup_int16, i16);
+        lookup_fn!(lookup_uint16, nvlist_lookup_uint16, u16);
+        lookup_fn!(lookup_int32, nvlist_lookup_int32, i32);
+        lookup_fn!(lookup_uint32, nvlist_lookup_uint32, u32);
+        lookup_fn!(lookup_int64, nvlist_lookup_int64, i64);
+        lookup_fn!(lookup_uint64, nvlist_lookup_uint64, u64);
+        lookup_fn!(lookup_hrtime, nvlist_lookup_hrtime, hrtime_t);
+
+        pub fn lookup_string(&self, name: &str) -> Result<Option<&str>, Error> {
+            match lookup_value!(self.as_ptr(), name, nvlist_lookup_string, *mut c_char, ptr::null_mut()) {
+                Some(value) => Ok(Some(try!(c_str(value)))),
+                None => Ok(None)
+            }
+        }
+
+        pub fn lookup_nvlist(&self, name: &str) -> Result<Option<NvListRef>, Error> {
+            let value = lookup_value!(self.as_ptr(), name, nvlist_lookup_nvlist, *mut nvlist_t, ptr::null_mut());
+            Ok(value.map(|raw| unsafe { NvListRef::from_raw(raw) }))
+        }
+
+        pub fn lookup_boolean_array(&self, name: &str) -> Result<Option<Vec<bool>>, Error> {
+            let values = lookup_array!(self.as_ptr(), name, nvlist_lookup_boolean_array, boolean_t);
+            Ok(values.map(|v| v.iter().map(boolean_to_bool).collect()))
+        }
+
+        lookup_array_fn!(lookup_byte_array, nvlist_lookup_byte_array, uchar_t);
+        lookup_array_fn!(lookup_int8_array, nvlist_lookup_int8_array, i8);
+        lookup_array_fn!(lookup_uint8_array, nvlist_lookup_uint8_array, u8);
+        lookup_array_fn!(lookup_int16_array, nvlist_lookup_int16_array, i16);
+        lookup_array_fn!(lookup_uint16_array, nvlist_lookup_uint16_array, u16);
+        lookup_array_fn!(lookup_int32_array, nvlist_lookup_int32_array, i32);
+        lookup_array_fn!(lookup_uint32_array, nvlist_lookup_uint32_array, u32);
+        lookup_array_fn!(lookup_int64_array, nvlist_lookup_int64_array, i64);
+        lookup_array_fn!(lookup_uint64_array, nvlist_lookup_uint64_array, u64);
+
+        pub fn lookup_string_array(&self, name: &str) -> Result<Option<Vec<&str>>, Error> {
+            match lookup_array!(self.as_ptr(), name, nvlist_lookup_string_array, *mut c_char) {
+                Some(values) => {
+                    let mut strings = Vec::with_capacity(values.len());
+                    for value in values {
+                        strings.push(try!(c_str(*value)));
+                    }
+                    Ok(Some(strings))
+                },
+                None => Ok(None)
+            }
+        }
+
+        pub fn lookup_nvlist_array(&self, name: &str) -> Result<Option<Vec<NvListRef>>, Error> {
+            let values = lookup_array!(self.as_ptr(), name, nvlist_lookup_nvlist_array, *mut nvlist_t);
+            Ok(values.map(|v| v.iter().map(|raw| unsafe { NvListRef::from_raw(*raw) }).collect()))
+        }
+    }
+}
+
+impl NvList {
+    pub fn new(u: Uniqueness) -> Result<NvList, Error> {
+        let mut raw: *mut nvlist_t = ptr::null_mut();
+        try!(errno_to_result(unsafe { nvlist_alloc(&mut raw, u.to_nvflag(), 0) }));
+        Ok(NvList { raw: raw })
+    }
+
+    /// Copies a `List` into a newly allocated native nvlist.
+    pub fn from_list(list: &List) -> Result<NvList, Error> {
+        Ok(NvList { raw: try!(list.to_ffi()) })
+    }
+
+    /// Takes ownership of a native nvlist, which will be freed on drop.
+    pub unsafe fn from_raw(raw: *mut nvlist_t) -> NvList {
+        NvList { raw: raw }
+    }
+
+    /// Gives up ownership of the native nvlist without freeing it.
+    pub fn into_raw(self) -> *mut nvlist_t {
+        let raw = self.raw;
+        ::std::mem::forget(self);
+        raw
+    }
+
+    pub fn as_ptr(&self) -> *mut nvlist_t {
+        self.raw
+    }
+
+    pub fn as_ref(&self) -> NvListRef {
+        unsafe { NvListRef::from_raw(self.raw) }
+    }
+
+    /// Copies the list, failing instead of panicking when libnvpair runs
+    /// out of memory.
+    pub fn try_clone(&self) -> Result<NvList, Error> {
+        self.as_ref().to_nvlist()
+    }
+
+    /// Adds a copy of the pair to the list.
+    pub fn add(&mut self, pair: &Pair) -> Result<(), Error> {
+        pair.add_to_ffi(self.raw)
+    }
+
+    /// Removes all pairs with this name. Returns false if there was none.
+    pub fn remove(&mut self, name: &str) -> Result<bool, Error> {
+        let name = try!(to_c_string(name));
+        match unsafe { nvlist_remove_all(self.raw, name.as_ptr()) } {
+            0 => Ok(true),
+            ENOENT => Ok(false),
+            e => Err(Error::Errno(e))
+        }
+    }
+
+    nvlist_reader!();
+}
+
+impl Drop for NvList {
+    fn drop(&mut self) {
+        unsafe { nvlist_free(self.raw) };
+    }
+}
+
+/// Panics when libnvpair runs out of memory, see `try_clone`.
+impl Clone for NvList {
+    fn clone(&self) -> NvList {
+        self.try_clone().expect("nvlist_dup failed")
+    }
+}
+
+impl fmt::Debug for NvList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+impl<'a> NvListRef<'a> {
+    /// Borrows a native nvlist. The caller picks a lifetime `'a` during
+    /// which the list must stay alive and unmodified.
+    pub unsafe fn from_raw(raw: *mut nvlist_t) -> NvListRef<'a> {
+        NvListRef {
+            raw: raw,
+            marker: PhantomData
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut nvlist_t {
+        self.raw
+    }
+
+    /// Copies the borrowed list into a new `NvList`.
+    pub fn to_nvlist(&self) -> Result<NvList, Error> {
+        let mut raw: *mut nvlist_t = ptr::null_mut();
+        try!(errno_to_result(unsafe { nvlist_dup(self.raw, &mut raw, 0) }));
+        Ok(NvList { raw: raw })
+    }
+
+    nvlist_reader!();
+}
+
+impl<'a> fmt::Debug for NvListRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.pairs().map(|p| (p.name(), p.value()))).finish()
+    }
+}
+
+impl<'a> PairRef<'a> {
+    pub fn as_ptr(&self) -> *mut nvpair_t {
+        self.raw
+    }
+
+    pub fn name(&self) -> Result<String, Error> {
+        nvpair_name_to_string(self.raw)
+    }
+
+    pub fn data_type(&self) -> data_type_t {
+        unsafe { nvpair_type(self.raw) }
+    }
+
+    /// Decodes the value of the pair.
+    pub fn value(&self) -> Result<PairValue<'static>, Error> {
+        let Pair(_, value) = try!(self.to_pair());
+        Ok(value)
+    }
+
+    pub fn to_pair(&self) -> Result<Pair<'static>, Error> {
+        Pair::from_ffi(self.raw)
+    }
+}
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = PairRef<'a>;
+
+    fn next(&mut self) -> Option<PairRef<'a>> {
+        let pair = unsafe { nvlist_next_nvpair(self.list, self.prev) };
+        if pair == ptr::null_mut() {
+            None
+        } else {
+            self.prev = pair;
+            Some(PairRef {
+                raw: pair,
+                marker: PhantomData
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{List, Pair, PairValue, Uniqueness};
+    use super::NvList;
+
+    #[test]
+    fn lookup_typed_values() {
+        let mut l = NvList::new(Uniqueness::Name).unwrap();
+        l.add(&Pair("bool".into(), PairValue::BooleanValue(true))).unwrap();
+        l.add(&Pair("flag".into(), PairValue::Boolean)).unwrap();
+        l.add(&Pair("guid".into(), PairValue::Uint64(0xdeadbeef))).unwrap();
+        l.add(&Pair("name".into(), PairValue::String("tank".to_owned()))).unwrap();
+        l.add(&Pair("ids".into(), PairValue::Int32Array(vec![1, 2, 3]))).unwrap();
+
+        assert_eq!(l.lookup_boolean_value("bool"), Ok(Some(true)));
+        assert_eq!(l.lookup_boolean("flag"), Ok(true));
+        assert_eq!(l.lookup_boolean("bool"), Ok(false));
+        assert_eq!(l.lookup_uint64("guid"), Ok(Some(0xdeadbeef)));
+        assert_eq!(l.lookup_uint32("guid"), Ok(None));
+        assert_eq!(l.lookup_string("name"), Ok(Some("tank")));
+        assert_eq!(l.lookup_int32_array("ids"), Ok(Some(&[1, 2, 3][..])));
+        assert_eq!(l.lookup_string("missing"), Ok(None));
+    }
+
+    #[test]
+    fn exists_remove_is_empty() {
+        let mut l = NvList::new(Uniqueness::Name).unwrap();
+        assert!(l.is_empty());
+        l.add(&Pair("bool".into(), PairValue::BooleanValue(true))).unwrap();
+        assert!(!l.is_empty());
+        assert_eq!(l.exists("bool"), Ok(true));
+        assert_eq!(l.remove("bool"), Ok(true));
+        assert_eq!(l.remove("bool"), Ok(false));
+        assert_eq!(l.exists("bool"), Ok(false));
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn nested_lookup_and_iteration() {
+        let mut inner = List::new(Uniqueness::Name);
+        inner.add(Pair("state".into(), PairValue::Uint64(0)));
+        let mut outer = List::new(Uniqueness::Name);
+        outer.add(Pair("version".into(), PairValue::Uint64(5000)));
+        outer.add(Pair("vdev_tree".into(), PairValue::NvList(inner)));
+        let l = NvList::from_list(&outer).unwrap();
+
+        let names: Vec<String> = l.pairs().map(|p| p.name().unwrap()).collect();
+        assert_eq!(names, vec!["version", "vdev_tree"]);
+
+        let tree = l.lookup_nvlist("vdev_tree").unwrap().unwrap();
+        assert_eq!(tree.lookup_uint64("state"), Ok(Some(0)));
+        assert_eq!(l.to_list(), Ok(outer));
+    }
+
+    #[test]
+    fn copies() {
+        let mut l = NvList::new(Uniqueness::Name).unwrap();
+        l.add(&Pair("guid".into(), PairValue::Uint64(7))).unwrap();
+        let copy = l.try_clone().unwrap();
+        l.remove("guid").unwrap();
+        assert_eq!(copy.lookup_uint64("guid"), Ok(Some(7)));
+        assert_eq!(copy.as_ref().to_nvlist().unwrap().lookup_uint64("guid"), Ok(Some(7)));
+    }
+}
@@ -0,0 +1,949 @@
+// Pure-Rust implementation of the packed nvlist formats written by
+// nvlist_pack(3NVPAIR) and read by nvlist_unpack(3NVPAIR).
+//
+// Every packed list starts with a four byte header: the encoding, the
+// endianness of the host that packed it and two reserved bytes. The list
+// itself follows, using either the XDR encoding (always big endian) or the
+// native encoding, which is a copy of the in-memory nvpair_t layout.
+
+use std::str;
+
+use ffi::data_type_t;
+use super::{Error, List, Pair, PairValue, Uniqueness};
+use super::to_c_string;
+
+const NV_ENCODE_NATIVE: u8 = 0;
+const NV_ENCODE_XDR: u8 = 1;
+
+const NVS_BIG_ENDIAN: u8 = 0;
+const NVS_LITTLE_ENDIAN: u8 = 1;
+
+const NV_VERSION: i32 = 0;
+
+// sizeof(nvpair_t) and sizeof(nvlist_t) in the native layout.
+const NVP_HEADER_SIZE: usize = 16;
+const NVLIST_SIZE: usize = 24;
+
+// How deep lists may be nested, the limit nvlist_unpack enforces too.
+const NV_MAX_RECURSION: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Native,
+    Xdr
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn align8(n: usize) -> usize {
+    (n + 7) & !7
+}
+
+fn host_is_big_endian() -> bool {
+    cfg!(target_endian = "big")
+}
+
+fn malformed<T>(reason: &str) -> Result<T, Error> {
+    Err(Error::InvalidPacked(reason.to_owned()))
+}
+
+fn data_type_from_i32(n: i32) -> Option<data_type_t> {
+    use ffi::data_type_t::*;
+    Some(match n {
+        1  => DATA_TYPE_BOOLEAN,
+        2  => DATA_TYPE_BYTE,
+        3  => DATA_TYPE_INT16,
+        4  => DATA_TYPE_UINT16,
+        5  => DATA_TYPE_INT32,
+        6  => DATA_TYPE_UINT32,
+        7  => DATA_TYPE_INT64,
+        8  => DATA_TYPE_UINT64,
+        9  => DATA_TYPE_STRING,
+        10 => DATA_TYPE_BYTE_ARRAY,
+        11 => DATA_TYPE_INT16_ARRAY,
+        12 => DATA_TYPE_UINT16_ARRAY,
+        13 => DATA_TYPE_INT32_ARRAY,
+        14 => DATA_TYPE_UINT32_ARRAY,
+        15 => DATA_TYPE_INT64_ARRAY,
+        16 => DATA_TYPE_UINT64_ARRAY,
+        17 => DATA_TYPE_STRING_ARRAY,
+        18 => DATA_TYPE_HRTIME,
+        19 => DATA_TYPE_NVLIST,
+        20 => DATA_TYPE_NVLIST_ARRAY,
+        21 => DATA_TYPE_BOOLEAN_VALUE,
+        22 => DATA_TYPE_INT8,
+        23 => DATA_TYPE_UINT8,
+        24 => DATA_TYPE_BOOLEAN_ARRAY,
+        25 => DATA_TYPE_INT8_ARRAY,
+        26 => DATA_TYPE_UINT8_ARRAY,
+        _  => return None
+    })
+}
+
+fn value_count(value: &PairValue) -> usize {
+    match *value {
+        PairValue::Boolean => 0,
+        PairValue::BooleanValueArray(ref v) => v.len(),
+        PairValue::ByteArray(ref v) => v.len(),
+        PairValue::Int8Array(ref v) => v.len(),
+        PairValue::Uint8Array(ref v) => v.len(),
+        PairValue::Int16Array(ref v) => v.len(),
+        PairValue::Uint16Array(ref v) => v.len(),
+        PairValue::Int32Array(ref v) => v.len(),
+        PairValue::Uint32Array(ref v) => v.len(),
+        PairValue::Int64Array(ref v) => v.len(),
+        PairValue::Uint64Array(ref v) => v.len(),
+        PairValue::StringArray(ref v) => v.len(),
+        PairValue::NvListArray(ref v) => v.len(),
+        _ => 1
+    }
+}
+
+// Size of the value in the native in-memory layout, as computed by
+// i_get_value_size() in libnvpair.
+fn native_value_size(value: &PairValue) -> usize {
+    let n = value_count(value);
+    match *value {
+        PairValue::Boolean => 0,
+        PairValue::Byte(_) | PairValue::Int8(_) | PairValue::Uint8(_) => 1,
+        PairValue::Int16(_) | PairValue::Uint16(_) => 2,
+        PairValue::BooleanValue(_) | PairValue::Int32(_) | PairValue::Uint32(_) => 4,
+        PairValue::Int64(_) | PairValue::Uint64(_) | PairValue::HrTime(_) => 8,
+        PairValue::String(ref s) => s.len() + 1,
+        PairValue::NvList(_) => NVLIST_SIZE,
+        PairValue::ByteArray(_) | PairValue::Int8Array(_) | PairValue::Uint8Array(_) => n,
+        PairValue::Int16Array(_) | PairValue::Uint16Array(_) => 2 * n,
+        PairValue::BooleanValueArray(_) | PairValue::Int32Array(_) | PairValue::Uint32Array(_) => 4 * n,
+        PairValue::Int64Array(_) | PairValue::Uint64Array(_) => 8 * n,
+        PairValue::StringArray(ref v) => 8 * n + v.iter().map(|s| s.len() + 1).sum::<usize>(),
+        PairValue::NvListArray(_) => (8 + NVLIST_SIZE) * n,
+    }
+}
+
+// The nvp_size of a pair in the native layout.
+fn native_pair_size(name: &str, value: &PairValue) -> usize {
+    align8(NVP_HEADER_SIZE + name.len() + 1) + align8(native_value_size(value))
+}
+
+struct Encoder {
+    buf: Vec<u8>,
+    big_endian: bool
+}
+
+impl Encoder {
+    fn bytes(&mut self, b: &[u8]) {
+        self.buf.extend_from_slice(b);
+    }
+
+    fn zeros(&mut self, n: usize) {
+        let len = self.buf.len();
+        self.buf.resize(len + n, 0);
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        let b = if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        self.bytes(&b);
+    }
+
+    fn u32(&mut self, v: u32) {
+        let b = if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        self.bytes(&b);
+    }
+
+    fn u64(&mut self, v: u64) {
+        let b = if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        self.bytes(&b);
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.u32(v as u32);
+    }
+
+    // Overwrites a previously reserved 32 bit slot.
+    fn patch_u32(&mut self, offset: usize, v: u32) {
+        let b = if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        self.buf[offset..offset + 4].copy_from_slice(&b);
+    }
+
+    // An XDR string: length, bytes and padding up to four bytes.
+    fn xdr_string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.xdr_opaque(s.as_bytes());
+    }
+
+    fn xdr_opaque(&mut self, b: &[u8]) {
+        self.bytes(b);
+        self.zeros(align4(b.len()) - b.len());
+    }
+
+    fn xdr_list(&mut self, list: &List) -> Result<(), Error> {
+        self.i32(NV_VERSION);
+        self.u32(list.uniqueness.to_nvflag());
+        for pair in &list.pairs {
+            try!(self.xdr_pair(pair));
+        }
+        // Two zero sizes mark the end of the list.
+        self.i32(0);
+        self.i32(0);
+        Ok(())
+    }
+
+    fn xdr_pair(&mut self, pair: &Pair) -> Result<(), Error> {
+        let Pair(ref name, ref value) = *pair;
+        try!(to_c_string(name));
+
+        let start = self.buf.len();
+        self.i32(0); // encoded size, patched below
+        self.i32(native_pair_size(name, value) as i32);
+        self.xdr_string(name);
+        self.i32(value.data_type() as i32);
+        self.i32(value_count(value) as i32);
+
+        // libnvpair encodes every 8 bit type with xdr_char, which widens a
+        // (signed) char to a full XDR int. Byte and uint8 values are sign
+        // extended just like int8 values.
+        match *value {
+            PairValue::Boolean => {},
+            PairValue::BooleanValue(v) => self.i32(v as i32),
+            PairValue::Byte(v) => self.i32(v as i8 as i32),
+            PairValue::Int8(v) => self.i32(v as i32),
+            PairValue::Uint8(v) => self.i32(v as i8 as i32),
+            PairValue::Int16(v) => self.i32(v as i32),
+            PairValue::Uint16(v) => self.u32(v as u32),
+            PairValue::Int32(v) => self.i32(v),
+            PairValue::Uint32(v) => self.u32(v),
+            PairValue::Int64(v) => self.u64(v as u64),
+            PairValue::Uint64(v) => self.u64(v),
+            PairValue::HrTime(v) => self.u64(v as u64),
+            PairValue::String(ref v) => {
+                try!(to_c_string(v));
+                self.xdr_string(v)
+            },
+            PairValue::NvList(ref v) => try!(self.xdr_list(v)),
+            PairValue::ByteArray(ref v) => self.xdr_opaque(v),
+            PairValue::BooleanValueArray(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.i32(*e as i32); }
+            },
+            PairValue::Int8Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.i32(*e as i32); }
+            },
+            PairValue::Uint8Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.i32(*e as i8 as i32); }
+            },
+            PairValue::Int16Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.i32(*e as i32); }
+            },
+            PairValue::Uint16Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.u32(*e as u32); }
+            },
+            PairValue::Int32Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.i32(*e); }
+            },
+            PairValue::Uint32Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.u32(*e); }
+            },
+            PairValue::Int64Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.u64(*e as u64); }
+            },
+            PairValue::Uint64Array(ref v) => {
+                self.u32(v.len() as u32);
+                for e in v { self.u64(*e); }
+            },
+            PairValue::StringArray(ref v) => {
+                for s in v {
+                    try!(to_c_string(s));
+                    self.xdr_string(s);
+                }
+            },
+            PairValue::NvListArray(ref v) => {
+                for l in v {
+                    try!(self.xdr_list(l));
+                }
+            },
+        }
+
+        let size = self.buf.len() - start;
+        self.patch_u32(start, size as u32);
+        Ok(())
+    }
+
+    fn native_list(&mut self, list: &List) -> Result<(), Error> {
+        self.i32(NV_VERSION);
+        self.u32(list.uniqueness.to_nvflag());
+        for pair in &list.pairs {
+            try!(self.native_pair(pair));
+        }
+        // A zero nvp_size marks the end of the list.
+        self.i32(0);
+        Ok(())
+    }
+
+    // The embedded nvlist_t of a DATA_TYPE_NVLIST(_ARRAY) value, with the
+    // private pointer nulled out like libnvpair does.
+    fn native_nvlist_t(&mut self, list: &List) {
+        self.i32(NV_VERSION);
+        self.u32(list.uniqueness.to_nvflag());
+        self.u64(0); // nvl_priv
+        self.u32(0); // nvl_flag
+        self.i32(0); // nvl_pad
+    }
+
+    fn native_pair(&mut self, pair: &Pair) -> Result<(), Error> {
+        let Pair(ref name, ref value) = *pair;
+        try!(to_c_string(name));
+        if name.len() + 1 > i16::max_value() as usize {
+            // nvp_name_sz is 16 bits wide; nvlist_add_* refuses such names
+            // with EINVAL as well.
+            return Err(Error::Errno(22));
+        }
+
+        let start = self.buf.len();
+        let size = native_pair_size(name, value);
+        self.i32(size as i32);
+        self.u16((name.len() + 1) as u16);
+        self.u16(0); // nvp_reserve
+        self.i32(value_count(value) as i32);
+        self.i32(value.data_type() as i32);
+        self.bytes(name.as_bytes());
+        let pad = align8(self.buf.len() - start + 1) - (self.buf.len() - start);
+        self.zeros(pad);
+
+        match *value {
+            PairValue::Boolean => {},
+            PairValue::BooleanValue(v) => self.i32(v as i32),
+            PairValue::Byte(v) | PairValue::Uint8(v) => self.u8(v),
+            PairValue::Int8(v) => self.u8(v as u8),
+            PairValue::Int16(v) => self.u16(v as u16),
+            PairValue::Uint16(v) => self.u16(v),
+            PairValue::Int32(v) => self.i32(v),
+            PairValue::Uint32(v) => self.u32(v),
+            PairValue::Int64(v) | PairValue::HrTime(v) => self.u64(v as u64),
+            PairValue::Uint64(v) => self.u64(v),
+            PairValue::String(ref v) => {
+                try!(to_c_string(v));
+                self.bytes(v.as_bytes());
+                self.u8(0);
+            },
+            PairValue::NvList(ref v) => self.native_nvlist_t(v),
+            PairValue::BooleanValueArray(ref v) => for e in v { self.i32(*e as i32) },
+            PairValue::ByteArray(ref v) | PairValue::Uint8Array(ref v) => self.bytes(v),
+            PairValue::Int8Array(ref v) => for e in v { self.u8(*e as u8) },
+            PairValue::Int16Array(ref v) => for e in v { self.u16(*e as u16) },
+            PairValue::Uint16Array(ref v) => for e in v { self.u16(*e) },
+            PairValue::Int32Array(ref v) => for e in v { self.i32(*e) },
+            PairValue::Uint32Array(ref v) => for e in v { self.u32(*e) },
+            PairValue::Int64Array(ref v) => for e in v { self.u64(*e as u64) },
+            PairValue::Uint64Array(ref v) => for e in v { self.u64(*e) },
+            PairValue::StringArray(ref v) => {
+                // The string pointers are nulled out, the strings follow.
+                self.zeros(8 * v.len());
+                for s in v {
+                    try!(to_c_string(s));
+                    self.bytes(s.as_bytes());
+                    self.u8(0);
+                }
+            },
+            PairValue::NvListArray(ref v) => {
+                self.zeros(8 * v.len());
+                for l in v {
+                    self.native_nvlist_t(l);
+                }
+            },
+        }
+
+        let written = self.buf.len() - start;
+        self.zeros(size - written);
+
+        // Embedded lists are packed right after the pair that holds them.
+        match *value {
+            PairValue::NvList(ref v) => try!(self.native_list(v)),
+            PairValue::NvListArray(ref v) => for l in v { try!(self.native_list(l)) },
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Packs the list like `nvlist_pack` does with the given encoding. Native
+/// lists use the byte order of this host.
+pub fn pack(list: &List, encoding: Encoding) -> Result<Vec<u8>, Error> {
+    let big_endian = host_is_big_endian();
+    let mut encoder = Encoder {
+        buf: Vec::new(),
+        big_endian: big_endian
+    };
+
+    encoder.u8(match encoding {
+        Encoding::Native => NV_ENCODE_NATIVE,
+        Encoding::Xdr => NV_ENCODE_XDR
+    });
+    encoder.u8(if big_endian { NVS_BIG_ENDIAN } else { NVS_LITTLE_ENDIAN });
+    encoder.u8(0);
+    encoder.u8(0);
+
+    match encoding {
+        Encoding::Native => try!(encoder.native_list(list)),
+        Encoding::Xdr => {
+            encoder.big_endian = true;
+            try!(encoder.xdr_list(list))
+        }
+    }
+
+    Ok(encoder.buf)
+}
+
+struct Decoder<'b> {
+    buf: &'b [u8],
+    pos: usize,
+    big_endian: bool,
+    depth: usize
+}
+
+impl<'b> Decoder<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], Error> {
+        let end = match self.pos.checked_add(n) {
+            Some(end) if end <= self.buf.len() => end,
+            _ => return malformed("unexpected end of packed nvlist")
+        };
+        let b = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(b)
+    }
+
+    // Checks an element count read from the input against the bytes left
+    // before `limit`, so a corrupt count cannot cause a huge allocation.
+    fn count(&self, nelem: i32, elem_size: usize, limit: usize) -> Result<usize, Error> {
+        if nelem < 0 {
+            return malformed("negative element count");
+        }
+        let nelem = nelem as usize;
+        let size = nelem.checked_mul(elem_size).and_then(|n| n.checked_add(self.pos));
+        match size {
+            Some(end) if end <= limit => Ok(nelem),
+            _ => malformed("element count exceeds the packed nvlist")
+        }
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(try!(self.take(1))[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let mut b = [0; 2];
+        b.copy_from_slice(try!(self.take(2)));
+        Ok(if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let mut b = [0; 4];
+        b.copy_from_slice(try!(self.take(4)));
+        Ok(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let mut b = [0; 8];
+        b.copy_from_slice(try!(self.take(8)));
+        Ok(if self.big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) })
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        self.u32().map(|v| v as i32)
+    }
+
+    fn utf8(b: &[u8]) -> Result<String, Error> {
+        match str::from_utf8(b) {
+            Ok(s) => Ok(s.to_owned()),
+            Err(e) => Err(Error::IllegalString(e))
+        }
+    }
+
+    // A NUL terminated string in the native layout.
+    fn c_string(&mut self) -> Result<String, Error> {
+        let rest = match self.buf.get(self.pos..) {
+            Some(rest) => rest,
+            None => return malformed("unexpected end of packed nvlist")
+        };
+        match rest.iter().position(|b| *b == 0) {
+            Some(len) => {
+                self.pos += len + 1;
+                Decoder::utf8(&rest[..len])
+            },
+            None => malformed("unterminated string")
+        }
+    }
+
+    fn xdr_string(&mut self) -> Result<String, Error> {
+        let len = try!(self.u32()) as usize;
+        let b = try!(self.xdr_opaque(len));
+        Decoder::utf8(b)
+    }
+
+    fn xdr_opaque(&mut self, len: usize) -> Result<&'b [u8], Error> {
+        let b = try!(self.take(len));
+        try!(self.skip(align4(len) - len));
+        Ok(b)
+    }
+
+    fn xdr_array_len(&mut self, nelem: usize) -> Result<usize, Error> {
+        let len = try!(self.u32()) as usize;
+        if len != nelem {
+            return malformed("array length does not match element count");
+        }
+        Ok(len)
+    }
+
+    fn list_header(&mut self) -> Result<Uniqueness, Error> {
+        self.depth += 1;
+        if self.depth > NV_MAX_RECURSION {
+            return malformed("nvlists nested too deeply");
+        }
+        let version = try!(self.i32());
+        if version != NV_VERSION {
+            return Err(Error::InvalidPacked(format!("unsupported nvlist version {}", version)));
+        }
+        Ok(Uniqueness::from_nvflag(try!(self.u32())))
+    }
+
+    fn xdr_list(&mut self) -> Result<List<'static>, Error> {
+        let mut list = List::new(try!(self.list_header()));
+        loop {
+            let encoded_size = try!(self.i32());
+            let decoded_size = try!(self.i32());
+            if encoded_size == 0 && decoded_size == 0 {
+                break;
+            }
+            let start = self.pos - 8;
+            let pair = try!(self.xdr_pair());
+            if self.pos - start != encoded_size as usize {
+                return malformed("encoded pair size mismatch");
+            }
+            list.add(pair);
+        }
+        self.depth -= 1;
+        Ok(list)
+    }
+
+    fn xdr_pair(&mut self) -> Result<Pair<'static>, Error> {
+        let name = try!(self.xdr_string());
+        let raw_type = try!(self.i32());
+        let nelem = try!(self.i32());
+        let data_type = match data_type_from_i32(raw_type) {
+            Some(t) => t,
+            None => return Err(Error::UnkownDataType(format!("{}", raw_type)))
+        };
+        // Every XDR element takes at least four bytes, except in byte arrays.
+        let limit = self.buf.len();
+
+        macro_rules! xdr_array {
+            ($variant:ident, $read:ident, $conv:expr) => {{
+                let nelem = try!(self.count(nelem, 4, limit));
+                let len = try!(self.xdr_array_len(nelem));
+                let mut v = Vec::with_capacity(len);
+                for _ in 0..len {
+                    v.push($conv(try!(self.$read())));
+                }
+                PairValue::$variant(v)
+            }}
+        }
+
+        let value = match data_type {
+            data_type_t::DATA_TYPE_BOOLEAN => PairValue::Boolean,
+            data_type_t::DATA_TYPE_BOOLEAN_VALUE => PairValue::BooleanValue(try!(self.i32()) != 0),
+            data_type_t::DATA_TYPE_BYTE => PairValue::Byte(try!(self.u32()) as u8),
+            data_type_t::DATA_TYPE_INT8 => PairValue::Int8(try!(self.u32()) as i8),
+            data_type_t::DATA_TYPE_UINT8 => PairValue::Uint8(try!(self.u32()) as u8),
+            data_type_t::DATA_TYPE_INT16 => PairValue::Int16(try!(self.u32()) as i16),
+            data_type_t::DATA_TYPE_UINT16 => PairValue::Uint16(try!(self.u32()) as u16),
+            data_type_t::DATA_TYPE_INT32 => PairValue::Int32(try!(self.i32())),
+            data_type_t::DATA_TYPE_UINT32 => PairValue::Uint32(try!(self.u32())),
+            data_type_t::DATA_TYPE_INT64 => PairValue::Int64(try!(self.u64()) as i64),
+            data_type_t::DATA_TYPE_UINT64 => PairValue::Uint64(try!(self.u64())),
+            data_type_t::DATA_TYPE_HRTIME => PairValue::HrTime(try!(self.u64()) as i64),
+            data_type_t::DATA_TYPE_STRING => PairValue::String(try!(self.xdr_string())),
+            data_type_t::DATA_TYPE_NVLIST => PairValue::NvList(try!(self.xdr_list())),
+            data_type_t::DATA_TYPE_BYTE_ARRAY => {
+                let nelem = try!(self.count(nelem, 1, limit));
+                PairValue::ByteArray(try!(self.xdr_opaque(nelem)).to_vec())
+            },
+            data_type_t::DATA_TYPE_BOOLEAN_ARRAY => xdr_array!(BooleanValueArray, u32, |v| v != 0),
+            data_type_t::DATA_TYPE_INT8_ARRAY => xdr_array!(Int8Array, u32, |v| v as i8),
+            data_type_t::DATA_TYPE_UINT8_ARRAY => xdr_array!(Uint8Array, u32, |v| v as u8),
+            data_type_t::DATA_TYPE_INT16_ARRAY => xdr_array!(Int16Array, u32, |v| v as i16),
+            data_type_t::DATA_TYPE_UINT16_ARRAY => xdr_array!(Uint16Array, u32, |v| v as u16),
+            data_type_t::DATA_TYPE_INT32_ARRAY => xdr_array!(Int32Array, u32, |v| v as i32),
+            data_type_t::DATA_TYPE_UINT32_ARRAY => xdr_array!(Uint32Array, u32, |v| v),
+            data_type_t::DATA_TYPE_INT64_ARRAY => xdr_array!(Int64Array, u64, |v| v as i64),
+            data_type_t::DATA_TYPE_UINT64_ARRAY => xdr_array!(Uint64Array, u64, |v| v),
+            data_type_t::DATA_TYPE_STRING_ARRAY => {
+                let nelem = try!(self.count(nelem, 4, limit));
+                let mut v = Vec::with_capacity(nelem);
+                for _ in 0..nelem {
+                    v.push(try!(self.xdr_string()));
+                }
+                PairValue::StringArray(v)
+            },
+            data_type_t::DATA_TYPE_NVLIST_ARRAY => {
+                let nelem = try!(self.count(nelem, 4, limit));
+                let mut v = Vec::with_capacity(nelem);
+                for _ in 0..nelem {
+                    v.push(try!(self.xdr_list()));
+                }
+                PairValue::NvListArray(v)
+            },
+            data_type_t::DATA_TYPE_UNKNOWN => return Err(Error::UnkownDataType(format!("{:?}", data_type)))
+        };
+
+        Ok(Pair(name.into(), value))
+    }
+
+    fn native_list(&mut self) -> Result<List<'static>, Error> {
+        let mut list = List::new(try!(self.list_header()));
+        loop {
+            // Peek at nvp_size; a zero size ends the list.
+            let size = try!(self.i32());
+            if size == 0 {
+                break;
+            }
+            self.pos -= 4;
+            list.add(try!(self.native_pair()));
+        }
+        self.depth -= 1;
+        Ok(list)
+    }
+
+    fn native_pair(&mut self) -> Result<Pair<'static>, Error> {
+        let start = self.pos;
+        let size = try!(self.i32());
+        let name_sz = try!(self.u16()) as usize;
+        try!(self.skip(2));
+        let nelem = try!(self.i32());
+        let raw_type = try!(self.i32());
+        if size < 0 || (size as usize) < NVP_HEADER_SIZE + name_sz {
+            return malformed("nvpair size too small");
+        }
+        let end = start + size as usize;
+        if end > self.buf.len() {
+            return malformed("unexpected end of packed nvlist");
+        }
+        let data_type = match data_type_from_i32(raw_type) {
+            Some(t) => t,
+            None => return Err(Error::UnkownDataType(format!("{}", raw_type)))
+        };
+
+        let name_bytes = try!(self.take(name_sz));
+        let name = try!(Decoder::utf8(&name_bytes[..name_sz.saturating_sub(1)]).map_err(|e| match e {
+            Error::IllegalString(e) => Error::IllegalName(e),
+            e => e
+        }));
+        self.pos = start + align8(NVP_HEADER_SIZE + name_sz);
+        if self.pos > end {
+            return malformed("nvpair name overruns its size");
+        }
+
+        macro_rules! native_array {
+            ($variant:ident, $read:ident, $conv:expr, $size:expr) => {{
+                let nelem = try!(self.count(nelem, $size, end));
+                let mut v = Vec::with_capacity(nelem);
+                for _ in 0..nelem {
+                    v.push($conv(try!(self.$read())));
+                }
+                PairValue::$variant(v)
+            }}
+        }
+
+        let mut embedded = 0;
+        let value = match data_type {
+            data_type_t::DATA_TYPE_BOOLEAN => PairValue::Boolean,
+            data_type_t::DATA_TYPE_BOOLEAN_VALUE => PairValue::BooleanValue(try!(self.i32()) != 0),
+            data_type_t::DATA_TYPE_BYTE => PairValue::Byte(try!(self.u8())),
+            data_type_t::DATA_TYPE_INT8 => PairValue::Int8(try!(self.u8()) as i8),
+            data_type_t::DATA_TYPE_UINT8 => PairValue::Uint8(try!(self.u8())),
+            data_type_t::DATA_TYPE_INT16 => PairValue::Int16(try!(self.u16()) as i16),
+            data_type_t::DATA_TYPE_UINT16 => PairValue::Uint16(try!(self.u16())),
+            data_type_t::DATA_TYPE_INT32 => PairValue::Int32(try!(self.i32())),
+            data_type_t::DATA_TYPE_UINT32 => PairValue::Uint32(try!(self.u32())),
+            data_type_t::DATA_TYPE_INT64 => PairValue::Int64(try!(self.u64()) as i64),
+            data_type_t::DATA_TYPE_UINT64 => PairValue::Uint64(try!(self.u64())),
+            data_type_t::DATA_TYPE_HRTIME => PairValue::HrTime(try!(self.u64()) as i64),
+            data_type_t::DATA_TYPE_STRING => PairValue::String(try!(self.c_string())),
+            data_type_t::DATA_TYPE_NVLIST => {
+                embedded = 1;
+                PairValue::NvList(List::new(Uniqueness::Unknown))
+            },
+            data_type_t::DATA_TYPE_BYTE_ARRAY => {
+                let nelem = try!(self.count(nelem, 1, end));
+                PairValue::ByteArray(try!(self.take(nelem)).to_vec())
+            },
+            data_type_t::DATA_TYPE_BOOLEAN_ARRAY => native_array!(BooleanValueArray, u32, |v| v != 0, 4),
+            data_type_t::DATA_TYPE_INT8_ARRAY => native_array!(Int8Array, u8, |v| v as i8, 1),
+            data_type_t::DATA_TYPE_UINT8_ARRAY => {
+                let nelem = try!(self.count(nelem, 1, end));
+                PairValue::Uint8Array(try!(self.take(nelem)).to_vec())
+            },
+            data_type_t::DATA_TYPE_INT16_ARRAY => native_array!(Int16Array, u16, |v| v as i16, 2),
+            data_type_t::DATA_TYPE_UINT16_ARRAY => native_array!(Uint16Array, u16, |v| v, 2),
+            data_type_t::DATA_TYPE_INT32_ARRAY => native_array!(Int32Array, u32, |v| v as i32, 4),
+            data_type_t::DATA_TYPE_UINT32_ARRAY => native_array!(Uint32Array, u32, |v| v, 4),
+            data_type_t::DATA_TYPE_INT64_ARRAY => native_array!(Int64Array, u64, |v| v as i64, 8),
+            data_type_t::DATA_TYPE_UINT64_ARRAY => native_array!(Uint64Array, u64, |v| v, 8),
+            data_type_t::DATA_TYPE_STRING_ARRAY => {
+                // A pointer and at least a NUL per string.
+                let nelem = try!(self.count(nelem, 9, end));
+                try!(self.skip(8 * nelem));
+                let mut v = Vec::with_capacity(nelem);
+                for _ in 0..nelem {
+                    v.push(try!(self.c_string()));
+                }
+                PairValue::StringArray(v)
+            },
+            data_type_t::DATA_TYPE_NVLIST_ARRAY => {
+                // A pointer and an nvlist_t per embedded list.
+                embedded = try!(self.count(nelem, 8 + NVLIST_SIZE, end));
+                PairValue::NvListArray(Vec::new())
+            },
+            data_type_t::DATA_TYPE_UNKNOWN => return Err(Error::UnkownDataType(format!("{:?}", data_type)))
+        };
+
+        if self.pos > end {
+            return malformed("nvpair value overruns its size");
+        }
+        self.pos = end;
+
+        let value = match value {
+            PairValue::NvList(_) => PairValue::NvList(try!(self.native_list())),
+            PairValue::NvListArray(_) => {
+                let mut v = Vec::with_capacity(embedded);
+                for _ in 0..embedded {
+                    v.push(try!(self.native_list()));
+                }
+                PairValue::NvListArray(v)
+            },
+            value => value
+        };
+
+        Ok(Pair(name.into(), value))
+    }
+}
+
+/// Unpacks a list packed by `nvlist_pack` or `pack`, in either encoding.
+pub fn unpack(buf: &[u8]) -> Result<List<'static>, Error> {
+    if buf.len() < 4 {
+        return malformed("missing nvlist header");
+    }
+    let big_endian = match buf[1] {
+        NVS_BIG_ENDIAN => true,
+        NVS_LITTLE_ENDIAN => false,
+        e => return Err(Error::InvalidPacked(format!("unknown endianness {}", e)))
+    };
+
+    let mut decoder = Decoder {
+        buf: buf,
+        pos: 4,
+        big_endian: big_endian,
+        depth: 0
+    };
+
+    match buf[0] {
+        NV_ENCODE_NATIVE => decoder.native_list(),
+        NV_ENCODE_XDR => {
+            decoder.big_endian = true;
+            decoder.xdr_list()
+        },
+        e => Err(Error::InvalidPacked(format!("unknown encoding {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+    use std::os::raw::c_char;
+    use ffi::*;
+    use super::super::{List, Pair, PairValue, Uniqueness};
+    use super::{pack, unpack, Encoding};
+
+    fn sample() -> List<'static> {
+        let mut inner = List::new(Uniqueness::Name);
+        inner.add(Pair("type".into(), PairValue::String("disk".to_owned())));
+        inner.add(Pair("guid".into(), PairValue::Uint64(0x1234_5678_9abc_def0)));
+        let mut child = List::new(Uniqueness::Name);
+        child.add(Pair("id".into(), PairValue::Uint64(0)));
+
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("flag".into(), PairValue::Boolean));
+        l.add(Pair("bool".into(), PairValue::BooleanValue(true)));
+        l.add(Pair("byte".into(), PairValue::Byte(0xff)));
+        l.add(Pair("int8".into(), PairValue::Int8(-3)));
+        l.add(Pair("uint8".into(), PairValue::Uint8(200)));
+        l.add(Pair("int16".into(), PairValue::Int16(-300)));
+        l.add(Pair("uint16".into(), PairValue::Uint16(60000)));
+        l.add(Pair("int32".into(), PairValue::Int32(-70000)));
+        l.add(Pair("uint32".into(), PairValue::Uint32(4000000000)));
+        l.add(Pair("int64".into(), PairValue::Int64(-1)));
+        l.add(Pair("hrtime".into(), PairValue::HrTime(1234567890)));
+        l.add(Pair("name".into(), PairValue::String("tank".to_owned())));
+        l.add(Pair("vdev_tree".into(), PairValue::NvList(inner)));
+        l.add(Pair("bytes".into(), PairValue::ByteArray(vec![1, 2, 3, 4, 5])));
+        l.add(Pair("bools".into(), PairValue::BooleanValueArray(vec![true, false])));
+        l.add(Pair("int8s".into(), PairValue::Int8Array(vec![-1, 1])));
+        l.add(Pair("int16s".into(), PairValue::Int16Array(vec![-1, 1, 2])));
+        l.add(Pair("uint32s".into(), PairValue::Uint32Array(vec![])));
+        l.add(Pair("uint64s".into(), PairValue::Uint64Array(vec![1, 2])));
+        l.add(Pair("strings".into(), PairValue::StringArray(vec!["a".to_owned(), "bcdef".to_owned()])));
+        l.add(Pair("children".into(), PairValue::NvListArray(vec![child.clone(), child])));
+        l
+    }
+
+    #[test]
+    fn xdr_roundtrip() {
+        let l = sample();
+        assert_eq!(unpack(&pack(&l, Encoding::Xdr).unwrap()), Ok(l));
+    }
+
+    #[test]
+    fn native_roundtrip() {
+        let l = sample();
+        assert_eq!(unpack(&pack(&l, Encoding::Native).unwrap()), Ok(l));
+    }
+
+    #[test]
+    fn xdr_known_bytes() {
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("a".into(), PairValue::Uint64(1)));
+        let packed = pack(&l, Encoding::Xdr).unwrap();
+        let mut expected = vec![1, if cfg!(target_endian = "big") { 0 } else { 1 }, 0, 0,
+                                0, 0, 0, 0,  0, 0, 0, 1,
+                                0, 0, 0, 32, 0, 0, 0, 32,
+                                0, 0, 0, 1,  b'a', 0, 0, 0,
+                                0, 0, 0, 8,  0, 0, 0, 1,
+                                0, 0, 0, 0,  0, 0, 0, 1];
+        expected.extend_from_slice(&[0; 8]);
+        assert_eq!(packed, expected);
+    }
+
+    #[test]
+    fn unpack_rejects_truncated() {
+        let packed = pack(&sample(), Encoding::Xdr).unwrap();
+        assert!(unpack(&packed[..packed.len() - 3]).is_err());
+    }
+
+    // A native list holding one pair with the given header fields, in the
+    // byte order of this host.
+    fn native_with_pair(size: i32, name_sz: u16, nelem: i32, data_type: i32, rest: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0, if cfg!(target_endian = "big") { 0 } else { 1 }, 0, 0];
+        buf.extend_from_slice(&0i32.to_ne_bytes());
+        buf.extend_from_slice(&1u32.to_ne_bytes());
+        buf.extend_from_slice(&size.to_ne_bytes());
+        buf.extend_from_slice(&name_sz.to_ne_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&nelem.to_ne_bytes());
+        buf.extend_from_slice(&data_type.to_ne_bytes());
+        buf.extend_from_slice(rest);
+        buf
+    }
+
+    // An XDR list holding one pair named "a" with the given header fields.
+    fn xdr_with_pair(nelem: i32, data_type: i32, rest: &[u8]) -> Vec<u8> {
+        let mut buf = vec![1, 0, 0, 0];
+        for word in &[0i32, 1, 64, 64, 1] {
+            buf.extend_from_slice(&word.to_be_bytes());
+        }
+        buf.extend_from_slice(&[b'a', 0, 0, 0]);
+        buf.extend_from_slice(&data_type.to_be_bytes());
+        buf.extend_from_slice(&nelem.to_be_bytes());
+        buf.extend_from_slice(rest);
+        buf
+    }
+
+    #[test]
+    fn unpack_rejects_bad_element_counts() {
+        let values = [0u8; 64];
+        for &nelem in &[-1, i32::max_value()] {
+            for &data_type in &[data_type_t::DATA_TYPE_UINT64_ARRAY as i32, data_type_t::DATA_TYPE_BYTE_ARRAY as i32,
+                                data_type_t::DATA_TYPE_STRING_ARRAY as i32, data_type_t::DATA_TYPE_NVLIST_ARRAY as i32] {
+                assert!(unpack(&native_with_pair(88, 2, nelem, data_type, &values)).is_err());
+                assert!(unpack(&xdr_with_pair(nelem, data_type, &values)).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn unpack_rejects_bad_name_sizes() {
+        let rest = [b'a'; 64];
+        // The name runs past the end of the buffer.
+        assert!(unpack(&native_with_pair(0x10018, 0xffff, 1, data_type_t::DATA_TYPE_STRING as i32, &rest)).is_err());
+        // The aligned name runs past the end of the pair, at the end of the buffer.
+        assert!(unpack(&native_with_pair(19, 3, 1, data_type_t::DATA_TYPE_STRING as i32, b"ab\0")).is_err());
+    }
+
+    #[test]
+    fn unpack_rejects_pair_smaller_than_header() {
+        assert!(unpack(&native_with_pair(8, 2, 1, data_type_t::DATA_TYPE_UINT64 as i32, &[0; 16])).is_err());
+        assert!(unpack(&native_with_pair(17, 2, 1, data_type_t::DATA_TYPE_UINT64 as i32, &[0; 16])).is_err());
+    }
+
+    #[test]
+    fn unpack_rejects_deep_nesting() {
+        let mut l = List::new(Uniqueness::Name);
+        for _ in 0..30 {
+            let mut outer = List::new(Uniqueness::Name);
+            outer.add(Pair("l".into(), PairValue::NvList(l)));
+            l = outer;
+        }
+        assert!(unpack(&pack(&l, Encoding::Xdr).unwrap()).is_err());
+        assert!(unpack(&pack(&l, Encoding::Native).unwrap()).is_err());
+    }
+
+    fn native_pack(l: &List, encoding: int) -> Vec<u8> {
+        let nvlist = l.to_ffi().unwrap();
+        let mut len: usize = 0;
+        assert_eq!(unsafe { nvlist_size(nvlist, &mut len, encoding) }, 0);
+        let mut packed = vec![0u8; len];
+        let mut buf = packed.as_mut_ptr() as *mut c_char;
+        assert_eq!(unsafe { nvlist_pack(nvlist, &mut buf, &mut len, encoding, 0) }, 0);
+        unsafe { nvlist_free(nvlist) };
+        packed.truncate(len);
+        packed
+    }
+
+    fn native_unpack(packed: &[u8]) -> List<'static> {
+        let mut nvlist: *mut nvlist_t = ptr::null_mut();
+        assert_eq!(unsafe { nvlist_unpack(packed.as_ptr() as *mut c_char, packed.len(), &mut nvlist, 0) }, 0);
+        let l = List::from_ffi(nvlist).unwrap();
+        unsafe { nvlist_free(nvlist) };
+        l
+    }
+
+    #[test]
+    fn xdr_matches_libnvpair() {
+        let l = sample();
+        let packed = pack(&l, Encoding::Xdr).unwrap();
+        assert_eq!(packed, native_pack(&l, NV_ENCODE_XDR));
+        assert_eq!(native_unpack(&packed), l);
+    }
+
+    #[test]
+    fn native_matches_libnvpair() {
+        let l = sample();
+        let packed = pack(&l, Encoding::Native).unwrap();
+        assert_eq!(packed, native_pack(&l, NV_ENCODE_NATIVE));
+        assert_eq!(native_unpack(&packed), l);
+    }
+}
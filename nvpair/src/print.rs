@@ -0,0 +1,141 @@
+// Renders a `List` the way nvlist_print(3NVPAIR) does with its default
+// settings: one pair per line, nested lists indented by a tab and unsigned
+// integers in hexadecimal.
+
+use std::fmt;
+
+use super::{List, PairValue};
+
+fn indent(f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+    for _ in 0..depth {
+        try!(f.write_str("\t"));
+    }
+    Ok(())
+}
+
+fn print_array<T, F>(f: &mut fmt::Formatter, depth: usize, name: &str, values: &[T], print: F) -> fmt::Result
+    where F: Fn(&mut fmt::Formatter, &T) -> fmt::Result
+{
+    for (i, value) in values.iter().enumerate() {
+        if i == 0 {
+            try!(indent(f, depth));
+            try!(write!(f, "{} = ", name));
+        } else {
+            try!(f.write_str(" "));
+        }
+        try!(print(f, value));
+    }
+    Ok(())
+}
+
+fn print_list(f: &mut fmt::Formatter, list: &List, depth: usize) -> fmt::Result {
+    try!(indent(f, depth));
+    try!(writeln!(f, "nvlist version: 0"));
+
+    let depth = depth + 1;
+    for pair in &list.pairs {
+        let name: &str = &pair.0;
+        match pair.1 {
+            PairValue::NvList(ref l) => {
+                try!(indent(f, depth));
+                try!(writeln!(f, "{} = (embedded nvlist)", name));
+                try!(print_list(f, l, depth));
+                try!(indent(f, depth));
+                try!(writeln!(f, "(end {})", name));
+            },
+            PairValue::NvListArray(ref v) => {
+                try!(indent(f, depth));
+                try!(writeln!(f, "{} = (array of embedded nvlists)", name));
+                for (i, l) in v.iter().enumerate() {
+                    try!(indent(f, depth));
+                    try!(writeln!(f, "(start {}[{}])", name, i));
+                    try!(print_list(f, l, depth));
+                    try!(indent(f, depth));
+                    try!(writeln!(f, "(end {}[{}])", name, i));
+                }
+            },
+            PairValue::BooleanValueArray(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "{}", *v as i32))),
+            PairValue::ByteArray(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "0x{:02x}", v))),
+            PairValue::Int8Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "{}", v))),
+            PairValue::Uint8Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "0x{:x}", v))),
+            PairValue::Int16Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "{}", v))),
+            PairValue::Uint16Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "0x{:x}", v))),
+            PairValue::Int32Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "{}", v))),
+            PairValue::Uint32Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "0x{:x}", v))),
+            PairValue::Int64Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "{}", v))),
+            PairValue::Uint64Array(ref v) => try!(print_array(f, depth, name, v, |f, v| write!(f, "0x{:x}", v))),
+            PairValue::StringArray(ref v) => try!(print_array(f, depth, name, v, |f, v| f.write_str(v))),
+            ref scalar => {
+                try!(indent(f, depth));
+                try!(write!(f, "{} = ", name));
+                try!(match *scalar {
+                    PairValue::Boolean => write!(f, "1"),
+                    PairValue::BooleanValue(v) => write!(f, "{}", v as i32),
+                    PairValue::Byte(v) => write!(f, "0x{:02x}", v),
+                    PairValue::Int8(v) => write!(f, "{}", v),
+                    PairValue::Uint8(v) => write!(f, "0x{:x}", v),
+                    PairValue::Int16(v) => write!(f, "{}", v),
+                    PairValue::Uint16(v) => write!(f, "0x{:x}", v),
+                    PairValue::Int32(v) => write!(f, "{}", v),
+                    PairValue::Uint32(v) => write!(f, "0x{:x}", v),
+                    PairValue::Int64(v) => write!(f, "{}", v),
+                    PairValue::Uint64(v) => write!(f, "0x{:x}", v),
+                    PairValue::HrTime(v) => write!(f, "0x{:x}", v as u64),
+                    PairValue::String(ref v) => f.write_str(v),
+                    _ => unreachable!()
+                });
+            }
+        }
+        // nvlist_print ends every pair with a newline, even after the
+        // closing line of an embedded list.
+        try!(writeln!(f));
+    }
+    Ok(())
+}
+
+impl<'l> fmt::Display for List<'l> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        print_list(f, self, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{List, Pair, PairValue, Uniqueness};
+
+    #[test]
+    fn matches_nvlist_print() {
+        let mut child = List::new(Uniqueness::Name);
+        child.add(Pair("id".into(), PairValue::Uint64(0)));
+        let mut tree = List::new(Uniqueness::Name);
+        tree.add(Pair("type".into(), PairValue::String("root".to_owned())));
+        tree.add(Pair("children".into(), PairValue::NvListArray(vec![child])));
+
+        let mut l = List::new(Uniqueness::Name);
+        l.add(Pair("version".into(), PairValue::Uint64(5000)));
+        l.add(Pair("name".into(), PairValue::String("tank".to_owned())));
+        l.add(Pair("errata".into(), PairValue::Int32(-1)));
+        l.add(Pair("is_log".into(), PairValue::Boolean));
+        l.add(Pair("guids".into(), PairValue::Uint64Array(vec![10, 11])));
+        l.add(Pair("vdev_tree".into(), PairValue::NvList(tree)));
+
+        assert_eq!(format!("{}", l), "nvlist version: 0
+\tversion = 0x1388
+\tname = tank
+\terrata = -1
+\tis_log = 1
+\tguids = 0xa 0xb
+\tvdev_tree = (embedded nvlist)
+\tnvlist version: 0
+\t\ttype = root
+\t\tchildren = (array of embedded nvlists)
+\t\t(start children[0])
+\t\tnvlist version: 0
+\t\t\tid = 0x0
+\t\t(end children[0])
+
+\t(end vdev_tree)
+
+");
+    }
+}
@@ -0,0 +1,504 @@
+// Serializes any `serde::Serialize` value into a `List`.
+//
+// Structs and maps become nvlists, sequences become typed arrays, `None`
+// fields are left out and unit values become bare `DATA_TYPE_BOOLEAN`
+// flags.
+
+use serde::ser::{self, Serialize, Impossible};
+
+use super::{Error, List, Pair, PairValue, Uniqueness};
+
+/// Serializes a struct or map into a new list.
+pub fn to_list<T: ?Sized + Serialize>(value: &T) -> Result<List<'static>, Error> {
+    match try!(value.serialize(Serializer)) {
+        Some(PairValue::NvList(list)) => Ok(list),
+        _ => Err(Error::Serde("only structs and maps can be serialized into a list".to_owned()))
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: ::std::fmt::Display>(msg: T) -> Error {
+        Error::Serde(msg.to_string())
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T, Error> {
+    Err(Error::Serde(format!("{} can not be stored in an nvlist", what)))
+}
+
+// Turns the serialized elements of a sequence into a typed array. All
+// elements must have the same type; an empty sequence becomes an empty
+// uint64 array.
+fn array_from_values(values: Vec<PairValue<'static>>) -> Result<PairValue<'static>, Error> {
+    macro_rules! collect {
+        ($element:ident, $array:ident) => {{
+            let mut array = Vec::with_capacity(values.len());
+            for value in values {
+                match value {
+                    PairValue::$element(v) => array.push(v),
+                    _ => return Err(Error::Serde("array elements must all have the same type".to_owned()))
+                }
+            }
+            Ok(PairValue::$array(array))
+        }}
+    }
+
+    match values.first() {
+        None => Ok(PairValue::Uint64Array(Vec::new())),
+        Some(&PairValue::BooleanValue(_)) => collect!(BooleanValue, BooleanValueArray),
+        Some(&PairValue::Byte(_)) => collect!(Byte, ByteArray),
+        Some(&PairValue::Int8(_)) => collect!(Int8, Int8Array),
+        Some(&PairValue::Uint8(_)) => collect!(Uint8, Uint8Array),
+        Some(&PairValue::Int16(_)) => collect!(Int16, Int16Array),
+        Some(&PairValue::Uint16(_)) => collect!(Uint16, Uint16Array),
+        Some(&PairValue::Int32(_)) => collect!(Int32, Int32Array),
+        Some(&PairValue::Uint32(_)) => collect!(Uint32, Uint32Array),
+        Some(&PairValue::Int64(_)) => collect!(Int64, Int64Array),
+        Some(&PairValue::Uint64(_)) => collect!(Uint64, Uint64Array),
+        Some(&PairValue::String(_)) => collect!(String, StringArray),
+        Some(&PairValue::NvList(_)) => collect!(NvList, NvListArray),
+        Some(_) => unsupported("a nested array or flag array")
+    }
+}
+
+fn wrap_variant(variant: &'static str, value: Option<PairValue<'static>>) -> Option<PairValue<'static>> {
+    let mut list = List::new(Uniqueness::Name);
+    if let Some(value) = value {
+        list.add(Pair(variant.into(), value));
+    }
+    Some(PairValue::NvList(list))
+}
+
+/// Serializes a single value. `Ok(None)` means the value is absent and the
+/// enclosing pair should be left out.
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::BooleanValue(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Int8(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Int16(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Int32(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Int64(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Uint8(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Uint16(v)))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Uint32(v)))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Uint64(v)))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Error> {
+        unsupported("a floating point value")
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Error> {
+        unsupported("a floating point value")
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::String(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::ByteArray(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Boolean))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::Boolean))
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::String(variant.to_owned())))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Self::Ok, Error> {
+        Ok(wrap_variant(variant, try!(value.serialize(Serializer))))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer { values: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+        Ok(VariantSerializer {
+            variant: variant,
+            inner: try!(self.serialize_seq(Some(len)))
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            list: List::new(Uniqueness::Name),
+            key: None
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(VariantSerializer {
+            variant: variant,
+            inner: try!(self.serialize_map(Some(len)))
+        })
+    }
+}
+
+struct SeqSerializer {
+    values: Vec<PairValue<'static>>
+}
+
+impl SeqSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        match try!(value.serialize(Serializer)) {
+            Some(value) => {
+                self.values.push(value);
+                Ok(())
+            },
+            None => unsupported("an absent array element")
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        array_from_values(self.values).map(Some)
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer {
+    list: List<'static>,
+    key: Option<String>
+}
+
+impl MapSerializer {
+    fn insert<T: ?Sized + Serialize>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        if let Some(value) = try!(value.serialize(Serializer)) {
+            self.list.add(Pair(key.into(), value));
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(try!(key.serialize(KeySerializer)));
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        match self.key.take() {
+            Some(key) => self.insert(key, value),
+            None => Err(Error::Serde("map value serialized without a key".to_owned()))
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(PairValue::NvList(self.list)))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Wraps the value of an enum variant in a single pair list, named after
+/// the variant.
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        let value = try!(ser::SerializeSeq::end(self.inner));
+        Ok(wrap_variant(self.variant, value))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Option<PairValue<'static>>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.inner.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        let value = try!(ser::SerializeMap::end(self.inner));
+        Ok(wrap_variant(self.variant, value))
+    }
+}
+
+/// Pair names must be strings.
+struct KeySerializer;
+
+fn key_must_be_string<T>() -> Result<T, Error> {
+    Err(Error::Serde("nvlist keys must be strings".to_owned()))
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<String, Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_i8(self, _v: i8) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_i16(self, _v: i16) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_i32(self, _v: i32) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_i64(self, _v: i64) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_u8(self, _v: u8) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_u16(self, _v: u16) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_u32(self, _v: u32) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_u64(self, _v: u64) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_f32(self, _v: f32) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_f64(self, _v: f64) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_none(self) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_unit(self) -> Result<String, Error> { key_must_be_string() }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> { key_must_be_string() }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<String, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<String, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+        key_must_be_string()
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Error> {
+        key_must_be_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use super::super::{Error, List, Pair, PairValue, Uniqueness};
+    use super::to_list;
+
+    #[derive(Serialize)]
+    struct Vdev {
+        id: u64,
+        path: String
+    }
+
+    #[derive(Serialize)]
+    struct Config {
+        name: String,
+        version: u64,
+        txg: Option<u64>,
+        comment: Option<String>,
+        readonly: bool,
+        is_log: (),
+        children: Vec<Vdev>,
+        errata: Vec<i32>
+    }
+
+    #[test]
+    fn struct_to_list() {
+        let config = Config {
+            name: "tank".to_owned(),
+            version: 5000,
+            txg: Some(4),
+            comment: None,
+            readonly: false,
+            is_log: (),
+            children: vec![Vdev { id: 0, path: "/dev/sda".to_owned() }],
+            errata: vec![1, 2]
+        };
+
+        let mut child = List::new(Uniqueness::Name);
+        child.add(Pair("id".into(), PairValue::Uint64(0)));
+        child.add(Pair("path".into(), PairValue::String("/dev/sda".to_owned())));
+        let mut expected = List::new(Uniqueness::Name);
+        expected.add(Pair("name".into(), PairValue::String("tank".to_owned())));
+        expected.add(Pair("version".into(), PairValue::Uint64(5000)));
+        expected.add(Pair("txg".into(), PairValue::Uint64(4)));
+        expected.add(Pair("readonly".into(), PairValue::BooleanValue(false)));
+        expected.add(Pair("is_log".into(), PairValue::Boolean));
+        expected.add(Pair("children".into(), PairValue::NvListArray(vec![child])));
+        expected.add(Pair("errata".into(), PairValue::Int32Array(vec![1, 2])));
+
+        assert_eq!(to_list(&config), Ok(expected));
+    }
+
+    #[test]
+    fn map_to_list() {
+        let mut map = BTreeMap::new();
+        map.insert("compression", "lz4");
+        let mut expected = List::new(Uniqueness::Name);
+        expected.add(Pair("compression".into(), PairValue::String("lz4".to_owned())));
+        assert_eq!(to_list(&map), Ok(expected));
+    }
+
+    #[test]
+    fn rejects_non_maps() {
+        assert!(to_list(&5u64).is_err());
+        let mut map = BTreeMap::new();
+        map.insert(1, 2);
+        assert_eq!(to_list(&map), Err(Error::Serde("nvlist keys must be strings".to_owned())));
+    }
+}
@@ -0,0 +1,130 @@
+use std::io;
+use std::ptr;
+
+use nvpair::{List, NvList, NvListRef, Pair, PairValue, Uniqueness};
+use nvpair::ffi::nvlist_t;
+use zfs_sys;
+
+use super::{Dataset, DatasetType};
+use super::snapshot::lzc_result;
+use super::super::{to_c_string, Error, Handle, Result};
+
+/// A bookmark of a filesystem or volume, as listed by `Dataset::bookmarks`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Bookmark {
+    /// The full name, like `pool/fs#mark`, usable as `SendOptions::from`.
+    pub name: String,
+    /// The GUID of the snapshot the bookmark was created from.
+    pub guid: u64,
+    pub createtxg: u64,
+    /// Seconds since the epoch.
+    pub creation: u64,
+}
+
+/// Checks that `name` is a full bookmark name like `pool/fs#mark`.
+fn validate_bookmark_name(name: &str) -> Result<()> {
+    let mut parts = name.splitn(2, '#');
+    match (parts.next(), parts.next()) {
+        (Some(dataset), Some(mark)) if !dataset.is_empty() && !dataset.contains('@') && !mark.is_empty()
+            && !mark.contains(|c| c == '#' || c == '@' || c == '/') => Ok(()),
+        _ => Err(Error::InvalidArgument(format!("'{}' is not a bookmark name like pool/fs#mark", name)))
+    }
+}
+
+/// Reads a numeric property from a `lzc_get_bookmarks` property list,
+/// where every property is a list with a `value`.
+fn bookmark_prop(props: &NvListRef, name: &str) -> Result<u64> {
+    let value = match try!(props.lookup_nvlist(name).map_err(Error::Nvpair)) {
+        Some(prop) => try!(prop.lookup_uint64("value").map_err(Error::Nvpair)),
+        None => None
+    };
+    value.ok_or_else(|| Error::UnavailableProperty(name.to_owned()))
+}
+
+impl<'h> Dataset<'h> {
+    /// Creates a bookmark `name` of this snapshot in its filesystem, like
+    /// `zfs bookmark pool/fs@snap pool/fs#name`. Returns the full name of
+    /// the bookmark.
+    pub fn bookmark(&self, name: &str) -> Result<String> {
+        let snapshot = self.name();
+        if self.dataset_type() != Some(DatasetType::Snapshot) {
+            return Err(Error::InvalidArgument(format!("'{}' is not a snapshot", snapshot)));
+        }
+        let bookmark = format!("{}#{}", snapshot.splitn(2, '@').next().unwrap(), name);
+        try!(validate_bookmark_name(&bookmark));
+
+        let mut bookmarks = List::new(Uniqueness::Name);
+        bookmarks.add(Pair::new(&*bookmark, PairValue::String(snapshot)));
+        let bookmarks = try!(NvList::from_list(&bookmarks).map_err(Error::Nvpair));
+        try!(lzc_result(|errors| unsafe { zfs_sys::lzc_bookmark(bookmarks.as_ptr(), errors) }));
+        Ok(bookmark)
+    }
+
+    /// The bookmarks of this filesystem or volume, like
+    /// `zfs list -t bookmark -o name,guid,createtxg,creation`.
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>> {
+        let name = self.name();
+        let c_name = try!(to_c_string(&name));
+        let mut props = List::new(Uniqueness::Name);
+        for prop in &["guid", "createtxg", "creation"] {
+            props.add(Pair::new(*prop, PairValue::Boolean));
+        }
+        let props = try!(NvList::from_list(&props).map_err(Error::Nvpair));
+        let mut raw: *mut nvlist_t = ptr::null_mut();
+        let rc = unsafe { zfs_sys::lzc_get_bookmarks(c_name.as_ptr(), props.as_ptr(), &mut raw) };
+        if rc != 0 {
+            return Err(Error::Io(io::Error::from_raw_os_error(rc)));
+        }
+        let marks = unsafe { NvList::from_raw(raw) };
+
+        let mut bookmarks = Vec::new();
+        for pair in marks.pairs() {
+            let mark = try!(pair.name().map_err(Error::Nvpair));
+            let props = match try!(marks.lookup_nvlist(&mark).map_err(Error::Nvpair)) {
+                Some(props) => props,
+                None => continue
+            };
+            bookmarks.push(Bookmark {
+                name: format!("{}#{}", name, mark),
+                guid: try!(bookmark_prop(&props, "guid")),
+                createtxg: try!(bookmark_prop(&props, "createtxg")),
+                creation: try!(bookmark_prop(&props, "creation")),
+            });
+        }
+        bookmarks.sort_by_key(|b| b.createtxg);
+        Ok(bookmarks)
+    }
+}
+
+impl Handle {
+    /// Destroys bookmarks, named like `pool/fs#mark`, like
+    /// `zfs destroy pool/fs#mark`. All bookmarks must be in the same pool
+    /// and names that do not exist are ignored. On failure the error is
+    /// `Error::Batch` with the errno of every bookmark that could not be
+    /// destroyed.
+    pub fn destroy_bookmarks(&self, names: &[&str]) -> Result<()> {
+        let mut bookmarks = List::new(Uniqueness::Name);
+        for name in names {
+            try!(validate_bookmark_name(name));
+            bookmarks.add(Pair::new(*name, PairValue::Boolean));
+        }
+        let bookmarks = try!(NvList::from_list(&bookmarks).map_err(Error::Nvpair));
+        lzc_result(|errors| unsafe { zfs_sys::lzc_destroy_bookmarks(bookmarks.as_ptr(), errors) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::validate_bookmark_name;
+
+    #[test]
+    fn bookmark_names() {
+        assert!(validate_bookmark_name("tank/fs#mark").is_ok());
+        assert!(validate_bookmark_name("tank/fs").is_err());
+        assert!(validate_bookmark_name("#mark").is_err());
+        assert!(validate_bookmark_name("tank#").is_err());
+        assert!(validate_bookmark_name("tank#a#b").is_err());
+        assert!(validate_bookmark_name("tank/fs@snap#mark").is_err());
+        assert!(validate_bookmark_name("tank#a/b").is_err());
+    }
+}
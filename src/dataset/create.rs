@@ -0,0 +1,329 @@
+use std::os::raw::c_int;
+
+use nvpair::{List, NvList, Pair, PairValue, Uniqueness};
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+use zfs_sys::{zfs_error_t, zfs_type_t};
+
+use super::{Dataset, DatasetProp, DatasetType};
+use super::property::validate_user_property_name;
+use super::super::{to_c_string, Compression, Error, ErrorKind, Handle, PropValue, Result};
+
+/// The `encryption` property, needs a libzfs with native encryption.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Encryption {
+    Off,
+    On,
+    Aes128Ccm,
+    Aes192Ccm,
+    Aes256Ccm,
+    Aes128Gcm,
+    Aes192Gcm,
+    Aes256Gcm,
+}
+
+impl Encryption {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encryption::Off => "off",
+            Encryption::On => "on",
+            Encryption::Aes128Ccm => "aes-128-ccm",
+            Encryption::Aes192Ccm => "aes-192-ccm",
+            Encryption::Aes256Ccm => "aes-256-ccm",
+            Encryption::Aes128Gcm => "aes-128-gcm",
+            Encryption::Aes192Gcm => "aes-192-gcm",
+            Encryption::Aes256Gcm => "aes-256-gcm",
+        }
+    }
+}
+
+/// The `keyformat` property.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum KeyFormat {
+    Raw,
+    Hex,
+    Passphrase,
+}
+
+impl KeyFormat {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            KeyFormat::Raw => "raw",
+            KeyFormat::Hex => "hex",
+            KeyFormat::Passphrase => "passphrase",
+        }
+    }
+}
+
+// From sys/spa.h.
+const SPA_MINBLOCKSIZE: u64 = 512;
+const SPA_MAXBLOCKSIZE: u64 = 16 * 1024 * 1024;
+const SPA_OLD_MAXBLOCKSIZE: u64 = 128 * 1024;
+const DEFAULT_VOLBLOCKSIZE: u64 = 8 * 1024;
+
+// Native properties newer than `zfs_prop_t`, only set by name.
+const ENCRYPTION_PROPS: [&'static str; 3] = ["encryption", "keyformat", "keylocation"];
+
+/// Properties and options for `Handle::create_filesystem` and
+/// `Handle::create_volume`, like the `-o` and `-p` options of
+/// `zfs create`. Values are checked when the dataset is created.
+#[derive(Debug,Clone,Default)]
+pub struct DatasetProps {
+    props: Vec<(String, PropValue)>,
+    parents: bool,
+}
+
+impl DatasetProps {
+    pub fn new() -> DatasetProps {
+        DatasetProps::default()
+    }
+
+    /// Sets any native property, replacing an earlier value.
+    pub fn property<V: Into<PropValue>>(self, prop: DatasetProp, value: V) -> DatasetProps {
+        self.named(prop.name(), value.into())
+    }
+
+    pub fn user_property(self, name: &str, value: &str) -> DatasetProps {
+        self.named(name, PropValue::String(value.to_owned()))
+    }
+
+    fn named(mut self, name: &str, value: PropValue) -> DatasetProps {
+        self.props.retain(|&(ref n, _)| n != name);
+        self.props.push((name.to_owned(), value));
+        self
+    }
+
+    pub fn compression(self, compression: Compression) -> DatasetProps {
+        self.property(DatasetProp::Compression, compression)
+    }
+
+    pub fn recordsize(self, bytes: u64) -> DatasetProps {
+        self.property(DatasetProp::Recordsize, PropValue::Bytes(bytes))
+    }
+
+    pub fn volblocksize(self, bytes: u64) -> DatasetProps {
+        self.property(DatasetProp::Volblocksize, PropValue::Bytes(bytes))
+    }
+
+    pub fn quota(self, bytes: u64) -> DatasetProps {
+        self.property(DatasetProp::Quota, PropValue::Bytes(bytes))
+    }
+
+    /// An absolute path, `none` or `legacy`.
+    pub fn mountpoint(self, mountpoint: &str) -> DatasetProps {
+        self.property(DatasetProp::Mountpoint, mountpoint)
+    }
+
+    pub fn encryption(self, encryption: Encryption) -> DatasetProps {
+        self.named("encryption", PropValue::Index(encryption.name().to_owned()))
+    }
+
+    pub fn keyformat(self, format: KeyFormat) -> DatasetProps {
+        self.named("keyformat", PropValue::Index(format.name().to_owned()))
+    }
+
+    /// `prompt` or a `file://` URI.
+    pub fn keylocation(self, location: &str) -> DatasetProps {
+        self.named("keylocation", PropValue::String(location.to_owned()))
+    }
+
+    /// Creates missing parent filesystems, like `zfs create -p`.
+    pub fn parents(mut self, parents: bool) -> DatasetProps {
+        self.parents = parents;
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&PropValue> {
+        self.props.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref v)| v)
+    }
+
+    fn validate(&self, dataset_type: DatasetType) -> Result<()> {
+        let invalid = |name: &str, reason: &str| Err(Error::InvalidProperty(name.to_owned(), reason.to_owned()));
+
+        for &(ref name, ref value) in &self.props {
+            if DatasetProp::from_name(name).is_none() && !ENCRYPTION_PROPS.contains(&&**name) {
+                try!(validate_user_property_name(name));
+                continue;
+            }
+            match (&**name, dataset_type) {
+                ("recordsize", DatasetType::Volume) | ("mountpoint", DatasetType::Volume) =>
+                    return invalid(name, "does not apply to volumes"),
+                ("volblocksize", DatasetType::Filesystem) =>
+                    return invalid(name, "only applies to volumes"),
+                ("recordsize", _) => match value.as_u64() {
+                    Some(n) if n.is_power_of_two() && n >= SPA_MINBLOCKSIZE && n <= SPA_MAXBLOCKSIZE => (),
+                    _ => return invalid(name, "must be a power of 2 from 512B to 16M"),
+                },
+                ("volblocksize", _) => match value.as_u64() {
+                    Some(n) if n.is_power_of_two() && n >= SPA_MINBLOCKSIZE && n <= SPA_OLD_MAXBLOCKSIZE => (),
+                    _ => return invalid(name, "must be a power of 2 from 512B to 128K"),
+                },
+                ("mountpoint", _) => match value.as_str() {
+                    Some(m) if m == "none" || m == "legacy" || m.starts_with('/') => (),
+                    _ => return invalid(name, "must be an absolute path, 'none' or 'legacy'"),
+                },
+                ("keylocation", _) => match value.as_str() {
+                    Some(l) if l == "prompt" || l.starts_with("file://") => (),
+                    _ => return invalid(name, "must be 'prompt' or a file:// URI"),
+                },
+                _ => ()
+            }
+        }
+
+        let encrypted = match self.get("encryption") {
+            Some(e) => e.as_str() != Some("off"),
+            None => false
+        };
+        if encrypted && self.get("keyformat").is_none() {
+            return invalid("keyformat", "is required when encryption is on");
+        }
+        if !encrypted && (self.get("keyformat").is_some() || self.get("keylocation").is_some()) {
+            return invalid("encryption", "must be on to set keyformat or keylocation");
+        }
+        Ok(())
+    }
+
+    /// Checks the properties for a dataset of this type and converts them
+    /// into the nvlist libzfs takes. `parents` is not included.
+    pub fn to_nvlist(&self, dataset_type: DatasetType) -> Result<NvList> {
+        try!(self.validate(dataset_type));
+        NvList::from_list(&self.to_list()).map_err(Error::Nvpair)
+    }
+
+    fn to_list(&self) -> List<'static> {
+        let mut list = List::new(Uniqueness::Name);
+        for &(ref name, ref value) in &self.props {
+            list.add(Pair::new(name.clone(), PairValue::String(value.to_string())));
+        }
+        list
+    }
+}
+
+impl Handle {
+    /// Creates a filesystem, like `zfs create`. The new filesystem is not
+    /// mounted.
+    pub fn create_filesystem(&self, name: &str, props: &DatasetProps) -> Result<Dataset> {
+        try!(props.validate(DatasetType::Filesystem));
+        self.create(name, zfs_sys::ZFS_TYPE_FILESYSTEM, props.to_list(), props.parents)
+    }
+
+    /// Creates a volume of `size` bytes, like `zfs create -V`.
+    pub fn create_volume(&self, name: &str, size: u64, props: &DatasetProps) -> Result<Dataset> {
+        try!(props.validate(DatasetType::Volume));
+        let blocksize = props.get("volblocksize").and_then(PropValue::as_u64).unwrap_or(DEFAULT_VOLBLOCKSIZE);
+        if size == 0 || size % blocksize != 0 {
+            return Err(Error::InvalidProperty("volsize".to_owned(),
+                                              format!("must be a nonzero multiple of volblocksize ({})", blocksize)));
+        }
+        let mut list = props.to_list();
+        list.add(Pair::new("volsize", PairValue::String(size.to_string())));
+        self.create(name, zfs_sys::ZFS_TYPE_VOLUME, list, props.parents)
+    }
+
+    fn create(&self, name: &str, dataset_type: zfs_type_t, props: List, parents: bool) -> Result<Dataset> {
+        let c_name = try!(to_c_string(name));
+        if parents {
+            // Like `zfs create -p`, an existing dataset is not an error.
+            if unsafe { zfs_sys::zfs_dataset_exists(self.handle, c_name.as_ptr(), dataset_type) } == boolean_t::B_TRUE {
+                return self.dataset(name);
+            }
+            if unsafe { zfs_sys::zfs_create_ancestors(self.handle, c_name.as_ptr()) } != 0 {
+                return Err(self.last_error());
+            }
+        }
+        let props = try!(NvList::from_list(&props).map_err(Error::Nvpair));
+        if unsafe { zfs_sys::zfs_create(self.handle, c_name.as_ptr(), dataset_type, props.as_ptr()) } != 0 {
+            return Err(self.last_error());
+        }
+        self.dataset(name)
+    }
+}
+
+fn pool_name(name: &str) -> &str {
+    name.split(|c| c == '/' || c == '@' || c == '#').next().unwrap_or(name)
+}
+
+impl<'h> Dataset<'h> {
+    /// Clones this snapshot into a new filesystem or volume at `target`,
+    /// like `zfs clone`. The target must be in the same pool.
+    pub fn clone_to(&self, target: &str, props: &DatasetProps) -> Result<Dataset<'h>> {
+        let origin = match self.dataset_type() {
+            Some(DatasetType::Snapshot) => try!(self.handle.dataset(self.name().splitn(2, '@').next().unwrap())),
+            _ => return Err(Error::InvalidArgument(format!("'{}' is not a snapshot", self.name())))
+        };
+        if pool_name(target) != pool_name(&self.name()) {
+            // The error zfs_clone would give, without the round trip.
+            return Err(Error::Libzfs {
+                kind: ErrorKind::CrossTarget,
+                errno: zfs_error_t::EZFS_CROSSTARGET as c_int,
+                action: format!("cannot create '{}'", target),
+                description: "source and target pools differ".to_owned(),
+            });
+        }
+        try!(props.validate(origin.dataset_type().unwrap_or(DatasetType::Filesystem)));
+
+        let c_target = try!(to_c_string(target));
+        if props.parents && unsafe { zfs_sys::zfs_create_ancestors(self.handle.handle, c_target.as_ptr()) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        let list = try!(NvList::from_list(&props.to_list()).map_err(Error::Nvpair));
+        if unsafe { zfs_sys::zfs_clone(self.raw, c_target.as_ptr(), list.as_ptr()) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        self.handle.dataset(target)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pool_name, DatasetProps, Encryption, KeyFormat};
+    use super::super::{DatasetProp, DatasetType};
+    use super::super::super::{Compression, Error};
+
+    fn rejected(props: DatasetProps, dataset_type: DatasetType) -> Option<String> {
+        match props.validate(dataset_type) {
+            Ok(()) => None,
+            Err(Error::InvalidProperty(name, _)) => Some(name),
+            Err(Error::InvalidUserProperty(name, _)) => Some(name),
+            Err(e) => panic!("unexpected error {:?}", e)
+        }
+    }
+
+    #[test]
+    fn validates_before_create() {
+        let fs = DatasetType::Filesystem;
+        assert_eq!(rejected(DatasetProps::new().compression(Compression::Lz4).recordsize(1 << 20).mountpoint("/srv"), fs), None);
+        assert_eq!(rejected(DatasetProps::new().recordsize(1000), fs), Some("recordsize".to_owned()));
+        assert_eq!(rejected(DatasetProps::new().volblocksize(8192), fs), Some("volblocksize".to_owned()));
+        assert_eq!(rejected(DatasetProps::new().mountpoint("srv"), fs), Some("mountpoint".to_owned()));
+        assert_eq!(rejected(DatasetProps::new().user_property("owner", "me"), fs), Some("owner".to_owned()));
+        assert_eq!(rejected(DatasetProps::new().volblocksize(1 << 20), DatasetType::Volume), Some("volblocksize".to_owned()));
+        assert_eq!(rejected(DatasetProps::new().recordsize(4096), DatasetType::Volume), Some("recordsize".to_owned()));
+    }
+
+    #[test]
+    fn validates_encryption() {
+        let fs = DatasetType::Filesystem;
+        let encrypted = DatasetProps::new().encryption(Encryption::Aes256Gcm);
+        assert_eq!(rejected(encrypted.clone(), fs), Some("keyformat".to_owned()));
+        assert_eq!(rejected(encrypted.clone().keyformat(KeyFormat::Passphrase).keylocation("file:///key"), fs), None);
+        assert_eq!(rejected(encrypted.keyformat(KeyFormat::Raw).keylocation("/key"), fs), Some("keylocation".to_owned()));
+        assert_eq!(rejected(DatasetProps::new().keyformat(KeyFormat::Hex), fs), Some("encryption".to_owned()));
+    }
+
+    #[test]
+    fn later_values_replace_earlier_ones() {
+        let props = DatasetProps::new().property(DatasetProp::Atime, false).property(DatasetProp::Atime, true);
+        let list = props.to_list();
+        assert_eq!(list.pairs().len(), 1);
+        assert_eq!(list.get("atime"), Some(&::nvpair::PairValue::String("on".to_owned())));
+    }
+
+    #[test]
+    fn pool_names() {
+        assert_eq!(pool_name("tank"), "tank");
+        assert_eq!(pool_name("tank/ci/env"), "tank");
+        assert_eq!(pool_name("tank@golden"), "tank");
+        assert_eq!(pool_name("tank#mark"), "tank");
+    }
+}
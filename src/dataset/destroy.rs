@@ -0,0 +1,326 @@
+use std::collections::BTreeSet;
+use std::io;
+
+use nvpair::{List, NvList, Pair, PairValue, Uniqueness};
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+
+use super::{Dataset, DatasetProp, DatasetType, ListOptions};
+use super::super::{to_c_string, Error, Handle, Result};
+
+/// How to destroy, like the options of `zfs destroy`.
+#[derive(Debug,Clone,Default)]
+pub struct DestroyOptions {
+    recursive: bool,
+    dependents: bool,
+    defer: bool,
+}
+
+impl DestroyOptions {
+    pub fn new() -> DestroyOptions {
+        DestroyOptions::default()
+    }
+
+    /// Also destroy children and snapshots, like `-r`. On a snapshot
+    /// this destroys the snapshots of the same name of all descendants,
+    /// like `zfs destroy -r fs@snap`.
+    pub fn recursive(mut self, recursive: bool) -> DestroyOptions {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Also destroy dependent clones, like `-R`. Implies `recursive`.
+    pub fn dependents(mut self, dependents: bool) -> DestroyOptions {
+        self.dependents = dependents;
+        self
+    }
+
+    /// Mark snapshots that are held or cloned for deferred destruction,
+    /// like `-d`. Only applies to snapshots.
+    pub fn defer(mut self, defer: bool) -> DestroyOptions {
+        self.defer = defer;
+        self
+    }
+}
+
+/// What a destroy would do, see `Dataset::plan_destroy`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct DestroyPlan {
+    /// Everything that would be destroyed, in the order it would be.
+    pub datasets: Vec<String>,
+    /// The space that would be freed, in bytes.
+    pub reclaimed: u64,
+}
+
+fn is_descendant(root: &str, name: &str) -> bool {
+    name.len() > root.len() && name.starts_with(root) &&
+        match name.as_bytes()[root.len()] {
+            b'/' | b'@' | b'#' => true,
+            _ => false
+        }
+}
+
+/// Picks snapshots by a `zfs destroy` snapshot spec like `a%c,e`, where
+/// `a%c` is the range from `a` to `c` and either end may be left out.
+/// `snapshots` holds the short names in creation order. Returns the
+/// selected indices and the names that do not exist; items naming one
+/// select nothing.
+fn select_snapshots(snapshots: &[String], spec: &str) -> (BTreeSet<usize>, Vec<String>) {
+    let mut missing = Vec::new();
+    let mut find = |name: &str| match snapshots.iter().position(|s| s == name) {
+        Some(i) => Some(i),
+        None => { missing.push(name.to_owned()); None }
+    };
+    let mut selected = BTreeSet::new();
+    for item in spec.split(',') {
+        match item.find('%') {
+            Some(i) => {
+                let first = if i == 0 { Some(0) } else { find(&item[..i]) };
+                let last = if i == item.len() - 1 {
+                    match snapshots.len() { 0 => continue, n => Some(n - 1) }
+                } else {
+                    find(&item[i + 1..])
+                };
+                if let (Some(first), Some(last)) = (first, last) {
+                    selected.extend(first..last + 1);
+                }
+            },
+            None => { selected.extend(find(item)); }
+        }
+    }
+    (selected, missing)
+}
+
+/// Collects what destroying the selected datasets means, and how much
+/// space it frees.
+struct Targets<'h> {
+    datasets: Vec<Dataset<'h>>,
+    reclaimed: u64,
+}
+
+impl<'h> Targets<'h> {
+    fn plan(&self) -> DestroyPlan {
+        DestroyPlan {
+            datasets: self.datasets.iter().map(Dataset::name).collect(),
+            reclaimed: self.reclaimed,
+        }
+    }
+
+    /// Adds the space of every filesystem or volume whose parent is not
+    /// destroyed as well; their `used` covers everything below them.
+    fn add_used_of_roots(&mut self) -> Result<()> {
+        let names: Vec<String> = self.datasets.iter().map(Dataset::name).collect();
+        for dataset in &self.datasets {
+            match dataset.dataset_type() {
+                Some(DatasetType::Filesystem) | Some(DatasetType::Volume) => (),
+                _ => continue
+            }
+            let name = dataset.name();
+            if names.iter().any(|other| is_descendant(other, &name)) {
+                continue;
+            }
+            self.reclaimed += try!(dataset.get(DatasetProp::Used)).value.as_u64().unwrap_or(0);
+        }
+        Ok(())
+    }
+
+    fn execute(self, handle: &Handle, defer: bool) -> Result<()> {
+        // Snapshots are destroyed in batches, flushed before each
+        // filesystem because it cannot go while it has snapshots.
+        let mut snapshots = List::new(Uniqueness::Name);
+        for dataset in self.datasets {
+            if dataset.dataset_type() == Some(DatasetType::Snapshot) {
+                snapshots.add(Pair::new(dataset.name(), PairValue::Boolean));
+                continue;
+            }
+            try!(destroy_snapshots(handle, &mut snapshots, defer));
+            if dataset.dataset_type() == Some(DatasetType::Filesystem) &&
+                unsafe { zfs_sys::zfs_unmount(dataset.raw, ::std::ptr::null(), 0) } != 0 {
+                return Err(handle.last_error());
+            }
+            if unsafe { zfs_sys::zfs_destroy(dataset.raw, boolean_t::B_FALSE) } != 0 {
+                return Err(handle.last_error());
+            }
+        }
+        destroy_snapshots(handle, &mut snapshots, defer)
+    }
+}
+
+fn destroy_snapshots(handle: &Handle, snapshots: &mut List, defer: bool) -> Result<()> {
+    if snapshots.pairs().is_empty() {
+        return Ok(());
+    }
+    let defer = if defer { boolean_t::B_TRUE } else { boolean_t::B_FALSE };
+    let nvlist = try!(NvList::from_list(snapshots).map_err(Error::Nvpair));
+    *snapshots = List::new(Uniqueness::Name);
+    if unsafe { zfs_sys::zfs_destroy_snaps_nvl(handle.handle, nvlist.as_ptr(), defer) } != 0 {
+        return Err(handle.last_error());
+    }
+    Ok(())
+}
+
+impl<'h> Dataset<'h> {
+    fn destroy_targets(&self, options: &DestroyOptions) -> Result<Targets<'h>> {
+        let name = self.name();
+        let is_snapshot = self.dataset_type() == Some(DatasetType::Snapshot);
+        if options.defer && !is_snapshot {
+            return Err(Error::InvalidArgument("deferred destroy only applies to snapshots".to_owned()));
+        }
+        if is_snapshot && (options.recursive || options.dependents) {
+            let mut parts = name.splitn(2, '@');
+            let parent = try!(self.handle.dataset(parts.next().unwrap()));
+            return parent.snapshot_targets(parts.next().unwrap_or(""), options);
+        }
+
+        // Deferred snapshots are only marked, their clones may stay.
+        let deferred = is_snapshot && options.defer;
+        let mut datasets: Vec<Dataset<'h>> = try!(self.dependents()).collect();
+        if !deferred && !options.dependents {
+            if !options.recursive && !datasets.is_empty() {
+                let names = datasets.iter().map(Dataset::name).collect();
+                return Err(if is_snapshot { Error::HasDependentClones(names) } else { Error::HasChildren(names) });
+            }
+            let clones: Vec<String> = datasets.iter().map(Dataset::name).filter(|n| !is_descendant(&name, n)).collect();
+            if !clones.is_empty() {
+                return Err(Error::HasDependentClones(clones));
+            }
+        }
+        if deferred && !options.dependents {
+            datasets.clear();
+        }
+        datasets.push(try!(self.reopen()));
+
+        let mut targets = Targets { datasets: datasets, reclaimed: 0 };
+        if is_snapshot {
+            targets.reclaimed += try!(self.get(DatasetProp::Used)).value.as_u64().unwrap_or(0);
+        }
+        try!(targets.add_used_of_roots());
+        Ok(targets)
+    }
+
+    fn snapshot_targets(&self, spec: &str, options: &DestroyOptions) -> Result<Targets<'h>> {
+        match self.dataset_type() {
+            Some(DatasetType::Filesystem) | Some(DatasetType::Volume) => (),
+            _ => return Err(Error::InvalidArgument(format!("'{}' is not a filesystem or volume", self.name())))
+        }
+        let mut filesystems = vec![try!(self.reopen())];
+        if options.recursive || options.dependents {
+            filesystems.extend(try!(self.list(&ListOptions::new())).skip(1));
+        }
+
+        let mut targets = Targets { datasets: Vec::new(), reclaimed: 0 };
+        let mut selected_snapshots = Vec::new();
+        for (i, fs) in filesystems.iter().enumerate() {
+            let snapshots = try!(fs.sorted_snapshots());
+            let names: Vec<String> = snapshots.iter()
+                .map(|s| s.name().splitn(2, '@').nth(1).unwrap_or("").to_owned())
+                .collect();
+            let (selected, missing) = select_snapshots(&names, spec);
+            // Like `zfs destroy -r`, descendants need not have them.
+            if i == 0 && !missing.is_empty() {
+                return Err(Error::NotFound(format!("{}@{}", fs.name(), missing[0])));
+            }
+
+            // Destroying adjacent snapshots frees more than their separate
+            // `used`, so ask for the space of every contiguous run.
+            let mut runs: Vec<(usize, usize)> = Vec::new();
+            for &index in &selected {
+                match runs.last_mut() {
+                    Some(run) if run.1 + 1 == index => { run.1 = index; continue; },
+                    _ => ()
+                }
+                runs.push((index, index));
+            }
+            for (first, last) in runs {
+                targets.reclaimed += try!(snaprange_space(&snapshots[first].name(), &snapshots[last].name()));
+            }
+
+            let mut snapshots: Vec<Option<Dataset<'h>>> = snapshots.into_iter().map(Some).collect();
+            for index in selected {
+                selected_snapshots.push(snapshots[index].take().unwrap());
+            }
+        }
+
+        for snapshot in selected_snapshots {
+            let dependents: Vec<Dataset<'h>> = try!(snapshot.dependents()).collect();
+            if !dependents.is_empty() && !options.dependents && !options.defer {
+                return Err(Error::HasDependentClones(dependents.iter().map(Dataset::name).collect()));
+            }
+            if options.dependents {
+                targets.datasets.extend(dependents);
+            }
+            targets.datasets.push(snapshot);
+        }
+        try!(targets.add_used_of_roots());
+        Ok(targets)
+    }
+
+    /// Destroys this dataset, like `zfs destroy`.
+    pub fn destroy(self, options: &DestroyOptions) -> Result<()> {
+        let handle = self.handle;
+        let defer = options.defer;
+        try!(self.destroy_targets(options)).execute(handle, defer)
+    }
+
+    /// Tells what `destroy` would destroy and how much space that frees,
+    /// like `zfs destroy -nv`, without changing anything.
+    pub fn plan_destroy(&self, options: &DestroyOptions) -> Result<DestroyPlan> {
+        Ok(try!(self.destroy_targets(options)).plan())
+    }
+
+    /// Destroys snapshots of this filesystem or volume by a spec like
+    /// `a%c,e`, like `zfs destroy fs@a%c,e`. With `recursive` the snapshots
+    /// of the same names of all descendants are destroyed too.
+    pub fn destroy_snapshots(&self, spec: &str, options: &DestroyOptions) -> Result<()> {
+        try!(self.snapshot_targets(spec, options)).execute(self.handle, options.defer)
+    }
+
+    /// The dry run of `destroy_snapshots`.
+    pub fn plan_destroy_snapshots(&self, spec: &str, options: &DestroyOptions) -> Result<DestroyPlan> {
+        Ok(try!(self.snapshot_targets(spec, options)).plan())
+    }
+}
+
+fn snaprange_space(first: &str, last: &str) -> Result<u64> {
+    let first = try!(to_c_string(first));
+    let last = try!(to_c_string(last));
+    let mut used = 0;
+    match unsafe { zfs_sys::lzc_snaprange_space(first.as_ptr(), last.as_ptr(), &mut used) } {
+        0 => Ok(used),
+        errno => Err(Error::Io(io::Error::from_raw_os_error(errno)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_descendant, select_snapshots};
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn descendants() {
+        assert!(is_descendant("tank/a", "tank/a/b"));
+        assert!(is_descendant("tank/a", "tank/a@snap"));
+        assert!(!is_descendant("tank/a", "tank/ab"));
+        assert!(!is_descendant("tank/a", "tank/a"));
+    }
+
+    #[test]
+    fn snapshot_specs() {
+        let snapshots = names(&["a", "b", "c", "d", "e"]);
+        let select = |spec| {
+            let (selected, missing) = select_snapshots(&snapshots, spec);
+            (selected.into_iter().collect::<Vec<_>>(), missing)
+        };
+        assert_eq!(select("b"), (vec![1], vec![]));
+        assert_eq!(select("b%d"), (vec![1, 2, 3], vec![]));
+        assert_eq!(select("%b,d%"), (vec![0, 1, 3, 4], vec![]));
+        assert_eq!(select("a%b,b%c"), (vec![0, 1, 2], vec![]));
+        assert_eq!(select("d%b"), (vec![], vec![]));
+        assert_eq!(select("a%x"), (vec![], names(&["x"])));
+        assert_eq!(select("x"), (vec![], names(&["x"])));
+        assert_eq!(select("a,x,c%e"), (vec![0, 2, 3, 4], names(&["x"])));
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::raw::c_int;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+use libc;
+use nvpair::{List, NvList, Pair, PairValue, Uniqueness};
+use nvpair::ffi::nvlist_t;
+use zfs_sys;
+use zfs_sys::zfs_error_t;
+
+use super::{Dataset, DatasetType};
+use super::snapshot::lzc_result;
+use super::super::{to_c_string, Error, ErrorKind, Handle, Result};
+
+/// Tags are limited to `MAXNAMELEN` including the terminating nul.
+const MAX_TAG_LEN: usize = 255;
+
+/// An open `/dev/zfs` that holds can be tied to. Holds placed with it are
+/// released when it is closed, also when the process dies.
+#[derive(Debug)]
+pub struct CleanupFd {
+    file: File
+}
+
+impl CleanupFd {
+    pub fn open() -> Result<CleanupFd> {
+        let file = try!(OpenOptions::new().read(true).write(true).custom_flags(libc::O_EXCL)
+                        .open("/dev/zfs").map_err(Error::Io));
+        Ok(CleanupFd { file: file })
+    }
+}
+
+/// The libzfs error for a failed hold or release, like `zfs hold` reports.
+fn reftag_error(errno: i32) -> Option<(zfs_error_t, &'static str)> {
+    match errno {
+        libc::ENOENT => Some((zfs_error_t::EZFS_NOENT, "dataset does not exist")),
+        libc::EEXIST => Some((zfs_error_t::EZFS_REFTAG_HOLD, "tag already exists on this dataset")),
+        libc::E2BIG | libc::ENAMETOOLONG => Some((zfs_error_t::EZFS_TAGTOOLONG, "tag too long")),
+        _ => None
+    }
+}
+
+fn libzfs_error(code: zfs_error_t, action: String, description: &str) -> Error {
+    Error::Libzfs {
+        kind: ErrorKind::from_raw(code as c_int),
+        errno: code as c_int,
+        action: action,
+        description: description.to_owned(),
+    }
+}
+
+/// Turns a batch of per-snapshot errnos into the libzfs error they all
+/// map to. Other errors, and batches that fail for different reasons,
+/// are returned as they are.
+fn hold_result(result: Result<()>, action: &str) -> Result<()> {
+    let failed = match result {
+        Err(Error::Batch(failed)) => failed,
+        other => return other
+    };
+    let mut errors = failed.values().map(|e| e.raw_os_error().and_then(reftag_error));
+    let first = errors.next().and_then(|e| e);
+    match first {
+        Some((code, description)) if errors.all(|e| e.map(|e| e.0) == Some(code)) => {
+            let names: Vec<String> = failed.keys().map(|name| format!("'{}'", name)).collect();
+            Err(libzfs_error(code, format!("{} {}", action, names.join(", ")), description))
+        },
+        _ => Err(Error::Batch(failed))
+    }
+}
+
+fn validate_tag(tag: &str) -> Result<()> {
+    if tag.is_empty() {
+        return Err(Error::InvalidArgument("hold tag is empty".to_owned()));
+    }
+    if tag.len() > MAX_TAG_LEN {
+        return Err(libzfs_error(zfs_error_t::EZFS_TAGTOOLONG, format!("cannot hold '{}'", tag), "tag too long"));
+    }
+    Ok(())
+}
+
+/// Holds snapshots until dropped, like `zfs hold` followed by
+/// `zfs release`. The holds are tied to a `CleanupFd`, so they are also
+/// released if the process dies.
+pub struct HoldGuard<'h> {
+    handle: &'h Handle,
+    snapshots: Vec<String>,
+    tag: String,
+    cleanup: CleanupFd,
+}
+
+impl<'h> HoldGuard<'h> {
+    pub fn snapshots(&self) -> &[String] {
+        &self.snapshots
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Releases the holds now, reporting errors that dropping the guard
+    /// would ignore.
+    pub fn release(mut self) -> Result<()> {
+        self.release_holds()
+    }
+
+    fn release_holds(&mut self) -> Result<()> {
+        let snapshots = mem::replace(&mut self.snapshots, Vec::new());
+        if snapshots.is_empty() {
+            return Ok(());
+        }
+        let snapshots: Vec<&str> = snapshots.iter().map(|s| &**s).collect();
+        self.handle.release(&snapshots, &self.tag)
+    }
+}
+
+impl<'h> fmt::Debug for HoldGuard<'h> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HoldGuard")
+            .field("snapshots", &self.snapshots)
+            .field("tag", &self.tag)
+            .field("cleanup", &self.cleanup)
+            .finish()
+    }
+}
+
+impl<'h> Drop for HoldGuard<'h> {
+    fn drop(&mut self) {
+        // Closing the cleanup fd afterwards releases whatever is left.
+        let _ = self.release_holds();
+    }
+}
+
+impl Handle {
+    /// Atomically places a hold with `tag` on snapshots named like
+    /// `pool/fs@snap`, like `zfs hold`. A held snapshot cannot be
+    /// destroyed. All snapshots must be in the same pool. With a `cleanup`
+    /// fd the holds are released when it is closed.
+    ///
+    /// When every failing snapshot failed for the same reason the error is
+    /// a libzfs error such as `ErrorKind::ReftagHold`, otherwise
+    /// `Error::Batch`. Snapshots that do not exist fail with
+    /// `ErrorKind::NoEnt`, but the kernel still holds the others; use
+    /// `hold_guard` to have them released.
+    pub fn hold(&self, snapshots: &[&str], tag: &str, cleanup: Option<&CleanupFd>) -> Result<()> {
+        try!(validate_tag(tag));
+        let mut holds = List::new(Uniqueness::Name);
+        for name in snapshots {
+            holds.add(Pair::new(*name, PairValue::String(tag.to_owned())));
+        }
+        let holds = try!(NvList::from_list(&holds).map_err(Error::Nvpair));
+        let fd = cleanup.map_or(-1, |cleanup| cleanup.file.as_raw_fd());
+        hold_result(lzc_result(|errors| unsafe { zfs_sys::lzc_hold(holds.as_ptr(), fd, errors) }),
+                    "cannot hold snapshot")
+    }
+
+    /// Like `hold`, but the holds are released when the returned guard
+    /// is dropped, or right away if any snapshot could not be held.
+    pub fn hold_guard(&self, snapshots: &[&str], tag: &str) -> Result<HoldGuard> {
+        let cleanup = try!(CleanupFd::open());
+        try!(self.hold(snapshots, tag, Some(&cleanup)));
+        Ok(HoldGuard {
+            handle: self,
+            snapshots: snapshots.iter().map(|s| s.to_string()).collect(),
+            tag: tag.to_owned(),
+            cleanup: cleanup,
+        })
+    }
+
+    /// Atomically releases the hold with `tag` from snapshots, like
+    /// `zfs release`. Errors are reported like those of `hold`, a missing
+    /// tag as `ErrorKind::ReftagRelease`.
+    pub fn release(&self, snapshots: &[&str], tag: &str) -> Result<()> {
+        try!(validate_tag(tag));
+        // The kernel skips missing tags without failing, so look for them
+        // first like `zfs release` does. Snapshots that cannot be looked
+        // at are left for lzc_release to report.
+        let mut untagged = Vec::new();
+        for name in snapshots {
+            if let Ok(holds) = get_holds(name) {
+                if !holds.contains_key(tag) {
+                    untagged.push(format!("'{}'", name));
+                }
+            }
+        }
+        if !untagged.is_empty() {
+            return Err(libzfs_error(zfs_error_t::EZFS_REFTAG_RELE,
+                                    format!("cannot release hold from snapshot {}", untagged.join(", ")),
+                                    "no such tag on this dataset"));
+        }
+
+        let mut holds = List::new(Uniqueness::Name);
+        for name in snapshots {
+            let mut tags = List::new(Uniqueness::Name);
+            tags.add(Pair::new(tag, PairValue::Boolean));
+            holds.add(Pair::new(*name, PairValue::NvList(tags)));
+        }
+        let holds = try!(NvList::from_list(&holds).map_err(Error::Nvpair));
+        hold_result(lzc_result(|errors| unsafe { zfs_sys::lzc_release(holds.as_ptr(), errors) }),
+                    "cannot release hold from snapshot")
+    }
+}
+
+impl<'h> Dataset<'h> {
+    /// Places a hold with `tag` on this snapshot, like `zfs hold`.
+    pub fn hold(&self, tag: &str) -> Result<()> {
+        self.handle.hold(&[&self.name()], tag, None)
+    }
+
+    /// Releases the hold with `tag` from this snapshot, like `zfs release`.
+    pub fn release(&self, tag: &str) -> Result<()> {
+        self.handle.release(&[&self.name()], tag)
+    }
+
+    /// The holds on this snapshot by tag, with the time each was placed
+    /// in seconds since the epoch, like `zfs holds`.
+    pub fn holds(&self) -> Result<BTreeMap<String, u64>> {
+        let name = self.name();
+        if self.dataset_type() != Some(DatasetType::Snapshot) {
+            return Err(Error::InvalidArgument(format!("'{}' is not a snapshot", name)));
+        }
+        get_holds(&name)
+    }
+}
+
+fn get_holds(snapshot: &str) -> Result<BTreeMap<String, u64>> {
+    let c_name = try!(to_c_string(snapshot));
+    let mut raw: *mut nvlist_t = ptr::null_mut();
+    let rc = unsafe { zfs_sys::lzc_get_holds(c_name.as_ptr(), &mut raw) };
+    if rc != 0 {
+        return Err(Error::Io(io::Error::from_raw_os_error(rc)));
+    }
+    let holds = unsafe { NvList::from_raw(raw) };
+    let mut tags = BTreeMap::new();
+    for pair in holds.pairs() {
+        let tag = try!(pair.name().map_err(Error::Nvpair));
+        if let PairValue::Uint64(time) = try!(pair.value().map_err(Error::Nvpair)) {
+            tags.insert(tag, time);
+        }
+    }
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::io;
+    use libc;
+    use super::{hold_result, validate_tag};
+    use super::super::super::{Error, ErrorKind};
+
+    fn batch(errnos: &[(&str, i32)]) -> super::Result<()> {
+        let failed: BTreeMap<String, io::Error> = errnos.iter()
+            .map(|&(name, errno)| (name.to_owned(), io::Error::from_raw_os_error(errno)))
+            .collect();
+        Err(Error::Batch(failed))
+    }
+
+    #[test]
+    fn reftag_errors() {
+        let err = hold_result(batch(&[("tank@a", libc::EEXIST), ("tank@b", libc::EEXIST)]), "cannot hold snapshot");
+        assert_eq!(err.as_ref().unwrap_err().kind(), Some(ErrorKind::ReftagHold));
+        assert!(err.unwrap_err().to_string().contains("'tank@a', 'tank@b'"));
+
+        let err = hold_result(batch(&[("tank@a", libc::ENOENT)]), "cannot hold snapshot");
+        assert_eq!(err.unwrap_err().kind(), Some(ErrorKind::NoEnt));
+        let err = hold_result(batch(&[("tank@a", libc::E2BIG)]), "cannot hold snapshot");
+        assert_eq!(err.unwrap_err().kind(), Some(ErrorKind::TagTooLong));
+    }
+
+    #[test]
+    fn mixed_errors_stay_a_batch() {
+        match hold_result(batch(&[("tank@a", libc::EEXIST), ("tank@b", libc::EINVAL)]), "cannot hold snapshot") {
+            Err(Error::Batch(failed)) => assert_eq!(failed.len(), 2),
+            other => panic!("unexpected result {:?}", other)
+        }
+        assert!(hold_result(Ok(()), "cannot hold snapshot").is_ok());
+    }
+
+    #[test]
+    fn tags() {
+        assert!(validate_tag("backup").is_ok());
+        assert!(validate_tag("").is_err());
+        let long: String = ::std::iter::repeat('x').take(256).collect();
+        assert_eq!(validate_tag(&long).unwrap_err().kind(), Some(ErrorKind::TagTooLong));
+    }
+}
@@ -0,0 +1,65 @@
+use std::mem;
+
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+
+use super::{Dataset, DestroyOptions};
+use super::super::{to_c_string, Error, Result};
+
+fn to_boolean(b: bool) -> boolean_t {
+    if b { boolean_t::B_TRUE } else { boolean_t::B_FALSE }
+}
+
+impl<'h> Dataset<'h> {
+    /// Rolls this filesystem or volume back to one of its snapshots, like
+    /// `zfs rollback`. Snapshots newer than `snapshot` are destroyed if
+    /// `destroy_newer` is set, like `-r`, and otherwise fail the rollback
+    /// with `Error::NewerSnapshots`.
+    pub fn rollback(&self, snapshot: &Dataset, destroy_newer: bool) -> Result<()> {
+        let name = self.name();
+        let snapshot_name = snapshot.name();
+        if !snapshot_name.starts_with(&format!("{}@", name)) {
+            return Err(Error::InvalidArgument(format!("'{}' is not a snapshot of '{}'", snapshot_name, name)));
+        }
+        let newer: Vec<String> = try!(self.sorted_snapshots()).iter()
+            .map(Dataset::name)
+            .skip_while(|n| *n != snapshot_name)
+            .skip(1)
+            .collect();
+        if let Some(first) = newer.first() {
+            if !destroy_newer {
+                return Err(Error::NewerSnapshots(newer.clone()));
+            }
+            try!(self.destroy_snapshots(&format!("{}%", &first[name.len() + 1..]), &DestroyOptions::new()));
+        }
+        if unsafe { zfs_sys::zfs_rollback(self.raw, snapshot.raw, boolean_t::B_FALSE) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        Ok(())
+    }
+
+    /// Renames this dataset, like `zfs rename`. With `recursive` a snapshot
+    /// is renamed in all descendants too, like `zfs rename -r fs@a fs@b`.
+    /// `force_unmount` unmounts busy filesystems, like `-f`. Afterwards
+    /// this handle refers to the dataset by its new name.
+    pub fn rename(&mut self, new_name: &str, recursive: bool, force_unmount: bool) -> Result<()> {
+        let c_name = try!(to_c_string(new_name));
+        let rc = unsafe { zfs_sys::zfs_rename(self.raw, c_name.as_ptr(), to_boolean(recursive), to_boolean(force_unmount)) };
+        if rc != 0 {
+            return Err(self.handle.last_error());
+        }
+        let mut renamed = try!(self.handle.dataset(new_name));
+        mem::swap(self, &mut renamed);
+        Ok(())
+    }
+
+    /// Promotes this clone so it no longer depends on its origin snapshot,
+    /// like `zfs promote`. The origin filesystem becomes a clone of this
+    /// one instead.
+    pub fn promote(&self) -> Result<()> {
+        if unsafe { zfs_sys::zfs_promote(self.raw) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::vec;
+
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+use zfs_sys::{zfs_handle_t, zfs_iter_f, zfs_type_t};
+
+use super::{c_string_lossy, to_c_string, Handle, Result};
+
+mod property;
+pub use self::property::{validate_user_property_name, DatasetProp};
+mod create;
+pub use self::create::{DatasetProps, Encryption, KeyFormat};
+mod destroy;
+pub use self::destroy::{DestroyOptions, DestroyPlan};
+mod snapshot;
+mod bookmark;
+pub use self::bookmark::Bookmark;
+mod hold;
+pub use self::hold::{CleanupFd, HoldGuard};
+mod lifecycle;
+mod send;
+pub use self::send::SendOptions;
+mod receive;
+pub use self::receive::ReceiveOptions;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum DatasetType {
+    Filesystem,
+    Volume,
+    Snapshot,
+    Bookmark,
+}
+
+impl DatasetType {
+    pub fn to_raw(&self) -> zfs_type_t {
+        match *self {
+            DatasetType::Filesystem => zfs_sys::ZFS_TYPE_FILESYSTEM,
+            DatasetType::Volume => zfs_sys::ZFS_TYPE_VOLUME,
+            DatasetType::Snapshot => zfs_sys::ZFS_TYPE_SNAPSHOT,
+            DatasetType::Bookmark => zfs_sys::ZFS_TYPE_BOOKMARK,
+        }
+    }
+
+    fn from_raw(t: zfs_type_t) -> Option<DatasetType> {
+        match t {
+            zfs_sys::ZFS_TYPE_FILESYSTEM => Some(DatasetType::Filesystem),
+            zfs_sys::ZFS_TYPE_VOLUME => Some(DatasetType::Volume),
+            zfs_sys::ZFS_TYPE_SNAPSHOT => Some(DatasetType::Snapshot),
+            zfs_sys::ZFS_TYPE_BOOKMARK => Some(DatasetType::Bookmark),
+            _ => None
+        }
+    }
+}
+
+/// What to list, like the `-d` and `-t` options of `zfs list`.
+#[derive(Debug,Clone)]
+pub struct ListOptions {
+    depth: Option<usize>,
+    types: Vec<DatasetType>,
+}
+
+impl ListOptions {
+    /// Lists filesystems and volumes recursively, like `zfs list -r`.
+    pub fn new() -> ListOptions {
+        ListOptions {
+            depth: None,
+            types: vec![DatasetType::Filesystem, DatasetType::Volume],
+        }
+    }
+
+    /// Descends at most `depth` levels below the starting datasets.
+    /// Snapshots and bookmarks are one level below their dataset.
+    pub fn depth(mut self, depth: usize) -> ListOptions {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn types(mut self, types: &[DatasetType]) -> ListOptions {
+        self.types = types.to_vec();
+        self
+    }
+
+    fn wants(&self, t: DatasetType) -> bool {
+        self.types.contains(&t)
+    }
+}
+
+impl Default for ListOptions {
+    fn default() -> ListOptions {
+        ListOptions::new()
+    }
+}
+
+/// Iterates over datasets collected by one of the listing functions.
+pub struct DatasetIterator<'h> {
+    datasets: vec::IntoIter<Dataset<'h>>
+}
+
+impl<'h> Iterator for DatasetIterator<'h> {
+    type Item = Dataset<'h>;
+
+    fn next(&mut self) -> Option<Dataset<'h>> {
+        self.datasets.next()
+    }
+}
+
+/// An open libzfs dataset handle, closed when dropped.
+pub struct Dataset<'h> {
+    handle: &'h Handle,
+    raw: *mut zfs_handle_t
+}
+
+extern "C" fn collect_dataset(zhp: *mut zfs_handle_t, data: *mut c_void) -> c_int {
+    let datasets = unsafe { &mut *(data as *mut Vec<*mut zfs_handle_t>) };
+    datasets.push(zhp);
+    0
+}
+
+/// Runs one of the `zfs_iter_*` functions and takes ownership of every
+/// handle it passes to the callback.
+fn collect<'h, F>(handle: &'h Handle, iter: F) -> Result<Vec<Dataset<'h>>>
+    where F: FnOnce(zfs_iter_f, *mut c_void) -> c_int
+{
+    let mut raw: Vec<*mut zfs_handle_t> = Vec::new();
+    let rc = iter(collect_dataset, &mut raw as *mut _ as *mut c_void);
+    let datasets = raw.into_iter().map(|raw| Dataset { handle: handle, raw: raw }).collect();
+    if rc != 0 {
+        return Err(handle.last_error());
+    }
+    Ok(datasets)
+}
+
+fn walk<'h>(dataset: Dataset<'h>, depth: Option<usize>, options: &ListOptions, out: &mut Vec<Dataset<'h>>) -> Result<()> {
+    let descend = depth != Some(0) && match dataset.dataset_type() {
+        Some(DatasetType::Filesystem) | Some(DatasetType::Volume) => true,
+        _ => false
+    };
+    let mut below = Vec::new();
+    if descend {
+        if options.wants(DatasetType::Snapshot) {
+            below.extend(try!(dataset.snapshots()));
+        }
+        if options.wants(DatasetType::Bookmark) {
+            below.extend(try!(collect(dataset.handle, |f, data| unsafe {
+                zfs_sys::zfs_iter_bookmarks(dataset.raw, f, data)
+            })));
+        }
+        below.extend(try!(collect(dataset.handle, |f, data| unsafe {
+            zfs_sys::zfs_iter_filesystems(dataset.raw, f, data)
+        })));
+    }
+    if dataset.dataset_type().map_or(false, |t| options.wants(t)) {
+        out.push(dataset);
+    }
+    for child in below {
+        try!(walk(child, depth.map(|d| d - 1), options, out));
+    }
+    Ok(())
+}
+
+impl Handle {
+    /// The root dataset of every imported pool.
+    pub fn datasets(&self) -> Result<DatasetIterator> {
+        let datasets = try!(collect(self, |f, data| unsafe { zfs_sys::zfs_iter_root(self.handle, f, data) }));
+        Ok(DatasetIterator { datasets: datasets.into_iter() })
+    }
+
+    /// Lists datasets of all pools, like `zfs list` without arguments.
+    /// Parents are listed before their children.
+    pub fn list(&self, options: &ListOptions) -> Result<DatasetIterator> {
+        let mut out = Vec::new();
+        for root in try!(collect(self, |f, data| unsafe { zfs_sys::zfs_iter_root(self.handle, f, data) })) {
+            try!(walk(root, options.depth, options, &mut out));
+        }
+        Ok(DatasetIterator { datasets: out.into_iter() })
+    }
+
+    /// Opens a filesystem, volume or snapshot by name.
+    pub fn dataset(&self, name: &str) -> Result<Dataset> {
+        let c_name = try!(to_c_string(name));
+        let raw = unsafe { zfs_sys::zfs_open(self.handle, c_name.as_ptr(), zfs_sys::ZFS_TYPE_DATASET) };
+        if raw.is_null() {
+            return Err(self.last_error());
+        }
+        Ok(Dataset { handle: self, raw: raw })
+    }
+}
+
+impl<'h> Dataset<'h> {
+    pub fn name(&self) -> String {
+        c_string_lossy(unsafe { zfs_sys::zfs_get_name(self.raw) })
+    }
+
+    pub fn dataset_type(&self) -> Option<DatasetType> {
+        DatasetType::from_raw(unsafe { zfs_sys::zfs_get_type(self.raw) })
+    }
+
+    pub fn handle(&self) -> &'h Handle {
+        self.handle
+    }
+
+    pub fn as_ptr(&self) -> *mut zfs_handle_t {
+        self.raw
+    }
+
+    /// The direct child filesystems and volumes, followed by the snapshots.
+    pub fn children(&self) -> Result<DatasetIterator<'h>> {
+        let datasets = try!(collect(self.handle, |f, data| unsafe { zfs_sys::zfs_iter_children(self.raw, f, data) }));
+        Ok(DatasetIterator { datasets: datasets.into_iter() })
+    }
+
+    pub fn snapshots(&self) -> Result<DatasetIterator<'h>> {
+        let datasets = try!(collect(self.handle, |f, data| unsafe {
+            zfs_sys::zfs_iter_snapshots(self.raw, boolean_t::B_FALSE, f, data)
+        }));
+        Ok(DatasetIterator { datasets: datasets.into_iter() })
+    }
+
+    /// Everything that depends on this dataset: descendants, snapshots and
+    /// clones of those snapshots. Dependents come before what they depend
+    /// on, the order `zfs destroy -R` uses.
+    pub fn dependents(&self) -> Result<DatasetIterator<'h>> {
+        let datasets = try!(collect(self.handle, |f, data| unsafe {
+            zfs_sys::zfs_iter_dependents(self.raw, boolean_t::B_FALSE, f, data)
+        }));
+        Ok(DatasetIterator { datasets: datasets.into_iter() })
+    }
+
+    /// Lists this dataset and the datasets below it, like
+    /// `zfs list -r <name>`.
+    pub fn list(&self, options: &ListOptions) -> Result<DatasetIterator<'h>> {
+        let mut out = Vec::new();
+        let this = try!(self.reopen());
+        try!(walk(this, options.depth, options, &mut out));
+        Ok(DatasetIterator { datasets: out.into_iter() })
+    }
+
+    /// A second handle to this dataset.
+    fn reopen(&self) -> Result<Dataset<'h>> {
+        self.handle.dataset(&self.name())
+    }
+
+    /// The snapshots of this filesystem or volume, oldest first.
+    fn sorted_snapshots(&self) -> Result<Vec<Dataset<'h>>> {
+        collect(self.handle, |f, data| unsafe { zfs_sys::zfs_iter_snapshots_sorted(self.raw, f, data) })
+    }
+}
+
+impl<'h> fmt::Debug for Dataset<'h> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dataset")
+            .field("name", &self.name())
+            .field("type", &self.dataset_type())
+            .finish()
+    }
+}
+
+impl<'h> Drop for Dataset<'h> {
+    fn drop(&mut self) {
+        unsafe { zfs_sys::zfs_close(self.raw) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DatasetType, ListOptions};
+
+    #[test]
+    fn type_raw_roundtrip() {
+        for t in &[DatasetType::Filesystem, DatasetType::Volume, DatasetType::Snapshot, DatasetType::Bookmark] {
+            assert_eq!(DatasetType::from_raw(t.to_raw()), Some(*t));
+        }
+        assert_eq!(DatasetType::from_raw(::zfs_sys::ZFS_TYPE_POOL), None);
+    }
+
+    #[test]
+    fn list_options_default_to_filesystems_and_volumes() {
+        let options = ListOptions::new();
+        assert!(options.wants(DatasetType::Filesystem));
+        assert!(options.wants(DatasetType::Volume));
+        assert!(!options.wants(DatasetType::Snapshot));
+        assert_eq!(options.depth, None);
+    }
+}
@@ -0,0 +1,593 @@
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+
+use nvpair::{List, NvListRef, Pair, PairValue, Uniqueness, NvList};
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+use zfs_sys::{zfs_prop_t, zprop_source_t};
+
+use super::Dataset;
+use super::super::{c_string_lossy, to_c_string, Error, PropSource, PropValue, Property, Result, UserPropError};
+use super::super::property::PropKind;
+
+/// The native dataset properties, mirroring the user visible part of
+/// `zfs_prop_t`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum DatasetProp {
+    Type,
+    Creation,
+    Used,
+    Available,
+    Referenced,
+    Compressratio,
+    Mounted,
+    Origin,
+    Quota,
+    Reservation,
+    Volsize,
+    Volblocksize,
+    Recordsize,
+    Mountpoint,
+    Sharenfs,
+    Checksum,
+    Compression,
+    Atime,
+    Devices,
+    Exec,
+    Setuid,
+    Readonly,
+    Zoned,
+    Snapdir,
+    Aclinherit,
+    Createtxg,
+    Name,
+    Canmount,
+    Xattr,
+    Copies,
+    Version,
+    Utf8only,
+    Normalization,
+    Casesensitivity,
+    Vscan,
+    Nbmand,
+    Sharesmb,
+    Refquota,
+    Refreservation,
+    Guid,
+    Primarycache,
+    Secondarycache,
+    Usedbysnapshots,
+    Usedbydataset,
+    Usedbychildren,
+    Usedbyrefreservation,
+    DeferDestroy,
+    Userrefs,
+    Logbias,
+    Objsetid,
+    Dedup,
+    Mlslabel,
+    Sync,
+    Dnodesize,
+    Refcompressratio,
+    Written,
+    Clones,
+    Logicalused,
+    Logicalreferenced,
+    Volmode,
+    FilesystemLimit,
+    SnapshotLimit,
+    FilesystemCount,
+    SnapshotCount,
+    Snapdev,
+    Acltype,
+    Context,
+    Fscontext,
+    Defcontext,
+    Rootcontext,
+    Relatime,
+    RedundantMetadata,
+    Overlay,
+    ReceiveResumeToken,
+}
+
+const ALL: [DatasetProp; 74] = [
+    DatasetProp::Type, DatasetProp::Creation, DatasetProp::Used, DatasetProp::Available,
+    DatasetProp::Referenced, DatasetProp::Compressratio, DatasetProp::Mounted, DatasetProp::Origin,
+    DatasetProp::Quota, DatasetProp::Reservation, DatasetProp::Volsize, DatasetProp::Volblocksize,
+    DatasetProp::Recordsize, DatasetProp::Mountpoint, DatasetProp::Sharenfs, DatasetProp::Checksum,
+    DatasetProp::Compression, DatasetProp::Atime, DatasetProp::Devices, DatasetProp::Exec,
+    DatasetProp::Setuid, DatasetProp::Readonly, DatasetProp::Zoned, DatasetProp::Snapdir,
+    DatasetProp::Aclinherit, DatasetProp::Createtxg, DatasetProp::Name, DatasetProp::Canmount,
+    DatasetProp::Xattr, DatasetProp::Copies, DatasetProp::Version, DatasetProp::Utf8only,
+    DatasetProp::Normalization, DatasetProp::Casesensitivity, DatasetProp::Vscan,
+    DatasetProp::Nbmand, DatasetProp::Sharesmb, DatasetProp::Refquota, DatasetProp::Refreservation,
+    DatasetProp::Guid, DatasetProp::Primarycache, DatasetProp::Secondarycache,
+    DatasetProp::Usedbysnapshots, DatasetProp::Usedbydataset, DatasetProp::Usedbychildren,
+    DatasetProp::Usedbyrefreservation, DatasetProp::DeferDestroy, DatasetProp::Userrefs,
+    DatasetProp::Logbias, DatasetProp::Objsetid, DatasetProp::Dedup, DatasetProp::Mlslabel,
+    DatasetProp::Sync, DatasetProp::Dnodesize, DatasetProp::Refcompressratio, DatasetProp::Written,
+    DatasetProp::Clones, DatasetProp::Logicalused, DatasetProp::Logicalreferenced,
+    DatasetProp::Volmode, DatasetProp::FilesystemLimit, DatasetProp::SnapshotLimit,
+    DatasetProp::FilesystemCount, DatasetProp::SnapshotCount, DatasetProp::Snapdev,
+    DatasetProp::Acltype, DatasetProp::Context, DatasetProp::Fscontext, DatasetProp::Defcontext,
+    DatasetProp::Rootcontext, DatasetProp::Relatime, DatasetProp::RedundantMetadata,
+    DatasetProp::Overlay, DatasetProp::ReceiveResumeToken,
+];
+
+impl DatasetProp {
+    pub fn all() -> &'static [DatasetProp] {
+        &ALL
+    }
+
+    /// The name used by `zfs get`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DatasetProp::Type => "type",
+            DatasetProp::Creation => "creation",
+            DatasetProp::Used => "used",
+            DatasetProp::Available => "available",
+            DatasetProp::Referenced => "referenced",
+            DatasetProp::Compressratio => "compressratio",
+            DatasetProp::Mounted => "mounted",
+            DatasetProp::Origin => "origin",
+            DatasetProp::Quota => "quota",
+            DatasetProp::Reservation => "reservation",
+            DatasetProp::Volsize => "volsize",
+            DatasetProp::Volblocksize => "volblocksize",
+            DatasetProp::Recordsize => "recordsize",
+            DatasetProp::Mountpoint => "mountpoint",
+            DatasetProp::Sharenfs => "sharenfs",
+            DatasetProp::Checksum => "checksum",
+            DatasetProp::Compression => "compression",
+            DatasetProp::Atime => "atime",
+            DatasetProp::Devices => "devices",
+            DatasetProp::Exec => "exec",
+            DatasetProp::Setuid => "setuid",
+            DatasetProp::Readonly => "readonly",
+            DatasetProp::Zoned => "zoned",
+            DatasetProp::Snapdir => "snapdir",
+            DatasetProp::Aclinherit => "aclinherit",
+            DatasetProp::Createtxg => "createtxg",
+            DatasetProp::Name => "name",
+            DatasetProp::Canmount => "canmount",
+            DatasetProp::Xattr => "xattr",
+            DatasetProp::Copies => "copies",
+            DatasetProp::Version => "version",
+            DatasetProp::Utf8only => "utf8only",
+            DatasetProp::Normalization => "normalization",
+            DatasetProp::Casesensitivity => "casesensitivity",
+            DatasetProp::Vscan => "vscan",
+            DatasetProp::Nbmand => "nbmand",
+            DatasetProp::Sharesmb => "sharesmb",
+            DatasetProp::Refquota => "refquota",
+            DatasetProp::Refreservation => "refreservation",
+            DatasetProp::Guid => "guid",
+            DatasetProp::Primarycache => "primarycache",
+            DatasetProp::Secondarycache => "secondarycache",
+            DatasetProp::Usedbysnapshots => "usedbysnapshots",
+            DatasetProp::Usedbydataset => "usedbydataset",
+            DatasetProp::Usedbychildren => "usedbychildren",
+            DatasetProp::Usedbyrefreservation => "usedbyrefreservation",
+            DatasetProp::DeferDestroy => "defer_destroy",
+            DatasetProp::Userrefs => "userrefs",
+            DatasetProp::Logbias => "logbias",
+            DatasetProp::Objsetid => "objsetid",
+            DatasetProp::Dedup => "dedup",
+            DatasetProp::Mlslabel => "mlslabel",
+            DatasetProp::Sync => "sync",
+            DatasetProp::Dnodesize => "dnodesize",
+            DatasetProp::Refcompressratio => "refcompressratio",
+            DatasetProp::Written => "written",
+            DatasetProp::Clones => "clones",
+            DatasetProp::Logicalused => "logicalused",
+            DatasetProp::Logicalreferenced => "logicalreferenced",
+            DatasetProp::Volmode => "volmode",
+            DatasetProp::FilesystemLimit => "filesystem_limit",
+            DatasetProp::SnapshotLimit => "snapshot_limit",
+            DatasetProp::FilesystemCount => "filesystem_count",
+            DatasetProp::SnapshotCount => "snapshot_count",
+            DatasetProp::Snapdev => "snapdev",
+            DatasetProp::Acltype => "acltype",
+            DatasetProp::Context => "context",
+            DatasetProp::Fscontext => "fscontext",
+            DatasetProp::Defcontext => "defcontext",
+            DatasetProp::Rootcontext => "rootcontext",
+            DatasetProp::Relatime => "relatime",
+            DatasetProp::RedundantMetadata => "redundant_metadata",
+            DatasetProp::Overlay => "overlay",
+            DatasetProp::ReceiveResumeToken => "receive_resume_token",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DatasetProp> {
+        ALL.iter().find(|p| p.name() == name).cloned()
+    }
+
+    pub fn to_raw(&self) -> zfs_prop_t {
+        use zfs_sys::zfs_prop_t::*;
+        match *self {
+            DatasetProp::Type => ZFS_PROP_TYPE,
+            DatasetProp::Creation => ZFS_PROP_CREATION,
+            DatasetProp::Used => ZFS_PROP_USED,
+            DatasetProp::Available => ZFS_PROP_AVAILABLE,
+            DatasetProp::Referenced => ZFS_PROP_REFERENCED,
+            DatasetProp::Compressratio => ZFS_PROP_COMPRESSRATIO,
+            DatasetProp::Mounted => ZFS_PROP_MOUNTED,
+            DatasetProp::Origin => ZFS_PROP_ORIGIN,
+            DatasetProp::Quota => ZFS_PROP_QUOTA,
+            DatasetProp::Reservation => ZFS_PROP_RESERVATION,
+            DatasetProp::Volsize => ZFS_PROP_VOLSIZE,
+            DatasetProp::Volblocksize => ZFS_PROP_VOLBLOCKSIZE,
+            DatasetProp::Recordsize => ZFS_PROP_RECORDSIZE,
+            DatasetProp::Mountpoint => ZFS_PROP_MOUNTPOINT,
+            DatasetProp::Sharenfs => ZFS_PROP_SHARENFS,
+            DatasetProp::Checksum => ZFS_PROP_CHECKSUM,
+            DatasetProp::Compression => ZFS_PROP_COMPRESSION,
+            DatasetProp::Atime => ZFS_PROP_ATIME,
+            DatasetProp::Devices => ZFS_PROP_DEVICES,
+            DatasetProp::Exec => ZFS_PROP_EXEC,
+            DatasetProp::Setuid => ZFS_PROP_SETUID,
+            DatasetProp::Readonly => ZFS_PROP_READONLY,
+            DatasetProp::Zoned => ZFS_PROP_ZONED,
+            DatasetProp::Snapdir => ZFS_PROP_SNAPDIR,
+            DatasetProp::Aclinherit => ZFS_PROP_ACLINHERIT,
+            DatasetProp::Createtxg => ZFS_PROP_CREATETXG,
+            DatasetProp::Name => ZFS_PROP_NAME,
+            DatasetProp::Canmount => ZFS_PROP_CANMOUNT,
+            DatasetProp::Xattr => ZFS_PROP_XATTR,
+            DatasetProp::Copies => ZFS_PROP_COPIES,
+            DatasetProp::Version => ZFS_PROP_VERSION,
+            DatasetProp::Utf8only => ZFS_PROP_UTF8ONLY,
+            DatasetProp::Normalization => ZFS_PROP_NORMALIZE,
+            DatasetProp::Casesensitivity => ZFS_PROP_CASE,
+            DatasetProp::Vscan => ZFS_PROP_VSCAN,
+            DatasetProp::Nbmand => ZFS_PROP_NBMAND,
+            DatasetProp::Sharesmb => ZFS_PROP_SHARESMB,
+            DatasetProp::Refquota => ZFS_PROP_REFQUOTA,
+            DatasetProp::Refreservation => ZFS_PROP_REFRESERVATION,
+            DatasetProp::Guid => ZFS_PROP_GUID,
+            DatasetProp::Primarycache => ZFS_PROP_PRIMARYCACHE,
+            DatasetProp::Secondarycache => ZFS_PROP_SECONDARYCACHE,
+            DatasetProp::Usedbysnapshots => ZFS_PROP_USEDSNAP,
+            DatasetProp::Usedbydataset => ZFS_PROP_USEDDS,
+            DatasetProp::Usedbychildren => ZFS_PROP_USEDCHILD,
+            DatasetProp::Usedbyrefreservation => ZFS_PROP_USEDREFRESERV,
+            DatasetProp::DeferDestroy => ZFS_PROP_DEFER_DESTROY,
+            DatasetProp::Userrefs => ZFS_PROP_USERREFS,
+            DatasetProp::Logbias => ZFS_PROP_LOGBIAS,
+            DatasetProp::Objsetid => ZFS_PROP_OBJSETID,
+            DatasetProp::Dedup => ZFS_PROP_DEDUP,
+            DatasetProp::Mlslabel => ZFS_PROP_MLSLABEL,
+            DatasetProp::Sync => ZFS_PROP_SYNC,
+            DatasetProp::Dnodesize => ZFS_PROP_DNODESIZE,
+            DatasetProp::Refcompressratio => ZFS_PROP_REFRATIO,
+            DatasetProp::Written => ZFS_PROP_WRITTEN,
+            DatasetProp::Clones => ZFS_PROP_CLONES,
+            DatasetProp::Logicalused => ZFS_PROP_LOGICALUSED,
+            DatasetProp::Logicalreferenced => ZFS_PROP_LOGICALREFERENCED,
+            DatasetProp::Volmode => ZFS_PROP_VOLMODE,
+            DatasetProp::FilesystemLimit => ZFS_PROP_FILESYSTEM_LIMIT,
+            DatasetProp::SnapshotLimit => ZFS_PROP_SNAPSHOT_LIMIT,
+            DatasetProp::FilesystemCount => ZFS_PROP_FILESYSTEM_COUNT,
+            DatasetProp::SnapshotCount => ZFS_PROP_SNAPSHOT_COUNT,
+            DatasetProp::Snapdev => ZFS_PROP_SNAPDEV,
+            DatasetProp::Acltype => ZFS_PROP_ACLTYPE,
+            DatasetProp::Context => ZFS_PROP_SELINUX_CONTEXT,
+            DatasetProp::Fscontext => ZFS_PROP_SELINUX_FSCONTEXT,
+            DatasetProp::Defcontext => ZFS_PROP_SELINUX_DEFCONTEXT,
+            DatasetProp::Rootcontext => ZFS_PROP_SELINUX_ROOTCONTEXT,
+            DatasetProp::Relatime => ZFS_PROP_RELATIME,
+            DatasetProp::RedundantMetadata => ZFS_PROP_REDUNDANT_METADATA,
+            DatasetProp::Overlay => ZFS_PROP_OVERLAY,
+            DatasetProp::ReceiveResumeToken => ZFS_PROP_RECEIVE_RESUME_TOKEN,
+        }
+    }
+
+    fn kind(&self) -> PropKind {
+        match *self {
+            DatasetProp::Used | DatasetProp::Available | DatasetProp::Referenced | DatasetProp::Quota |
+            DatasetProp::Reservation | DatasetProp::Volsize | DatasetProp::Volblocksize |
+            DatasetProp::Recordsize | DatasetProp::Refquota | DatasetProp::Refreservation |
+            DatasetProp::Usedbysnapshots | DatasetProp::Usedbydataset | DatasetProp::Usedbychildren |
+            DatasetProp::Usedbyrefreservation | DatasetProp::Written | DatasetProp::Logicalused |
+            DatasetProp::Logicalreferenced => PropKind::Bytes,
+            DatasetProp::Compressratio | DatasetProp::Refcompressratio => PropKind::Ratio,
+            DatasetProp::Mounted | DatasetProp::Atime | DatasetProp::Devices | DatasetProp::Exec |
+            DatasetProp::Setuid | DatasetProp::Readonly | DatasetProp::Zoned | DatasetProp::Utf8only |
+            DatasetProp::Vscan | DatasetProp::Nbmand | DatasetProp::DeferDestroy |
+            DatasetProp::Relatime | DatasetProp::Overlay => PropKind::Bool,
+            DatasetProp::Snapdir | DatasetProp::Aclinherit | DatasetProp::Canmount |
+            DatasetProp::Xattr | DatasetProp::Normalization | DatasetProp::Casesensitivity |
+            DatasetProp::Primarycache | DatasetProp::Secondarycache | DatasetProp::Logbias |
+            DatasetProp::Dedup | DatasetProp::Sync | DatasetProp::Dnodesize | DatasetProp::Volmode |
+            DatasetProp::Snapdev | DatasetProp::Acltype |
+            DatasetProp::RedundantMetadata => PropKind::Index,
+            DatasetProp::Compression => PropKind::Compression,
+            DatasetProp::Checksum => PropKind::Checksum,
+            DatasetProp::Type | DatasetProp::Origin | DatasetProp::Mountpoint | DatasetProp::Sharenfs |
+            DatasetProp::Name | DatasetProp::Sharesmb | DatasetProp::Mlslabel | DatasetProp::Clones |
+            DatasetProp::Context | DatasetProp::Fscontext | DatasetProp::Defcontext |
+            DatasetProp::Rootcontext | DatasetProp::ReceiveResumeToken => PropKind::String,
+            _ => PropKind::Number,
+        }
+    }
+
+    /// Whether the property has no value on some datasets it applies to,
+    /// like `origin` on a filesystem that is not a clone.
+    fn may_be_unset(&self) -> bool {
+        match *self {
+            DatasetProp::Origin | DatasetProp::Clones | DatasetProp::ReceiveResumeToken => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the property can only be read, like `zfs_prop_readonly()`.
+    pub fn is_readonly(&self) -> bool {
+        match *self {
+            DatasetProp::Type | DatasetProp::Creation | DatasetProp::Used | DatasetProp::Available |
+            DatasetProp::Referenced | DatasetProp::Compressratio | DatasetProp::Mounted |
+            DatasetProp::Origin | DatasetProp::Createtxg | DatasetProp::Name | DatasetProp::Guid |
+            DatasetProp::Usedbysnapshots | DatasetProp::Usedbydataset | DatasetProp::Usedbychildren |
+            DatasetProp::Usedbyrefreservation | DatasetProp::DeferDestroy | DatasetProp::Userrefs |
+            DatasetProp::Objsetid | DatasetProp::Refcompressratio | DatasetProp::Written |
+            DatasetProp::Clones | DatasetProp::Logicalused | DatasetProp::Logicalreferenced |
+            DatasetProp::FilesystemCount | DatasetProp::SnapshotCount |
+            DatasetProp::ReceiveResumeToken => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the property can only be set when the dataset is created,
+    /// like `zfs_prop_setonce()`.
+    pub fn is_setonce(&self) -> bool {
+        match *self {
+            DatasetProp::Volblocksize | DatasetProp::Utf8only | DatasetProp::Normalization |
+            DatasetProp::Casesensitivity => true,
+            _ => false,
+        }
+    }
+
+    /// Whether a value set on a dataset is passed on to its children, so
+    /// that `zfs inherit` can clear it.
+    pub fn is_inheritable(&self) -> bool {
+        match *self {
+            DatasetProp::Quota | DatasetProp::Reservation | DatasetProp::Volsize |
+            DatasetProp::Refquota | DatasetProp::Refreservation | DatasetProp::FilesystemLimit |
+            DatasetProp::SnapshotLimit | DatasetProp::Version | DatasetProp::Canmount => false,
+            _ => !self.is_readonly() && !self.is_setonce(),
+        }
+    }
+}
+
+// From zfs_prop_user() and zfs_valid_proplist().
+const ZAP_MAXNAMELEN: usize = 256;
+
+/// Checks a user property name against the rules libzfs enforces.
+pub fn validate_user_property_name(name: &str) -> Result<()> {
+    let invalid = |reason| Err(Error::InvalidUserProperty(name.to_owned(), reason));
+    if name.len() >= ZAP_MAXNAMELEN {
+        return invalid(UserPropError::NameTooLong);
+    }
+    if name.starts_with('-') {
+        return invalid(UserPropError::LeadingDash);
+    }
+    if let Some(c) = name.chars().find(|&c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || ":-._".contains(c))) {
+        return invalid(UserPropError::InvalidChar(c));
+    }
+    if !name.contains(':') {
+        return invalid(UserPropError::MissingColon);
+    }
+    Ok(())
+}
+
+pub fn validate_user_property(name: &str, value: &str) -> Result<()> {
+    try!(validate_user_property_name(name));
+    if value.len() >= zfs_sys::ZFS_MAXPROPLEN {
+        return Err(Error::InvalidUserProperty(name.to_owned(), UserPropError::ValueTooLong));
+    }
+    Ok(())
+}
+
+impl<'h> Dataset<'h> {
+    /// Reads a native property with its source. Fails with
+    /// `Error::UnavailableProperty` if it does not apply to this dataset,
+    /// e.g. `volsize` on a filesystem, or has no value, e.g. `origin` of a
+    /// filesystem that is not a clone.
+    pub fn get(&self, prop: DatasetProp) -> Result<Property> {
+        let applies = unsafe {
+            zfs_sys::zfs_prop_valid_for_type(prop.to_raw(), zfs_sys::zfs_get_type(self.raw), boolean_t::B_FALSE)
+        };
+        if applies == boolean_t::B_FALSE {
+            return Err(Error::UnavailableProperty(prop.name().to_owned()));
+        }
+
+        let mut source = zprop_source_t::ZPROP_SRC_NONE;
+        let mut buf = [0 as c_char; zfs_sys::ZFS_MAXPROPLEN];
+        let mut statbuf = [0 as c_char; zfs_sys::ZFS_MAXPROPLEN];
+        let rc = unsafe {
+            zfs_sys::zfs_prop_get(self.raw, prop.to_raw(), buf.as_mut_ptr(), buf.len(), &mut source,
+                                  statbuf.as_mut_ptr(), statbuf.len(), boolean_t::B_TRUE)
+        };
+        if rc != 0 {
+            return Err(self.read_error(prop));
+        }
+
+        let kind = prop.kind();
+        let value = if kind.is_numeric() {
+            let mut n = 0;
+            let mut numeric_source = zprop_source_t::ZPROP_SRC_NONE;
+            let rc = unsafe {
+                zfs_sys::zfs_prop_get_numeric(self.raw, prop.to_raw(), &mut n, &mut numeric_source,
+                                              ::std::ptr::null_mut(), 0)
+            };
+            if rc != 0 {
+                return Err(self.read_error(prop));
+            }
+            kind.from_numeric(n)
+        } else {
+            kind.from_string(c_string_lossy(buf.as_ptr()))
+        };
+        Ok(Property { value: value, source: PropSource::from_raw(source, c_string_lossy(statbuf.as_ptr())) })
+    }
+
+    /// The error of a failed read of a property that applies. libzfs
+    /// fails without reporting anything for properties that are not set,
+    /// and never clears its last error, so those are told apart by the
+    /// property rather than by the handle.
+    fn read_error(&self, prop: DatasetProp) -> Error {
+        if prop.may_be_unset() {
+            Error::UnavailableProperty(prop.name().to_owned())
+        } else {
+            self.handle.last_error()
+        }
+    }
+
+    /// Reads every native property that applies to this dataset and has
+    /// a value.
+    pub fn properties(&self) -> Result<Vec<(DatasetProp, Property)>> {
+        let mut props = Vec::new();
+        for &prop in DatasetProp::all() {
+            match self.get(prop) {
+                Ok(value) => props.push((prop, value)),
+                Err(Error::UnavailableProperty(_)) => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(props)
+    }
+
+    /// Sets several native properties in one operation, like
+    /// `zfs set a=1 b=2`. Either all of them are set or none.
+    pub fn set(&self, props: &[(DatasetProp, PropValue)]) -> Result<()> {
+        let mut list = List::new(Uniqueness::Name);
+        for &(prop, ref value) in props {
+            list.add(Pair::new(prop.name(), PairValue::String(value.to_string())));
+        }
+        self.set_list(&list)
+    }
+
+    fn set_list(&self, list: &List) -> Result<()> {
+        let nvlist = try!(NvList::from_list(list).map_err(Error::Nvpair));
+        if unsafe { zfs_sys::zfs_prop_set_list(self.raw, nvlist.as_ptr()) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        Ok(())
+    }
+
+    /// Clears the local value of a property, like `zfs inherit`. With
+    /// `received` the received value is restored instead, like
+    /// `zfs inherit -S`.
+    pub fn inherit(&self, prop: DatasetProp, received: bool) -> Result<()> {
+        self.inherit_raw(prop.name(), received)
+    }
+
+    fn inherit_raw(&self, name: &str, received: bool) -> Result<()> {
+        let name = try!(to_c_string(name));
+        let received = if received { boolean_t::B_TRUE } else { boolean_t::B_FALSE };
+        if unsafe { zfs_sys::zfs_prop_inherit(self.raw, name.as_ptr(), received) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        Ok(())
+    }
+
+    /// The user properties that are set on or inherited by this dataset.
+    pub fn user_properties(&self) -> Result<BTreeMap<String, Property>> {
+        let raw = unsafe { zfs_sys::zfs_get_user_props(self.raw) };
+        let mut props = BTreeMap::new();
+        if raw.is_null() {
+            return Ok(props);
+        }
+        // Every property is an nvlist with its "value" and the "source"
+        // dataset it is set on, or "$recvd" for received values.
+        let name = self.name();
+        let list = unsafe { NvListRef::from_raw(raw) };
+        for pair in list.pairs() {
+            let prop = match try!(pair.value().map_err(Error::Nvpair)) {
+                PairValue::NvList(prop) => prop,
+                _ => continue
+            };
+            let value = match prop.get("value") {
+                Some(&PairValue::String(ref value)) => value.clone(),
+                _ => continue
+            };
+            let source = match prop.get("source") {
+                Some(&PairValue::String(ref source)) if *source == name => PropSource::Local,
+                Some(&PairValue::String(ref source)) if source == "$recvd" => PropSource::Received,
+                Some(&PairValue::String(ref source)) => PropSource::Inherited(source.clone()),
+                _ => PropSource::None
+            };
+            props.insert(try!(pair.name().map_err(Error::Nvpair)),
+                         Property { value: PropValue::String(value), source: source });
+        }
+        Ok(props)
+    }
+
+    /// Sets a user property such as `com.example:owner`.
+    pub fn set_user_property(&self, name: &str, value: &str) -> Result<()> {
+        try!(validate_user_property(name, value));
+        let mut list = List::new(Uniqueness::Name);
+        list.add(Pair::new(name, PairValue::String(value.to_owned())));
+        self.set_list(&list)
+    }
+
+    /// Removes the local value of a user property, like `zfs inherit`.
+    pub fn inherit_user_property(&self, name: &str, received: bool) -> Result<()> {
+        try!(validate_user_property_name(name));
+        self.inherit_raw(name, received)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_user_property, validate_user_property_name, DatasetProp};
+    use super::super::super::{Error, UserPropError};
+
+    fn rule(result: ::Result<()>) -> Option<UserPropError> {
+        match result {
+            Ok(()) => None,
+            Err(Error::InvalidUserProperty(_, reason)) => Some(reason),
+            Err(e) => panic!("unexpected error {:?}", e)
+        }
+    }
+
+    #[test]
+    fn names_roundtrip() {
+        for prop in DatasetProp::all() {
+            assert_eq!(DatasetProp::from_name(prop.name()), Some(*prop));
+        }
+        assert_eq!(DatasetProp::from_name("com.example:owner"), None);
+    }
+
+    #[test]
+    fn raw_values_follow_zfs_prop_t() {
+        assert_eq!(DatasetProp::Type.to_raw() as i32, 0);
+        assert_eq!(DatasetProp::Compression.to_raw() as i32, 16);
+        assert_eq!(DatasetProp::Guid.to_raw() as i32, 42);
+        assert_eq!(DatasetProp::ReceiveResumeToken.to_raw() as i32, 81);
+    }
+
+    #[test]
+    fn settable_properties() {
+        assert!(DatasetProp::Used.is_readonly());
+        assert!(!DatasetProp::Compression.is_readonly());
+        assert!(DatasetProp::Casesensitivity.is_setonce());
+        assert!(!DatasetProp::Casesensitivity.is_inheritable());
+        assert!(DatasetProp::Compression.is_inheritable());
+        assert!(!DatasetProp::Quota.is_inheritable());
+        assert!(!DatasetProp::Guid.is_inheritable());
+        assert!(DatasetProp::Origin.may_be_unset());
+        assert!(!DatasetProp::Compression.may_be_unset());
+    }
+
+    #[test]
+    fn user_property_names() {
+        assert_eq!(rule(validate_user_property_name("com.example:owner")), None);
+        assert_eq!(rule(validate_user_property_name("a:b-c_d.e")), None);
+        assert_eq!(rule(validate_user_property_name("owner")), Some(UserPropError::MissingColon));
+        assert_eq!(rule(validate_user_property_name("-com:owner")), Some(UserPropError::LeadingDash));
+        assert_eq!(rule(validate_user_property_name("com:Owner")), Some(UserPropError::InvalidChar('O')));
+        assert_eq!(rule(validate_user_property_name("com:own er")), Some(UserPropError::InvalidChar(' ')));
+        assert_eq!(rule(validate_user_property_name(&format!("com:{}", "x".repeat(252)))), Some(UserPropError::NameTooLong));
+        assert_eq!(rule(validate_user_property("com:owner", &"x".repeat(4096))), Some(UserPropError::ValueTooLong));
+    }
+}
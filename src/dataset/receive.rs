@@ -0,0 +1,246 @@
+use std::ffi::CString;
+use std::io::{self, Read};
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::AsRawFd;
+use std::panic;
+use std::ptr;
+use std::thread;
+
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+
+use super::DatasetProp;
+use super::property::validate_user_property;
+use super::send::{copy, pipe};
+use super::super::{to_c_string, Error, Handle, PropValue, Result};
+use super::super::sendstream::{parse_begin, RECORD_LEN};
+
+/// How to receive, like the options of `zfs receive`.
+///
+/// Property overrides (`-o`) are not part of the receive itself: they are
+/// set as local values on the received filesystem once the stream is in,
+/// native properties first and then user properties one by one. They are
+/// checked before the stream is read, so read-only properties, properties
+/// that can only be set at creation such as `casesensitivity` and
+/// malformed values libzfs would reject, such as a relative
+/// `mountpoint`, fail without receiving anything. If setting them still
+/// fails afterwards the error is `Error::Received`, with the name of the
+/// snapshot that was received, and the overrides set before the failing
+/// one stay set.
+///
+/// There is no `-x`: no properties are received from the stream, so there
+/// is nothing to exclude.
+#[derive(Debug,Clone,Default)]
+pub struct ReceiveOptions {
+    force: bool,
+    resumable: bool,
+    origin: Option<String>,
+    props: Vec<(DatasetProp, PropValue)>,
+    user_props: Vec<(String, String)>,
+}
+
+impl ReceiveOptions {
+    pub fn new() -> ReceiveOptions {
+        ReceiveOptions::default()
+    }
+
+    /// Rolls the target back to its most recent snapshot first, like `-F`.
+    pub fn force(mut self, force: bool) -> ReceiveOptions {
+        self.force = force;
+        self
+    }
+
+    /// Keeps a partially received stream so the send can be resumed, like
+    /// `-s`. An interrupted receive then fails with `Error::Resumable`.
+    pub fn resumable(mut self, resumable: bool) -> ReceiveOptions {
+        self.resumable = resumable;
+        self
+    }
+
+    /// Receives an incremental stream as a clone of this snapshot.
+    pub fn origin(mut self, origin: &str) -> ReceiveOptions {
+        self.origin = Some(origin.to_owned());
+        self
+    }
+
+    /// Sets a native property on the received filesystem, like `-o`.
+    pub fn property<V: Into<PropValue>>(mut self, prop: DatasetProp, value: V) -> ReceiveOptions {
+        self.props.push((prop, value.into()));
+        self
+    }
+
+    pub fn user_property(mut self, name: &str, value: &str) -> ReceiveOptions {
+        self.user_props.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+/// The snapshot a stream is received into: `target` itself if it names a
+/// snapshot, otherwise `target` with the snapshot name of `toname`.
+fn snapshot_name(target: &str, toname: &str) -> Option<String> {
+    match toname.find('@') {
+        Some(i) => Some(format!("{}{}", target, &toname[i..])),
+        None => None
+    }
+}
+
+/// Rejects overrides that could only fail once the stream is received.
+fn validate_overrides(options: &ReceiveOptions) -> Result<()> {
+    for &(prop, ref value) in &options.props {
+        if prop.is_readonly() {
+            return Err(Error::InvalidProperty(prop.name().to_owned(), "read-only property".to_owned()));
+        }
+        if prop.is_setonce() {
+            return Err(Error::InvalidProperty(prop.name().to_owned(),
+                                              "can only be set when the dataset is created".to_owned()));
+        }
+        if prop == DatasetProp::Mountpoint {
+            let mountpoint = value.to_string();
+            if !mountpoint.starts_with('/') && mountpoint != "none" && mountpoint != "legacy" {
+                return Err(Error::InvalidProperty(prop.name().to_owned(),
+                                                  "must be an absolute path, 'none', or 'legacy'".to_owned()));
+            }
+        }
+    }
+    for &(ref name, ref value) in &options.user_props {
+        try!(validate_user_property(name, value));
+    }
+    Ok(())
+}
+
+impl Handle {
+    /// Receives a send stream from `reader` into `target`, like
+    /// `zfs receive`. `target` is either the snapshot to create or a
+    /// filesystem, which takes the snapshot name from the stream. Returns
+    /// the name of the received snapshot.
+    ///
+    /// See `ReceiveOptions` for how property overrides are applied.
+    pub fn receive<R: Read>(&self, target: &str, mut reader: R, options: &ReceiveOptions) -> Result<String> {
+        try!(validate_overrides(options));
+
+        let mut header = None;
+        let snapname = if target.contains('@') {
+            target.to_owned()
+        } else {
+            let mut record = vec![0; RECORD_LEN];
+            try!(reader.read_exact(&mut record).map_err(Error::Io));
+            let (begin, _) = try!(parse_begin(&record));
+            header = Some(record);
+            match snapshot_name(target, &begin.toname) {
+                Some(snapname) => snapname,
+                None => return Err(Error::InvalidStream(format!("'{}' is not a snapshot name", begin.toname)))
+            }
+        };
+        let c_snapname = try!(to_c_string(&snapname));
+        let origin = match options.origin {
+            Some(ref origin) => Some(try!(to_c_string(origin))),
+            None => None
+        };
+        let force = if options.force { boolean_t::B_TRUE } else { boolean_t::B_FALSE };
+        let resumable = options.resumable;
+
+        // lzc_receive reads from a file descriptor, so it runs on its own
+        // thread and this one copies `reader` into the other end of a pipe.
+        let (read_end, mut write_end) = try!(pipe().map_err(Error::Io));
+        let receiver = thread::spawn(move || receive_fd(c_snapname, origin, force, resumable, header, read_end.as_raw_fd()));
+        let copied = copy(&mut reader, &mut write_end, &mut |_| ());
+        drop(write_end);
+        let rc = match receiver.join() {
+            Ok(rc) => rc,
+            Err(e) => panic::resume_unwind(e)
+        };
+
+        // A failing reader makes the receive fail too, so its error is the
+        // cause. A broken pipe means the receive stopped reading first.
+        let error = match copied {
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe && rc != 0 => Some(io::Error::from_raw_os_error(rc)),
+            Err(e) => Some(e),
+            Ok(_) if rc != 0 => Some(io::Error::from_raw_os_error(rc)),
+            Ok(_) => None
+        };
+        if let Some(error) = error {
+            if options.resumable {
+                if let Some(token) = self.resume_token(&snapname) {
+                    return Err(Error::Resumable(error, token));
+                }
+            }
+            return Err(Error::Io(error));
+        }
+
+        match self.apply_overrides(&snapname, options) {
+            Ok(()) => Ok(snapname),
+            Err(e) => Err(Error::Received(snapname, Box::new(e)))
+        }
+    }
+
+    fn apply_overrides(&self, snapname: &str, options: &ReceiveOptions) -> Result<()> {
+        let fs = try!(self.dataset(snapname.splitn(2, '@').next().unwrap()));
+        if !options.props.is_empty() {
+            try!(fs.set(&options.props));
+        }
+        for &(ref name, ref value) in &options.user_props {
+            try!(fs.set_user_property(name, value));
+        }
+        Ok(())
+    }
+
+    /// The `receive_resume_token` of the filesystem a snapshot is received
+    /// into, if an interrupted receive left one.
+    fn resume_token(&self, snapname: &str) -> Option<String> {
+        let fs = match self.dataset(snapname.splitn(2, '@').next().unwrap()) {
+            Ok(fs) => fs,
+            Err(_) => return None
+        };
+        match fs.get(DatasetProp::ReceiveResumeToken) {
+            Ok(prop) => prop.value.as_str().and_then(|token| if token == "-" { None } else { Some(token.to_owned()) }),
+            Err(_) => None
+        }
+    }
+}
+
+fn receive_fd(snapname: CString, origin: Option<CString>, force: boolean_t, resumable: bool,
+              header: Option<Vec<u8>>, fd: c_int) -> c_int {
+    let origin = origin.as_ref().map_or(ptr::null(), |origin| origin.as_ptr());
+    let props = ptr::null_mut();
+    unsafe {
+        match (header, resumable) {
+            (Some(header), _) => {
+                let resumable = if resumable { boolean_t::B_TRUE } else { boolean_t::B_FALSE };
+                zfs_sys::lzc_receive_with_header(snapname.as_ptr(), props, origin, force, resumable, fd,
+                                                 header.as_ptr() as *const c_void)
+            },
+            (None, true) => zfs_sys::lzc_receive_resumable(snapname.as_ptr(), props, origin, force, fd),
+            (None, false) => zfs_sys::lzc_receive(snapname.as_ptr(), props, origin, force, fd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{snapshot_name, validate_overrides, ReceiveOptions};
+    use super::super::DatasetProp;
+    use super::super::super::Error;
+
+    #[test]
+    fn snapshot_names() {
+        assert_eq!(snapshot_name("backup/fs", "tank/fs@snap"), Some("backup/fs@snap".to_owned()));
+        assert_eq!(snapshot_name("backup/fs", "tank/fs"), None);
+    }
+
+    #[test]
+    fn overrides() {
+        let valid = ReceiveOptions::new().property(DatasetProp::Compression, "lz4")
+            .property(DatasetProp::Mountpoint, "/backup").user_property("backup:from", "src");
+        assert!(validate_overrides(&valid).is_ok());
+
+        let invalid = |options: ReceiveOptions| match validate_overrides(&options) {
+            Err(Error::InvalidProperty(name, _)) => name,
+            other => panic!("unexpected result {:?}", other)
+        };
+        assert_eq!(invalid(ReceiveOptions::new().property(DatasetProp::Used, 0u64)), "used");
+        assert_eq!(invalid(ReceiveOptions::new().property(DatasetProp::Casesensitivity, "mixed")), "casesensitivity");
+        assert_eq!(invalid(ReceiveOptions::new().property(DatasetProp::Mountpoint, "relative")), "mountpoint");
+        assert!(validate_overrides(&ReceiveOptions::new().property(DatasetProp::Mountpoint, "none")).is_ok());
+        assert!(validate_overrides(&ReceiveOptions::new().user_property("owner", "me")).is_err());
+    }
+}
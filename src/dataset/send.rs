@@ -0,0 +1,237 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::panic;
+use std::ptr;
+use std::thread;
+
+use libc;
+use zfs_sys;
+use zfs_sys::lzc_send_flags;
+
+use super::{Dataset, DatasetType};
+use super::super::{to_c_string, Error, Result};
+
+/// How to send, like the options of `zfs send`.
+#[derive(Debug,Clone,Default)]
+pub struct SendOptions {
+    from: Option<String>,
+    large_blocks: bool,
+    embed_data: bool,
+    compressed: bool,
+    raw: bool,
+    resume: Option<(u64, u64)>,
+}
+
+impl SendOptions {
+    pub fn new() -> SendOptions {
+        SendOptions::default()
+    }
+
+    /// Sends only the changes since this snapshot or bookmark, given by
+    /// full name (`pool/fs@snap` or `pool/fs#mark`), like `-i`.
+    pub fn from(mut self, from: &str) -> SendOptions {
+        self.from = Some(from.to_owned());
+        self
+    }
+
+    /// Allows blocks larger than 128K, like `-L`.
+    pub fn large_blocks(mut self, large_blocks: bool) -> SendOptions {
+        self.large_blocks = large_blocks;
+        self
+    }
+
+    /// Sends embedded blocks as they are, like `-e`.
+    pub fn embed_data(mut self, embed_data: bool) -> SendOptions {
+        self.embed_data = embed_data;
+        self
+    }
+
+    /// Sends compressed blocks without decompressing them, like `-c`.
+    pub fn compressed(mut self, compressed: bool) -> SendOptions {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Sends encrypted blocks as they are, like `-w`. The libzfs_core this
+    /// crate binds has no native encryption, so `send` and `send_space`
+    /// fail when this is set rather than send decrypted data.
+    pub fn raw(mut self, raw: bool) -> SendOptions {
+        self.raw = raw;
+        self
+    }
+
+    /// Continues an interrupted send from this object and offset, as found
+    /// in the `receive_resume_token` of the receiving side.
+    pub fn resume(mut self, object: u64, offset: u64) -> SendOptions {
+        self.resume = Some((object, offset));
+        self
+    }
+
+    fn flags(&self) -> lzc_send_flags {
+        let mut flags = 0;
+        if self.large_blocks {
+            flags |= zfs_sys::LZC_SEND_FLAG_LARGE_BLOCK;
+        }
+        if self.embed_data {
+            flags |= zfs_sys::LZC_SEND_FLAG_EMBED_DATA;
+        }
+        if self.compressed {
+            flags |= zfs_sys::LZC_SEND_FLAG_COMPRESS;
+        }
+        flags
+    }
+
+    fn check_supported(&self) -> Result<()> {
+        if self.raw {
+            return Err(Error::InvalidArgument("raw sends need a libzfs_core with native encryption".to_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// Creates a pipe, returning the read and the write end. Both ends are
+/// closed on exec, so a child spawned meanwhile cannot keep the write end
+/// open and stop the reader from seeing EOF.
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0 as c_int; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Copies everything from `reader` to `writer`, calling `progress` with
+/// the total number of bytes copied so far after every chunk.
+pub fn copy<R: Read, W: Write, F: FnMut(u64)>(reader: &mut R, writer: &mut W, progress: &mut F) -> io::Result<u64> {
+    let mut buf = vec![0; 128 * 1024];
+    let mut total = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)
+        };
+        try!(writer.write_all(&buf[..n]));
+        total += n as u64;
+        progress(total);
+    }
+}
+
+impl<'h> Dataset<'h> {
+    /// Estimates the size in bytes of the stream `send` would write with
+    /// these options, like `zfs send -nv`. `resume` is ignored.
+    pub fn send_space(&self, options: &SendOptions) -> Result<u64> {
+        try!(options.check_supported());
+        let snapname = try!(self.send_snapname());
+        let from = match options.from {
+            Some(ref from) => Some(try!(to_c_string(from))),
+            None => None
+        };
+        let from = from.as_ref().map_or(ptr::null(), |from| from.as_ptr());
+        let mut space = 0;
+        match unsafe { zfs_sys::lzc_send_space(snapname.as_ptr(), from, options.flags(), &mut space) } {
+            0 => Ok(space),
+            errno => Err(Error::Io(io::Error::from_raw_os_error(errno)))
+        }
+    }
+
+    fn send_snapname(&self) -> Result<CString> {
+        if self.dataset_type() != Some(DatasetType::Snapshot) {
+            return Err(Error::InvalidArgument(format!("'{}' is not a snapshot", self.name())));
+        }
+        to_c_string(&self.name())
+    }
+
+    /// Writes a send stream of this snapshot to `writer`, like `zfs send`.
+    /// Returns the number of bytes written.
+    pub fn send<W: Write>(&self, writer: W, options: &SendOptions) -> Result<u64> {
+        self.send_with_progress(writer, options, |_| ())
+    }
+
+    /// Like `send`, calling `progress` with the number of bytes written so
+    /// far as the stream is written.
+    pub fn send_with_progress<W, F>(&self, mut writer: W, options: &SendOptions, mut progress: F) -> Result<u64>
+        where W: Write, F: FnMut(u64)
+    {
+        try!(options.check_supported());
+        let snapname = try!(self.send_snapname());
+        let from = match options.from {
+            Some(ref from) => Some(try!(to_c_string(from))),
+            None => None
+        };
+        let flags = options.flags();
+        let resume = options.resume;
+
+        // lzc_send writes to a file descriptor, so it runs on its own thread
+        // and this one copies from the other end of a pipe into `writer`.
+        let (mut reader, write_end) = try!(pipe().map_err(Error::Io));
+        let sender = thread::spawn(move || {
+            let from = from.as_ref().map_or(ptr::null(), |from| from.as_ptr());
+            let fd = write_end.as_raw_fd();
+            unsafe {
+                match resume {
+                    Some((object, offset)) => zfs_sys::lzc_send_resume(snapname.as_ptr(), from, fd, flags, object, offset),
+                    None => zfs_sys::lzc_send(snapname.as_ptr(), from, fd, flags),
+                }
+            }
+        });
+        let copied = copy(&mut reader, &mut writer, &mut progress);
+        // Closing the read end makes lzc_send fail with EPIPE rather than
+        // block forever if `writer` failed.
+        drop(reader);
+        let rc = match sender.join() {
+            Ok(rc) => rc,
+            Err(e) => panic::resume_unwind(e)
+        };
+        let total = try!(copied.map_err(Error::Io));
+        if rc != 0 {
+            return Err(Error::Io(io::Error::from_raw_os_error(rc)));
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+    use std::os::unix::io::AsRawFd;
+    use libc;
+    use super::{copy, pipe, SendOptions};
+
+    #[test]
+    fn flags() {
+        assert_eq!(SendOptions::new().flags(), 0);
+        let options = SendOptions::new().large_blocks(true).embed_data(true).compressed(true);
+        assert_eq!(options.flags(), 0b111);
+        assert!(SendOptions::new().raw(true).check_supported().is_err());
+    }
+
+    #[test]
+    fn copy_reports_progress() {
+        let data = vec![7u8; 300 * 1024];
+        let mut out = Vec::new();
+        let mut reported = Vec::new();
+        let total = copy(&mut Cursor::new(&data), &mut out, &mut |n| reported.push(n)).unwrap();
+        assert_eq!(total, data.len() as u64);
+        assert_eq!(out, data);
+        assert_eq!(reported.last(), Some(&total));
+    }
+
+    #[test]
+    fn pipe_connects_ends() {
+        let (mut reader, mut writer) = pipe().unwrap();
+        for end in &[&reader, &writer] {
+            let flags = unsafe { libc::fcntl(end.as_raw_fd(), libc::F_GETFD) };
+            assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+        }
+        writer.write_all(b"stream").unwrap();
+        drop(writer);
+        let mut out = Vec::new();
+        copy(&mut reader, &mut out, &mut |_| ()).unwrap();
+        assert_eq!(out, b"stream");
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::os::raw::c_int;
+use std::ptr;
+
+use nvpair::{List, NvList, Pair, PairValue, Uniqueness};
+use nvpair::ffi::nvlist_t;
+use zfs_sys;
+
+use super::ListOptions;
+use super::property::validate_user_property;
+use super::super::{Error, Handle, Result};
+
+/// Splits `pool/fs@snap` into the dataset and snapshot name.
+fn split_snapshot_name(name: &str) -> Result<(&str, &str)> {
+    let mut parts = name.splitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(dataset), Some(snapshot)) if !dataset.is_empty() && !snapshot.is_empty() && !snapshot.contains('@') =>
+            Ok((dataset, snapshot)),
+        _ => Err(Error::InvalidArgument(format!("'{}' is not a snapshot name like pool/fs@snap", name)))
+    }
+}
+
+/// Runs a libzfs_core call that reports failures per name in an error
+/// list of errnos. Some calls, like `lzc_hold`, skip names they cannot
+/// handle and still return 0, so any listed name is a failure.
+pub fn lzc_result<F>(call: F) -> Result<()>
+    where F: FnOnce(*mut *mut nvlist_t) -> c_int
+{
+    let mut raw: *mut nvlist_t = ptr::null_mut();
+    let rc = call(&mut raw);
+    let errors = if raw.is_null() { None } else { Some(unsafe { NvList::from_raw(raw) }) };
+    if rc == 0 && errors.as_ref().map_or(true, |errors| errors.pairs().next().is_none()) {
+        return Ok(());
+    }
+    let mut failed = BTreeMap::new();
+    if let Some(errors) = errors {
+        for pair in errors.pairs() {
+            let name = try!(pair.name().map_err(Error::Nvpair));
+            if let PairValue::Int32(errno) = try!(pair.value().map_err(Error::Nvpair)) {
+                failed.insert(name, io::Error::from_raw_os_error(errno));
+            }
+        }
+    }
+    if failed.is_empty() {
+        return Err(Error::Io(io::Error::from_raw_os_error(rc)));
+    }
+    Err(Error::Batch(failed))
+}
+
+impl Handle {
+    /// Atomically creates snapshots, named like `pool/fs@snap`, with the
+    /// given user properties. All snapshots must be in the same pool.
+    /// When any of them cannot be created none are, and the error is
+    /// `Error::Batch` with the errno of every name that failed.
+    pub fn snapshot(&self, names: &[&str], props: &[(&str, &str)]) -> Result<()> {
+        let mut snapshots = List::new(Uniqueness::Name);
+        for name in names {
+            try!(split_snapshot_name(name));
+            snapshots.add(Pair::new(*name, PairValue::Boolean));
+        }
+        let mut properties = List::new(Uniqueness::Name);
+        for &(name, value) in props {
+            try!(validate_user_property(name, value));
+            properties.add(Pair::new(name, PairValue::String(value.to_owned())));
+        }
+        let snapshots = try!(NvList::from_list(&snapshots).map_err(Error::Nvpair));
+        let properties = try!(NvList::from_list(&properties).map_err(Error::Nvpair));
+        lzc_result(|errors| unsafe { zfs_sys::lzc_snapshot(snapshots.as_ptr(), properties.as_ptr(), errors) })
+    }
+
+    /// Like `snapshot`, but every `pool/fs@snap` also snapshots all
+    /// filesystems and volumes below `pool/fs`, like `zfs snapshot -r`.
+    pub fn snapshot_recursive(&self, names: &[&str], props: &[(&str, &str)]) -> Result<()> {
+        let mut expanded = Vec::new();
+        for name in names {
+            let (dataset, snapshot) = try!(split_snapshot_name(name));
+            for descendant in try!(try!(self.dataset(dataset)).list(&ListOptions::new())) {
+                expanded.push(format!("{}@{}", descendant.name(), snapshot));
+            }
+        }
+        let expanded: Vec<&str> = expanded.iter().map(|s| &**s).collect();
+        self.snapshot(&expanded, props)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::split_snapshot_name;
+
+    #[test]
+    fn snapshot_names() {
+        assert_eq!(split_snapshot_name("tank/fs@today").ok(), Some(("tank/fs", "today")));
+        assert!(split_snapshot_name("tank/fs").is_err());
+        assert!(split_snapshot_name("@today").is_err());
+        assert!(split_snapshot_name("tank@").is_err());
+        assert!(split_snapshot_name("tank@a@b").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::ffi::NulError;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+
+use nvpair;
+use zfs_sys::zfs_error_t;
+
+/// The kind of failure libzfs reported, one for every `EZFS_*` code.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ErrorKind {
+    NoMem,
+    BadProp,
+    PropReadonly,
+    PropType,
+    PropNonInherit,
+    PropSpace,
+    BadType,
+    Busy,
+    Exists,
+    NoEnt,
+    BadStream,
+    DatasetReadonly,
+    VolumeTooBig,
+    InvalidName,
+    BadRestore,
+    BadBackup,
+    BadTarget,
+    NoDevice,
+    BadDevice,
+    NoReplicas,
+    Resilvering,
+    BadVersion,
+    PoolUnavailable,
+    DeviceOverflow,
+    BadPath,
+    CrossTarget,
+    Zoned,
+    MountFailed,
+    UnmountFailed,
+    UnshareNfsFailed,
+    ShareNfsFailed,
+    Permission,
+    NoSpace,
+    Fault,
+    Io,
+    Interrupted,
+    IsSpare,
+    InvalidConfig,
+    Recursive,
+    NoHistory,
+    PoolProps,
+    PoolNotSupported,
+    PoolInvalidArgument,
+    NameTooLong,
+    OpenFailed,
+    NoCapacity,
+    LabelFailed,
+    BadWho,
+    BadPermission,
+    BadPermissionSet,
+    NoDelegation,
+    UnshareSmbFailed,
+    ShareSmbFailed,
+    BadCache,
+    IsL2Cache,
+    VdevNotSupported,
+    NotSupported,
+    ActiveSpare,
+    UnplayedLogs,
+    ReftagRelease,
+    ReftagHold,
+    TagTooLong,
+    PipeFailed,
+    ThreadCreateFailed,
+    PostSplitOnline,
+    Scrubbing,
+    NoScrub,
+    Diff,
+    DiffData,
+    PoolReadonly,
+    Unknown,
+}
+
+impl ErrorKind {
+    pub fn from_raw(code: c_int) -> ErrorKind {
+        use zfs_sys::zfs_error_t::*;
+        match zfs_error_t::from_raw(code) {
+            EZFS_NOMEM => ErrorKind::NoMem,
+            EZFS_BADPROP => ErrorKind::BadProp,
+            EZFS_PROPREADONLY => ErrorKind::PropReadonly,
+            EZFS_PROPTYPE => ErrorKind::PropType,
+            EZFS_PROPNONINHERIT => ErrorKind::PropNonInherit,
+            EZFS_PROPSPACE => ErrorKind::PropSpace,
+            EZFS_BADTYPE => ErrorKind::BadType,
+            EZFS_BUSY => ErrorKind::Busy,
+            EZFS_EXISTS => ErrorKind::Exists,
+            EZFS_NOENT => ErrorKind::NoEnt,
+            EZFS_BADSTREAM => ErrorKind::BadStream,
+            EZFS_DSREADONLY => ErrorKind::DatasetReadonly,
+            EZFS_VOLTOOBIG => ErrorKind::VolumeTooBig,
+            EZFS_INVALIDNAME => ErrorKind::InvalidName,
+            EZFS_BADRESTORE => ErrorKind::BadRestore,
+            EZFS_BADBACKUP => ErrorKind::BadBackup,
+            EZFS_BADTARGET => ErrorKind::BadTarget,
+            EZFS_NODEVICE => ErrorKind::NoDevice,
+            EZFS_BADDEV => ErrorKind::BadDevice,
+            EZFS_NOREPLICAS => ErrorKind::NoReplicas,
+            EZFS_RESILVERING => ErrorKind::Resilvering,
+            EZFS_BADVERSION => ErrorKind::BadVersion,
+            EZFS_POOLUNAVAIL => ErrorKind::PoolUnavailable,
+            EZFS_DEVOVERFLOW => ErrorKind::DeviceOverflow,
+            EZFS_BADPATH => ErrorKind::BadPath,
+            EZFS_CROSSTARGET => ErrorKind::CrossTarget,
+            EZFS_ZONED => ErrorKind::Zoned,
+            EZFS_MOUNTFAILED => ErrorKind::MountFailed,
+            EZFS_UMOUNTFAILED => ErrorKind::UnmountFailed,
+            EZFS_UNSHARENFSFAILED => ErrorKind::UnshareNfsFailed,
+            EZFS_SHARENFSFAILED => ErrorKind::ShareNfsFailed,
+            EZFS_PERM => ErrorKind::Permission,
+            EZFS_NOSPC => ErrorKind::NoSpace,
+            EZFS_FAULT => ErrorKind::Fault,
+            EZFS_IO => ErrorKind::Io,
+            EZFS_INTR => ErrorKind::Interrupted,
+            EZFS_ISSPARE => ErrorKind::IsSpare,
+            EZFS_INVALCONFIG => ErrorKind::InvalidConfig,
+            EZFS_RECURSIVE => ErrorKind::Recursive,
+            EZFS_NOHISTORY => ErrorKind::NoHistory,
+            EZFS_POOLPROPS => ErrorKind::PoolProps,
+            EZFS_POOL_NOTSUP => ErrorKind::PoolNotSupported,
+            EZFS_POOL_INVALARG => ErrorKind::PoolInvalidArgument,
+            EZFS_NAMETOOLONG => ErrorKind::NameTooLong,
+            EZFS_OPENFAILED => ErrorKind::OpenFailed,
+            EZFS_NOCAP => ErrorKind::NoCapacity,
+            EZFS_LABELFAILED => ErrorKind::LabelFailed,
+            EZFS_BADWHO => ErrorKind::BadWho,
+            EZFS_BADPERM => ErrorKind::BadPermission,
+            EZFS_BADPERMSET => ErrorKind::BadPermissionSet,
+            EZFS_NODELEGATION => ErrorKind::NoDelegation,
+            EZFS_UNSHARESMBFAILED => ErrorKind::UnshareSmbFailed,
+            EZFS_SHARESMBFAILED => ErrorKind::ShareSmbFailed,
+            EZFS_BADCACHE => ErrorKind::BadCache,
+            EZFS_ISL2CACHE => ErrorKind::IsL2Cache,
+            EZFS_VDEVNOTSUP => ErrorKind::VdevNotSupported,
+            EZFS_NOTSUP => ErrorKind::NotSupported,
+            EZFS_ACTIVE_SPARE => ErrorKind::ActiveSpare,
+            EZFS_UNPLAYED_LOGS => ErrorKind::UnplayedLogs,
+            EZFS_REFTAG_RELE => ErrorKind::ReftagRelease,
+            EZFS_REFTAG_HOLD => ErrorKind::ReftagHold,
+            EZFS_TAGTOOLONG => ErrorKind::TagTooLong,
+            EZFS_PIPEFAILED => ErrorKind::PipeFailed,
+            EZFS_THREADCREATEFAILED => ErrorKind::ThreadCreateFailed,
+            EZFS_POSTSPLIT_ONLINE => ErrorKind::PostSplitOnline,
+            EZFS_SCRUBBING => ErrorKind::Scrubbing,
+            EZFS_NO_SCRUB => ErrorKind::NoScrub,
+            EZFS_DIFF => ErrorKind::Diff,
+            EZFS_DIFFDATA => ErrorKind::DiffData,
+            EZFS_POOLREADONLY => ErrorKind::PoolReadonly,
+            EZFS_SUCCESS | EZFS_UNKNOWN => ErrorKind::Unknown,
+        }
+    }
+}
+
+/// The rule a user property name or value breaks.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum UserPropError {
+    /// Names need a colon to tell them apart from native properties.
+    MissingColon,
+    LeadingDash,
+    /// Only lowercase letters, digits, `:`, `-`, `.` and `_` are allowed.
+    InvalidChar(char),
+    NameTooLong,
+    ValueTooLong,
+}
+
+impl fmt::Display for UserPropError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UserPropError::MissingColon => write!(f, "name must contain a ':', as in module:property"),
+            UserPropError::LeadingDash => write!(f, "name must not start with '-'"),
+            UserPropError::InvalidChar(c) =>
+                write!(f, "'{}' is not allowed, use lowercase letters, digits, ':', '-', '.' and '_'", c),
+            UserPropError::NameTooLong => write!(f, "name must be shorter than 256 bytes"),
+            UserPropError::ValueTooLong => write!(f, "value must be shorter than 4096 bytes"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    CouldNotInitialize,
+    InteriorNul(NulError),
+    Nvpair(nvpair::Error),
+    /// The named property could not be read.
+    UnavailableProperty(String),
+    /// The named property was rejected before reaching libzfs, for the
+    /// given reason.
+    InvalidProperty(String, String),
+    /// The named user property was rejected before reaching libzfs.
+    InvalidUserProperty(String, UserPropError),
+    /// A libzfs_core call failed with this errno.
+    Io(io::Error),
+    /// A libzfs_core call failed for these names, with their errno.
+    Batch(BTreeMap<String, io::Error>),
+    /// A resumable receive was interrupted. Pass the `receive_resume_token`
+    /// to the sending side to continue.
+    Resumable(io::Error, String),
+    /// The named snapshot was received, but applying the property
+    /// overrides to its filesystem failed.
+    Received(String, Box<Error>),
+    /// The arguments do not make sense together.
+    InvalidArgument(String),
+    /// The named dataset or snapshot does not exist.
+    NotFound(String),
+    /// Destroying needs `recursive` because of these children.
+    HasChildren(Vec<String>),
+    /// Destroying needs `dependents` because of these clones.
+    HasDependentClones(Vec<String>),
+    /// A send stream is malformed, for the given reason.
+    InvalidStream(String),
+    /// A send stream checksum does not match at the record at this offset.
+    StreamChecksum(u64),
+    /// Rolling back needs destroying these newer snapshots.
+    NewerSnapshots(Vec<String>),
+    /// A libzfs call failed. `errno` is the raw `libzfs_errno` code, `action`
+    /// what was attempted ("cannot open 'tank'") and `description` why it
+    /// failed ("no such pool").
+    Libzfs {
+        kind: ErrorKind,
+        errno: c_int,
+        action: String,
+        description: String,
+    },
+}
+
+impl Error {
+    /// The libzfs error kind, if this error came from libzfs.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match *self {
+            Error::Libzfs { kind, .. } => Some(kind),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::CouldNotInitialize => write!(f, "could not initialize libzfs"),
+            Error::InteriorNul(ref e) => write!(f, "{}", e),
+            Error::Nvpair(ref e) => write!(f, "{}", e),
+            Error::UnavailableProperty(ref name) => write!(f, "property '{}' is unavailable", name),
+            Error::InvalidProperty(ref name, ref reason) => write!(f, "invalid property '{}': {}", name, reason),
+            Error::InvalidUserProperty(ref name, reason) => write!(f, "invalid user property '{}': {}", name, reason),
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Resumable(ref e, _) => write!(f, "{}, the receive can be resumed", e),
+            Error::Received(ref name, ref e) => write!(f, "received '{}' but could not set its properties: {}", name, e),
+            Error::Batch(ref errors) => {
+                let errors: Vec<String> = errors.iter().map(|(name, e)| format!("{}: {}", name, e)).collect();
+                write!(f, "{}", errors.join(", "))
+            }
+            Error::InvalidArgument(ref reason) => write!(f, "invalid argument: {}", reason),
+            Error::NotFound(ref name) => write!(f, "'{}' does not exist", name),
+            Error::HasChildren(ref names) => write!(f, "has children: {}", names.join(", ")),
+            Error::HasDependentClones(ref names) => write!(f, "has dependent clones: {}", names.join(", ")),
+            Error::InvalidStream(ref reason) => write!(f, "invalid send stream: {}", reason),
+            Error::StreamChecksum(offset) => write!(f, "send stream checksum mismatch at byte {}", offset),
+            Error::NewerSnapshots(ref names) => write!(f, "more recent snapshots exist: {}", names.join(", ")),
+            Error::Libzfs { ref action, ref description, .. } => {
+                if action.is_empty() {
+                    write!(f, "{}", description)
+                } else {
+                    write!(f, "{}: {}", action, description)
+                }
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::CouldNotInitialize => "could not initialize libzfs",
+            Error::InteriorNul(_) => "string contains an interior nul byte",
+            Error::Nvpair(_) => "nvlist error",
+            Error::UnavailableProperty(_) => "property is unavailable",
+            Error::InvalidProperty(..) => "invalid property",
+            Error::InvalidUserProperty(..) => "invalid user property",
+            Error::Io(_) => "libzfs_core call failed",
+            Error::Resumable(..) => "receive was interrupted",
+            Error::Received(..) => "received, but could not set properties",
+            Error::Batch(_) => "libzfs_core call failed for some names",
+            Error::InvalidArgument(_) => "invalid argument",
+            Error::NotFound(_) => "dataset does not exist",
+            Error::HasChildren(_) => "dataset has children",
+            Error::HasDependentClones(_) => "dataset has dependent clones",
+            Error::InvalidStream(_) => "invalid send stream",
+            Error::StreamChecksum(_) => "send stream checksum mismatch",
+            Error::NewerSnapshots(_) => "more recent snapshots exist",
+            Error::Libzfs { ref description, .. } => description,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Error, ErrorKind};
+
+    #[test]
+    fn kind_from_raw() {
+        assert_eq!(ErrorKind::from_raw(2000), ErrorKind::NoMem);
+        assert_eq!(ErrorKind::from_raw(2008), ErrorKind::Exists);
+        assert_eq!(ErrorKind::from_raw(2009), ErrorKind::NoEnt);
+        assert_eq!(ErrorKind::from_raw(0), ErrorKind::Unknown);
+        assert_eq!(ErrorKind::from_raw(12345), ErrorKind::Unknown);
+    }
+
+    #[test]
+    fn display() {
+        let e = Error::Libzfs {
+            kind: ErrorKind::NoEnt,
+            errno: 2009,
+            action: "cannot open 'tank'".to_owned(),
+            description: "no such pool".to_owned(),
+        };
+        assert_eq!(e.to_string(), "cannot open 'tank': no such pool");
+        assert_eq!(e.kind(), Some(ErrorKind::NoEnt));
+    }
+}
@@ -1,10 +1,21 @@
+extern crate libc;
+extern crate nvpair;
 extern crate zfs_sys;
 
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+use nvpair::ffi::boolean_t;
+
+mod error;
+pub use error::{Error, ErrorKind, UserPropError};
+
+mod property;
+pub use property::{Checksum, Compression, PropSource, PropValue, Property};
+
+pub mod dataset;
+pub mod sendstream;
 pub mod zpool;
-#[derive(Debug)]
-pub enum Error {
-    CouldNotInitialize
-}
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -12,17 +23,42 @@ pub struct Handle {
     handle: *mut zfs_sys::libzfs_handle_t
 }
 
+fn to_c_string(s: &str) -> Result<CString> {
+    CString::new(s).map_err(Error::InteriorNul)
+}
+
+fn c_string_lossy(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
 impl Handle {
     pub fn new() -> Result<Handle> {
         let raw = unsafe { zfs_sys::libzfs_init() };
         if raw == std::ptr::null_mut() {
             return Err(Error::CouldNotInitialize);
         } else {
+            // We report errors through Result, libzfs should not print them.
+            unsafe { zfs_sys::libzfs_print_on_error(raw, boolean_t::B_FALSE) };
             Ok(Handle {
                 handle: raw
             })
         }
     }
+
+    /// Captures the error state of the handle. Must be called right after
+    /// the failed libzfs call, before anything else touches the handle.
+    fn last_error(&self) -> Error {
+        let errno = unsafe { zfs_sys::libzfs_errno(self.handle) };
+        Error::Libzfs {
+            kind: ErrorKind::from_raw(errno),
+            errno: errno,
+            action: c_string_lossy(unsafe { zfs_sys::libzfs_error_action(self.handle) }),
+            description: c_string_lossy(unsafe { zfs_sys::libzfs_error_description(self.handle) }),
+        }
+    }
 }
 
 impl Drop for Handle {
@@ -0,0 +1,288 @@
+use std::fmt;
+
+use zfs_sys::zprop_source_t;
+
+/// The `compression` property.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Compression {
+    On,
+    Off,
+    Lzjb,
+    /// Gzip with a level from 1 to 9, plain `gzip` is level 6.
+    Gzip(u8),
+    Zle,
+    Lz4,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "on" => Some(Compression::On),
+            "off" => Some(Compression::Off),
+            "lzjb" => Some(Compression::Lzjb),
+            "gzip" => Some(Compression::Gzip(6)),
+            "zle" => Some(Compression::Zle),
+            "lz4" => Some(Compression::Lz4),
+            _ if name.starts_with("gzip-") => match name[5..].parse() {
+                Ok(level) if level >= 1 && level <= 9 => Some(Compression::Gzip(level)),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Compression::On => write!(f, "on"),
+            Compression::Off => write!(f, "off"),
+            Compression::Lzjb => write!(f, "lzjb"),
+            Compression::Gzip(level) => write!(f, "gzip-{}", level),
+            Compression::Zle => write!(f, "zle"),
+            Compression::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+/// The `checksum` property.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Checksum {
+    On,
+    Off,
+    Fletcher2,
+    Fletcher4,
+    Sha256,
+    NoParity,
+    Sha512,
+    Skein,
+    Edonr,
+}
+
+impl Checksum {
+    pub fn from_name(name: &str) -> Option<Checksum> {
+        match name {
+            "on" => Some(Checksum::On),
+            "off" => Some(Checksum::Off),
+            "fletcher2" => Some(Checksum::Fletcher2),
+            "fletcher4" => Some(Checksum::Fletcher4),
+            "sha256" => Some(Checksum::Sha256),
+            "noparity" => Some(Checksum::NoParity),
+            "sha512" => Some(Checksum::Sha512),
+            "skein" => Some(Checksum::Skein),
+            "edonr" => Some(Checksum::Edonr),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Checksum::On => "on",
+            Checksum::Off => "off",
+            Checksum::Fletcher2 => "fletcher2",
+            Checksum::Fletcher4 => "fletcher4",
+            Checksum::Sha256 => "sha256",
+            Checksum::NoParity => "noparity",
+            Checksum::Sha512 => "sha512",
+            Checksum::Skein => "skein",
+            Checksum::Edonr => "edonr",
+        })
+    }
+}
+
+/// A property value, typed the way libzfs stores it.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum PropValue {
+    Number(u64),
+    /// A size in bytes.
+    Bytes(u64),
+    /// A ratio in hundredths, 150 for 1.50x.
+    Ratio(u64),
+    /// An on/off property.
+    Bool(bool),
+    /// One of a fixed set of values, like `standard` for `sync`.
+    Index(String),
+    Compression(Compression),
+    Checksum(Checksum),
+    String(String),
+}
+
+impl PropValue {
+    /// The value of a `Number`, `Bytes` or `Ratio`.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            PropValue::Number(n) | PropValue::Bytes(n) | PropValue::Ratio(n) => Some(n),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            PropValue::Bool(b) => Some(b),
+            _ => None
+        }
+    }
+
+    /// The value of an `Index` or `String`.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            PropValue::Index(ref s) | PropValue::String(ref s) => Some(s),
+            _ => None
+        }
+    }
+}
+
+/// Formats the value the way `zfs set` and `zpool set` expect it.
+impl fmt::Display for PropValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PropValue::Number(n) | PropValue::Bytes(n) => write!(f, "{}", n),
+            PropValue::Ratio(n) => write!(f, "{}.{:02}x", n / 100, n % 100),
+            PropValue::Bool(true) => write!(f, "on"),
+            PropValue::Bool(false) => write!(f, "off"),
+            PropValue::Compression(c) => write!(f, "{}", c),
+            PropValue::Checksum(c) => write!(f, "{}", c),
+            PropValue::Index(ref s) | PropValue::String(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<u64> for PropValue {
+    fn from(n: u64) -> PropValue {
+        PropValue::Number(n)
+    }
+}
+
+impl From<bool> for PropValue {
+    fn from(b: bool) -> PropValue {
+        PropValue::Bool(b)
+    }
+}
+
+impl<'a> From<&'a str> for PropValue {
+    fn from(s: &'a str) -> PropValue {
+        PropValue::String(s.to_owned())
+    }
+}
+
+impl From<String> for PropValue {
+    fn from(s: String) -> PropValue {
+        PropValue::String(s)
+    }
+}
+
+impl From<Compression> for PropValue {
+    fn from(c: Compression) -> PropValue {
+        PropValue::Compression(c)
+    }
+}
+
+impl From<Checksum> for PropValue {
+    fn from(c: Checksum) -> PropValue {
+        PropValue::Checksum(c)
+    }
+}
+
+/// How a native property is stored, which decides how it is read and
+/// which `PropValue` it becomes.
+pub enum PropKind {
+    Number,
+    Bytes,
+    Ratio,
+    Bool,
+    Index,
+    Compression,
+    Checksum,
+    String,
+}
+
+impl PropKind {
+    /// Whether libzfs can return the value as an integer.
+    pub fn is_numeric(&self) -> bool {
+        match *self {
+            PropKind::Number | PropKind::Bytes | PropKind::Ratio | PropKind::Bool => true,
+            _ => false
+        }
+    }
+
+    pub fn from_numeric(&self, n: u64) -> PropValue {
+        match *self {
+            PropKind::Bytes => PropValue::Bytes(n),
+            PropKind::Ratio => PropValue::Ratio(n),
+            PropKind::Bool => PropValue::Bool(n != 0),
+            _ => PropValue::Number(n),
+        }
+    }
+
+    pub fn from_string(&self, s: String) -> PropValue {
+        match *self {
+            PropKind::Index => PropValue::Index(s),
+            PropKind::Compression => match Compression::from_name(&s) {
+                Some(c) => PropValue::Compression(c),
+                None => PropValue::Index(s)
+            },
+            PropKind::Checksum => match Checksum::from_name(&s) {
+                Some(c) => PropValue::Checksum(c),
+                None => PropValue::Index(s)
+            },
+            _ => PropValue::String(s),
+        }
+    }
+}
+
+/// Where a property value comes from, as in the SOURCE column of `zfs get`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum PropSource {
+    None,
+    Default,
+    Temporary,
+    Local,
+    /// Inherited from the named dataset.
+    Inherited(String),
+    Received,
+}
+
+impl PropSource {
+    pub fn from_raw(source: zprop_source_t, inherited_from: String) -> PropSource {
+        match source {
+            zprop_source_t::ZPROP_SRC_NONE => PropSource::None,
+            zprop_source_t::ZPROP_SRC_DEFAULT => PropSource::Default,
+            zprop_source_t::ZPROP_SRC_TEMPORARY => PropSource::Temporary,
+            zprop_source_t::ZPROP_SRC_LOCAL => PropSource::Local,
+            zprop_source_t::ZPROP_SRC_INHERITED => PropSource::Inherited(inherited_from),
+            zprop_source_t::ZPROP_SRC_RECEIVED => PropSource::Received,
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Property {
+    pub value: PropValue,
+    pub source: PropSource,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Checksum, Compression, PropKind, PropValue};
+
+    #[test]
+    fn display_as_zfs_set_argument() {
+        assert_eq!(PropValue::from(true).to_string(), "on");
+        assert_eq!(PropValue::from(false).to_string(), "off");
+        assert_eq!(PropValue::from(4096).to_string(), "4096");
+        assert_eq!(PropValue::from("lz4").to_string(), "lz4");
+        assert_eq!(PropValue::from(Compression::Gzip(9)).to_string(), "gzip-9");
+        assert_eq!(PropValue::Ratio(150).to_string(), "1.50x");
+    }
+
+    #[test]
+    fn parse_enums() {
+        assert_eq!(Compression::from_name("gzip"), Some(Compression::Gzip(6)));
+        assert_eq!(Compression::from_name("gzip-1"), Some(Compression::Gzip(1)));
+        assert_eq!(Compression::from_name("gzip-10"), None);
+        assert_eq!(Checksum::from_name("sha512"), Some(Checksum::Sha512));
+        assert_eq!(PropKind::Compression.from_string("zstd".to_owned()), PropValue::Index("zstd".to_owned()));
+    }
+}
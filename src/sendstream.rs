@@ -0,0 +1,1056 @@
+//! Reads the streams written by `zfs send` without receiving them, like
+//! `zstreamdump`. A stream is a sequence of DMU replay records: a fixed
+//! size header, sometimes followed by a payload, each covered by a
+//! running fletcher-4 checksum. Streams can also be rewritten with fresh
+//! checksums. This is plain Rust and needs no kernel module.
+
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+use nvpair::{Encoding, List};
+
+use super::{Error, Result};
+
+/// The size of every record header, `sizeof (dmu_replay_record_t)`.
+pub const RECORD_LEN: usize = 312;
+pub const DMU_BACKUP_MAGIC: u64 = 0x2F5bacbac;
+
+// The running checksum takes the last 32 bytes of every record header.
+const CHECKSUM_OFFSET: usize = RECORD_LEN - 32;
+// The type specific fields start after drr_type and drr_payloadlen.
+const U: usize = 8;
+const MAXNAMELEN: usize = 256;
+// Larger payloads mean a corrupt length rather than a real record.
+const MAX_PAYLOAD_LEN: u64 = 1 << 28;
+
+const DRR_BEGIN: u32 = 0;
+const DRR_OBJECT: u32 = 1;
+const DRR_FREEOBJECTS: u32 = 2;
+const DRR_WRITE: u32 = 3;
+const DRR_FREE: u32 = 4;
+const DRR_END: u32 = 5;
+const DRR_WRITE_BYREF: u32 = 6;
+const DRR_SPILL: u32 = 7;
+const DRR_WRITE_EMBEDDED: u32 = 8;
+
+const DMU_SUBSTREAM: u64 = 1;
+const DMU_COMPOUNDSTREAM: u64 = 2;
+
+/// A running fletcher-4 checksum, like `fletcher_4_incremental_native`
+/// over words in the byte order of the stream.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub struct Fletcher4 {
+    a: u64,
+    b: u64,
+    c: u64,
+    d: u64,
+}
+
+impl Fletcher4 {
+    pub fn new() -> Fletcher4 {
+        Fletcher4::default()
+    }
+
+    /// Adds `data` to the checksum. A trailing partial word is ignored, as
+    /// in ZFS.
+    pub fn update(&mut self, data: &[u8], big_endian: bool) {
+        for word in data.chunks(4).filter(|w| w.len() == 4) {
+            let bytes = [word[0], word[1], word[2], word[3]];
+            let word = if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) };
+            self.a = self.a.wrapping_add(word as u64);
+            self.b = self.b.wrapping_add(self.a);
+            self.c = self.c.wrapping_add(self.b);
+            self.d = self.d.wrapping_add(self.c);
+        }
+    }
+
+    pub fn value(&self) -> [u64; 4] {
+        [self.a, self.b, self.c, self.d]
+    }
+}
+
+/// Whether a stream holds one dataset or a package of them, as written
+/// by `zfs send -R`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum HeaderType {
+    Substream,
+    /// The BEGIN payload describes the datasets, whose substreams follow
+    /// the next END record.
+    Compound,
+}
+
+/// The `DRR_BEGIN` record that starts every stream and substream.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Begin {
+    pub header_type: HeaderType,
+    /// The `DMU_BACKUP_FEATURE_*` flags the stream uses.
+    pub features: u64,
+    pub creation_time: u64,
+    /// The `dmu_objset_type_t` of the sent dataset.
+    pub objset_type: u32,
+    pub flags: u32,
+    pub toguid: u64,
+    /// Zero for a full stream.
+    pub fromguid: u64,
+    /// The sent snapshot, like `pool/fs@snap`.
+    pub toname: String,
+    /// The packed nvlist following the record, empty if there is none.
+    /// Kept as it is in the stream, see `description`.
+    pub payload: Vec<u8>,
+}
+
+impl Begin {
+    /// Unpacks the payload, which describes the datasets of a compound
+    /// stream. Nothing checks the payload until the END record, so only
+    /// trust it once that has been read.
+    pub fn description(&self) -> Result<Option<List<'static>>> {
+        if self.payload.is_empty() {
+            return Ok(None);
+        }
+        List::unpack(&self.payload).map(Some).map_err(Error::Nvpair)
+    }
+
+    /// Replaces the payload with a description packed like `zfs send`
+    /// does.
+    pub fn set_description(&mut self, description: &List) -> Result<()> {
+        self.payload = try!(description.pack(Encoding::Xdr).map_err(Error::Nvpair));
+        Ok(())
+    }
+}
+
+/// A record of a send stream, see `sys/zfs_ioctl.h`. Payloads are kept as
+/// they are in the stream, including padding.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Record {
+    Begin(Begin),
+    Object {
+        object: u64,
+        object_type: u32,
+        bonus_type: u32,
+        block_size: u32,
+        bonus_len: u32,
+        checksum_type: u8,
+        compression: u8,
+        dnode_slots: u8,
+        toguid: u64,
+        /// The bonus buffer, padded to 8 bytes.
+        bonus: Vec<u8>,
+    },
+    FreeObjects {
+        first_object: u64,
+        num_objects: u64,
+        toguid: u64,
+    },
+    Write {
+        object: u64,
+        object_type: u32,
+        offset: u64,
+        logical_size: u64,
+        toguid: u64,
+        checksum_type: u8,
+        checksum_flags: u8,
+        compression: u8,
+        /// The dedup key: the block checksum and its properties.
+        key_checksum: [u64; 4],
+        key_prop: u64,
+        /// Nonzero only if `compression` is set.
+        compressed_size: u64,
+        data: Vec<u8>,
+    },
+    Free {
+        object: u64,
+        offset: u64,
+        /// `u64::MAX` frees to the end of the object.
+        length: u64,
+        toguid: u64,
+    },
+    End {
+        /// The checksum of the (sub)stream up to this record.
+        checksum: [u64; 4],
+        toguid: u64,
+    },
+    WriteByRef {
+        object: u64,
+        offset: u64,
+        length: u64,
+        toguid: u64,
+        ref_guid: u64,
+        ref_object: u64,
+        ref_offset: u64,
+        checksum_type: u8,
+        checksum_flags: u8,
+        key_checksum: [u64; 4],
+        key_prop: u64,
+    },
+    Spill {
+        object: u64,
+        length: u64,
+        toguid: u64,
+        data: Vec<u8>,
+    },
+    WriteEmbedded {
+        object: u64,
+        offset: u64,
+        length: u64,
+        toguid: u64,
+        compression: u8,
+        embedded_type: u8,
+        logical_size: u32,
+        physical_size: u32,
+        /// The embedded data, padded to 8 bytes.
+        data: Vec<u8>,
+    },
+}
+
+/// Decodes header fields in the byte order of the stream.
+struct Fields<'a> {
+    buf: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn u8(&self, offset: usize) -> u8 {
+        self.buf[offset]
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        let b = &self.buf[offset..offset + 4];
+        let bytes = [b[0], b[1], b[2], b[3]];
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+
+    fn u64(&self, offset: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.buf[offset..offset + 8]);
+        if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) }
+    }
+
+    fn checksum(&self, offset: usize) -> [u64; 4] {
+        [self.u64(offset), self.u64(offset + 8), self.u64(offset + 16), self.u64(offset + 24)]
+    }
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidStream(reason)
+}
+
+/// Reads a BEGIN record header, without its payload. Also tells the byte
+/// order of the stream: true if it was written on a big endian host.
+pub fn parse_begin(header: &[u8]) -> Result<(Begin, bool)> {
+    if header.len() < RECORD_LEN {
+        return Err(invalid("short BEGIN record".to_owned()));
+    }
+    let big_endian = match (Fields { buf: header, big_endian: false }).u64(U) {
+        DMU_BACKUP_MAGIC => false,
+        magic if magic == DMU_BACKUP_MAGIC.swap_bytes() => true,
+        magic => return Err(invalid(format!("bad magic {:#x}", magic)))
+    };
+    let f = Fields { buf: header, big_endian: big_endian };
+    if f.u32(0) != DRR_BEGIN {
+        return Err(invalid("stream does not start with a BEGIN record".to_owned()));
+    }
+    let versioninfo = f.u64(U + 8);
+    let header_type = match versioninfo & 3 {
+        DMU_SUBSTREAM => HeaderType::Substream,
+        DMU_COMPOUNDSTREAM => HeaderType::Compound,
+        t => return Err(invalid(format!("unknown stream header type {}", t)))
+    };
+    let toname = &header[U + 48..U + 48 + MAXNAMELEN];
+    let len = toname.iter().position(|&b| b == 0).unwrap_or(MAXNAMELEN);
+    let begin = Begin {
+        header_type: header_type,
+        features: (versioninfo >> 2) & ((1 << 30) - 1),
+        creation_time: f.u64(U + 16),
+        objset_type: f.u32(U + 24),
+        flags: f.u32(U + 28),
+        toguid: f.u64(U + 32),
+        fromguid: f.u64(U + 40),
+        toname: String::from_utf8_lossy(&toname[..len]).into_owned(),
+        payload: Vec::new(),
+    };
+    Ok((begin, big_endian))
+}
+
+/// The payload length of a record, which only BEGIN records store.
+fn payload_len(f: &Fields) -> u64 {
+    let round8 = |n: u64| (n + 7) & !7;
+    match f.u32(0) {
+        DRR_BEGIN => f.u32(4) as u64,
+        DRR_OBJECT => round8(f.u32(U + 20) as u64),
+        DRR_WRITE => if f.u8(U + 42) != 0 { f.u64(U + 88) } else { f.u64(U + 24) },
+        DRR_SPILL => f.u64(U + 8),
+        DRR_WRITE_EMBEDDED => round8(f.u32(U + 44) as u64),
+        _ => 0
+    }
+}
+
+fn decode(f: &Fields, payload: Vec<u8>) -> Result<Record> {
+    Ok(match f.u32(0) {
+        DRR_BEGIN => {
+            let (mut begin, _) = try!(parse_begin(f.buf));
+            begin.payload = payload;
+            Record::Begin(begin)
+        },
+        DRR_OBJECT => Record::Object {
+            object: f.u64(U),
+            object_type: f.u32(U + 8),
+            bonus_type: f.u32(U + 12),
+            block_size: f.u32(U + 16),
+            bonus_len: f.u32(U + 20),
+            checksum_type: f.u8(U + 24),
+            compression: f.u8(U + 25),
+            dnode_slots: f.u8(U + 26),
+            toguid: f.u64(U + 32),
+            bonus: payload,
+        },
+        DRR_FREEOBJECTS => Record::FreeObjects {
+            first_object: f.u64(U),
+            num_objects: f.u64(U + 8),
+            toguid: f.u64(U + 16),
+        },
+        DRR_WRITE => Record::Write {
+            object: f.u64(U),
+            object_type: f.u32(U + 8),
+            offset: f.u64(U + 16),
+            logical_size: f.u64(U + 24),
+            toguid: f.u64(U + 32),
+            checksum_type: f.u8(U + 40),
+            checksum_flags: f.u8(U + 41),
+            compression: f.u8(U + 42),
+            key_checksum: f.checksum(U + 48),
+            key_prop: f.u64(U + 80),
+            compressed_size: f.u64(U + 88),
+            data: payload,
+        },
+        DRR_FREE => Record::Free {
+            object: f.u64(U),
+            offset: f.u64(U + 8),
+            length: f.u64(U + 16),
+            toguid: f.u64(U + 24),
+        },
+        DRR_END => Record::End {
+            checksum: f.checksum(U),
+            toguid: f.u64(U + 32),
+        },
+        DRR_WRITE_BYREF => Record::WriteByRef {
+            object: f.u64(U),
+            offset: f.u64(U + 8),
+            length: f.u64(U + 16),
+            toguid: f.u64(U + 24),
+            ref_guid: f.u64(U + 32),
+            ref_object: f.u64(U + 40),
+            ref_offset: f.u64(U + 48),
+            checksum_type: f.u8(U + 56),
+            checksum_flags: f.u8(U + 57),
+            key_checksum: f.checksum(U + 64),
+            key_prop: f.u64(U + 96),
+        },
+        DRR_SPILL => Record::Spill {
+            object: f.u64(U),
+            length: f.u64(U + 8),
+            toguid: f.u64(U + 16),
+            data: payload,
+        },
+        DRR_WRITE_EMBEDDED => Record::WriteEmbedded {
+            object: f.u64(U),
+            offset: f.u64(U + 8),
+            length: f.u64(U + 16),
+            toguid: f.u64(U + 24),
+            compression: f.u8(U + 32),
+            embedded_type: f.u8(U + 33),
+            logical_size: f.u32(U + 40),
+            physical_size: f.u32(U + 44),
+            data: payload,
+        },
+        t => return Err(invalid(format!("unknown record type {}", t)))
+    })
+}
+
+/// Reads until `buf` is full or the reader is exhausted, returning how
+/// much was read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(filled)
+}
+
+/// Iterates over the records of a send stream, verifying checksums as it
+/// goes. Compound and concatenated streams are read to the end. After an
+/// error the iterator ends.
+pub struct StreamReader<R> {
+    reader: R,
+    big_endian: bool,
+    started: bool,
+    checksum: Fletcher4,
+    offset: u64,
+    failed: bool,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(reader: R) -> StreamReader<R> {
+        StreamReader {
+            reader: reader,
+            big_endian: false,
+            started: false,
+            checksum: Fletcher4::new(),
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// The number of bytes read so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Whether the stream was written on a big endian host.
+    pub fn big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>> {
+        let start = self.offset;
+        let mut header = [0u8; RECORD_LEN];
+        match try!(read_full(&mut self.reader, &mut header).map_err(Error::Io)) {
+            0 => return Ok(None),
+            RECORD_LEN => (),
+            _ => return Err(invalid(format!("stream ends inside the record at byte {}", start)))
+        }
+        self.offset += RECORD_LEN as u64;
+
+        // Every substream starts with a BEGIN record, whose magic tells the
+        // byte order. The type reads the same either way.
+        if (Fields { buf: &header, big_endian: false }).u32(0) == DRR_BEGIN {
+            self.big_endian = try!(parse_begin(&header)).1;
+            self.started = true;
+        } else if !self.started {
+            return Err(invalid("stream does not start with a BEGIN record".to_owned()));
+        }
+        let big_endian = self.big_endian;
+        let f = Fields { buf: &header, big_endian: big_endian };
+        let drr_type = f.u32(0);
+
+        // Each record stores the checksum of everything before it, except
+        // BEGIN records, whose name overlaps that field. Zero means the
+        // writer did not store one.
+        let before = self.checksum;
+        self.checksum.update(&header[..CHECKSUM_OFFSET], big_endian);
+        let stored = f.checksum(CHECKSUM_OFFSET);
+        if drr_type != DRR_BEGIN && stored != [0; 4] && stored != self.checksum.value() {
+            return Err(Error::StreamChecksum(start));
+        }
+        self.checksum.update(&header[CHECKSUM_OFFSET..], big_endian);
+
+        let len = payload_len(&f);
+        if len > MAX_PAYLOAD_LEN {
+            return Err(invalid(format!("payload of {} bytes in the record at byte {}", len, start)));
+        }
+        let mut payload = vec![0; len as usize];
+        if try!(read_full(&mut self.reader, &mut payload).map_err(Error::Io)) < payload.len() {
+            return Err(invalid(format!("stream ends inside the payload of the record at byte {}", start)));
+        }
+        self.offset += len;
+        self.checksum.update(&payload, big_endian);
+
+        if drr_type == DRR_END {
+            // The END record holds the checksum of its whole substream, and
+            // the next one starts over.
+            if f.checksum(U) != before.value() {
+                return Err(Error::StreamChecksum(start));
+            }
+            self.checksum = Fletcher4::new();
+        }
+        decode(&f, payload).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for StreamReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Result<Record>> {
+        if self.failed {
+            return None;
+        }
+        match self.read_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => None,
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Encodes header fields in the byte order of the stream.
+struct FieldsMut<'a> {
+    buf: &'a mut [u8],
+    big_endian: bool,
+}
+
+impl<'a> FieldsMut<'a> {
+    fn u8(&mut self, offset: usize, value: u8) {
+        self.buf[offset] = value;
+    }
+
+    fn u32(&mut self, offset: usize, value: u32) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.buf[offset..offset + 4].copy_from_slice(&bytes);
+    }
+
+    fn u64(&mut self, offset: usize, value: u64) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.buf[offset..offset + 8].copy_from_slice(&bytes);
+    }
+
+    fn checksum(&mut self, offset: usize, value: &[u64; 4]) {
+        for (i, word) in value.iter().enumerate() {
+            self.u64(offset + 8 * i, *word);
+        }
+    }
+}
+
+/// Encodes a record header and returns its payload. The running checksum
+/// is left to the caller.
+fn encode<'r>(record: &'r Record, header: &mut [u8; RECORD_LEN], big_endian: bool) -> Result<Cow<'r, [u8]>> {
+    let mut f = FieldsMut { buf: header, big_endian: big_endian };
+    let payload: Cow<[u8]> = match *record {
+        Record::Begin(ref begin) => {
+            if begin.toname.len() >= MAXNAMELEN {
+                return Err(invalid(format!("name '{}' is too long", begin.toname)));
+            }
+            let header_type = match begin.header_type {
+                HeaderType::Substream => DMU_SUBSTREAM,
+                HeaderType::Compound => DMU_COMPOUNDSTREAM,
+            };
+            f.u32(0, DRR_BEGIN);
+            f.u32(4, begin.payload.len() as u32);
+            f.u64(U, DMU_BACKUP_MAGIC);
+            f.u64(U + 8, header_type | (begin.features << 2));
+            f.u64(U + 16, begin.creation_time);
+            f.u32(U + 24, begin.objset_type);
+            f.u32(U + 28, begin.flags);
+            f.u64(U + 32, begin.toguid);
+            f.u64(U + 40, begin.fromguid);
+            f.buf[U + 48..U + 48 + begin.toname.len()].copy_from_slice(begin.toname.as_bytes());
+            Cow::Borrowed(&begin.payload[..])
+        },
+        Record::Object { object, object_type, bonus_type, block_size, bonus_len, checksum_type, compression,
+                         dnode_slots, toguid, ref bonus } => {
+            f.u32(0, DRR_OBJECT);
+            f.u64(U, object);
+            f.u32(U + 8, object_type);
+            f.u32(U + 12, bonus_type);
+            f.u32(U + 16, block_size);
+            f.u32(U + 20, bonus_len);
+            f.u8(U + 24, checksum_type);
+            f.u8(U + 25, compression);
+            f.u8(U + 26, dnode_slots);
+            f.u64(U + 32, toguid);
+            Cow::Borrowed(&bonus[..])
+        },
+        Record::FreeObjects { first_object, num_objects, toguid } => {
+            f.u32(0, DRR_FREEOBJECTS);
+            f.u64(U, first_object);
+            f.u64(U + 8, num_objects);
+            f.u64(U + 16, toguid);
+            Cow::Borrowed(&[][..])
+        },
+        Record::Write { object, object_type, offset, logical_size, toguid, checksum_type, checksum_flags,
+                        compression, ref key_checksum, key_prop, compressed_size, ref data } => {
+            f.u32(0, DRR_WRITE);
+            f.u64(U, object);
+            f.u32(U + 8, object_type);
+            f.u64(U + 16, offset);
+            f.u64(U + 24, logical_size);
+            f.u64(U + 32, toguid);
+            f.u8(U + 40, checksum_type);
+            f.u8(U + 41, checksum_flags);
+            f.u8(U + 42, compression);
+            f.checksum(U + 48, key_checksum);
+            f.u64(U + 80, key_prop);
+            f.u64(U + 88, compressed_size);
+            Cow::Borrowed(&data[..])
+        },
+        Record::Free { object, offset, length, toguid } => {
+            f.u32(0, DRR_FREE);
+            f.u64(U, object);
+            f.u64(U + 8, offset);
+            f.u64(U + 16, length);
+            f.u64(U + 24, toguid);
+            Cow::Borrowed(&[][..])
+        },
+        Record::End { toguid, .. } => {
+            // The checksum depends on what was written before, so the
+            // writer fills it in.
+            f.u32(0, DRR_END);
+            f.u64(U + 32, toguid);
+            Cow::Borrowed(&[][..])
+        },
+        Record::WriteByRef { object, offset, length, toguid, ref_guid, ref_object, ref_offset, checksum_type,
+                             checksum_flags, ref key_checksum, key_prop } => {
+            f.u32(0, DRR_WRITE_BYREF);
+            f.u64(U, object);
+            f.u64(U + 8, offset);
+            f.u64(U + 16, length);
+            f.u64(U + 24, toguid);
+            f.u64(U + 32, ref_guid);
+            f.u64(U + 40, ref_object);
+            f.u64(U + 48, ref_offset);
+            f.u8(U + 56, checksum_type);
+            f.u8(U + 57, checksum_flags);
+            f.checksum(U + 64, key_checksum);
+            f.u64(U + 96, key_prop);
+            Cow::Borrowed(&[][..])
+        },
+        Record::Spill { object, length, toguid, ref data } => {
+            f.u32(0, DRR_SPILL);
+            f.u64(U, object);
+            f.u64(U + 8, length);
+            f.u64(U + 16, toguid);
+            Cow::Borrowed(&data[..])
+        },
+        Record::WriteEmbedded { object, offset, length, toguid, compression, embedded_type, logical_size,
+                                physical_size, ref data } => {
+            f.u32(0, DRR_WRITE_EMBEDDED);
+            f.u64(U, object);
+            f.u64(U + 8, offset);
+            f.u64(U + 16, length);
+            f.u64(U + 24, toguid);
+            f.u8(U + 32, compression);
+            f.u8(U + 33, embedded_type);
+            f.u32(U + 40, logical_size);
+            f.u32(U + 44, physical_size);
+            Cow::Borrowed(&data[..])
+        },
+    };
+    Ok(payload)
+}
+
+/// Writes records as a send stream, computing the checksums `zfs receive`
+/// verifies. The checksum of `Record::End` is ignored and recomputed.
+pub struct StreamWriter<W> {
+    writer: W,
+    big_endian: bool,
+    checksum: Fletcher4,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Writes in the given byte order, receivers accept either.
+    pub fn new(writer: W, big_endian: bool) -> StreamWriter<W> {
+        StreamWriter {
+            writer: writer,
+            big_endian: big_endian,
+            checksum: Fletcher4::new(),
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        let mut header = [0u8; RECORD_LEN];
+        let payload = try!(encode(record, &mut header, self.big_endian));
+        let big_endian = self.big_endian;
+        let expected = payload_len(&Fields { buf: &header, big_endian: big_endian });
+        if payload.len() as u64 != expected {
+            return Err(invalid(format!("{} bytes of data where the record needs {}", payload.len(), expected)));
+        }
+
+        let is_begin = match *record { Record::Begin(_) => true, _ => false };
+        let is_end = match *record { Record::End { .. } => true, _ => false };
+        if is_end {
+            (FieldsMut { buf: &mut header, big_endian: big_endian }).checksum(U, &self.checksum.value());
+        }
+        self.checksum.update(&header[..CHECKSUM_OFFSET], big_endian);
+        if !is_begin {
+            (FieldsMut { buf: &mut header, big_endian: big_endian }).checksum(CHECKSUM_OFFSET, &self.checksum.value());
+        }
+        self.checksum.update(&header[CHECKSUM_OFFSET..], big_endian);
+        self.checksum.update(&payload, big_endian);
+        if is_end {
+            self.checksum = Fletcher4::new();
+        }
+
+        try!(self.writer.write_all(&header).map_err(Error::Io));
+        try!(self.writer.write_all(&payload).map_err(Error::Io));
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        try!(self.writer.flush().map_err(Error::Io));
+        Ok(self.writer)
+    }
+}
+
+/// Copies a send stream from `reader` to `writer`, passing every record
+/// through `f`, which may change it or drop it by returning `None`. The
+/// output keeps the byte order of the input and has fresh checksums.
+///
+/// To drop FREE records:
+///
+/// ```no_run
+/// # use zfs::sendstream::{rewrite, Record};
+/// # let (input, output) = (::std::io::empty(), ::std::io::sink());
+/// rewrite(input, output, |record| match record {
+///     Record::Free { .. } => None,
+///     record => Some(record)
+/// }).unwrap();
+/// ```
+pub fn rewrite<R, W, F>(reader: R, writer: W, mut f: F) -> Result<W>
+    where R: Read, W: Write, F: FnMut(Record) -> Option<Record>
+{
+    let mut records = StreamReader::new(reader);
+    let first = match records.next() {
+        Some(record) => try!(record),
+        None => return Ok(writer)
+    };
+    let mut out = StreamWriter::new(writer, records.big_endian());
+    if let Some(record) = f(first) {
+        try!(out.write(&record));
+    }
+    for record in records {
+        if let Some(record) = f(try!(record)) {
+            try!(out.write(&record));
+        }
+    }
+    out.into_inner()
+}
+
+/// Splits a stream into its substreams, each written to the writer `open`
+/// returns for its BEGIN record. The header of a compound stream is
+/// dropped, so every output is a plain stream `zfs receive` accepts.
+/// Returns the number of substreams.
+pub fn split_substreams<R, W, F>(reader: R, mut open: F) -> Result<usize>
+    where R: Read, W: Write, F: FnMut(&Begin) -> Result<W>
+{
+    let mut records = StreamReader::new(reader);
+    let mut out: Option<StreamWriter<W>> = None;
+    let mut count = 0;
+    while let Some(record) = records.next() {
+        let record = try!(record);
+        let is_end = match record {
+            Record::Begin(ref begin) if begin.header_type == HeaderType::Compound => continue,
+            Record::Begin(ref begin) => {
+                out = Some(StreamWriter::new(try!(open(begin)), records.big_endian()));
+                count += 1;
+                false
+            },
+            Record::End { .. } => true,
+            _ => false
+        };
+        // Records outside a substream, like the END of a compound header,
+        // are dropped.
+        if let Some(ref mut writer) = out {
+            try!(writer.write(&record));
+        }
+        if is_end {
+            if let Some(writer) = out.take() {
+                try!(writer.into_inner());
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rewrite, split_substreams, Fletcher4, HeaderType, Record, StreamReader, StreamWriter};
+    use super::{DMU_BACKUP_MAGIC, RECORD_LEN};
+    use super::{CHECKSUM_OFFSET, DRR_BEGIN, DRR_END, DRR_FREE, DRR_OBJECT, DRR_WRITE, U};
+    use super::super::{Error, Result};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use nvpair::{Encoding, List, Pair, PairValue, Uniqueness};
+
+    /// Builds streams the way `dump_record` in dmu_send.c writes them.
+    struct Builder {
+        out: Vec<u8>,
+        checksum: Fletcher4,
+        big_endian: bool,
+    }
+
+    impl Builder {
+        fn new(big_endian: bool) -> Builder {
+            Builder { out: Vec::new(), checksum: Fletcher4::new(), big_endian: big_endian }
+        }
+
+        fn put(&self, header: &mut [u8], offset: usize, bytes: &[u8]) {
+            let mut bytes = bytes.to_vec();
+            if self.big_endian {
+                bytes.reverse();
+            }
+            header[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        fn record(&mut self, drr_type: u32, fields: &[(usize, u64)], payload: &[u8]) {
+            self.named_record(drr_type, fields, &[], "", payload)
+        }
+
+        fn named_record(&mut self, drr_type: u32, fields: &[(usize, u64)], words: &[(usize, u32)], name: &str,
+                        payload: &[u8]) {
+            let mut header = [0u8; RECORD_LEN];
+            header[U + 48..U + 48 + name.len()].copy_from_slice(name.as_bytes());
+            self.put(&mut header, 0, &drr_type.to_le_bytes());
+            if drr_type == DRR_BEGIN {
+                self.put(&mut header, 4, &(payload.len() as u32).to_le_bytes());
+            }
+            for &(offset, value) in fields {
+                self.put(&mut header, offset, &value.to_le_bytes());
+            }
+            for &(offset, value) in words {
+                self.put(&mut header, offset, &value.to_le_bytes());
+            }
+            if drr_type == DRR_END {
+                for (i, word) in self.checksum.value().iter().enumerate() {
+                    self.put(&mut header, U + 8 * i, &word.to_le_bytes());
+                }
+            }
+            self.checksum.update(&header[..CHECKSUM_OFFSET], self.big_endian);
+            if drr_type != DRR_BEGIN {
+                for (i, word) in self.checksum.value().iter().enumerate() {
+                    self.put(&mut header, CHECKSUM_OFFSET + 8 * i, &word.to_le_bytes());
+                }
+            }
+            self.checksum.update(&header[CHECKSUM_OFFSET..], self.big_endian);
+            self.checksum.update(payload, self.big_endian);
+            self.out.extend_from_slice(&header);
+            self.out.extend_from_slice(payload);
+            if drr_type == DRR_END {
+                self.checksum = Fletcher4::new();
+            }
+        }
+
+        fn begin(&mut self, name: &str) {
+            self.named_record(DRR_BEGIN, &[(U, DMU_BACKUP_MAGIC), (U + 8, 1 | (4 << 2)), (U + 32, 7)], &[], name, &[]);
+        }
+    }
+
+    fn sample(big_endian: bool) -> Vec<u8> {
+        let mut b = Builder::new(big_endian);
+        b.begin("tank/fs@snap");
+        b.named_record(DRR_OBJECT, &[(U, 8)], &[(U + 20, 5)], "", &[1, 2, 3, 4, 5, 0, 0, 0]);
+        b.record(DRR_WRITE, &[(U, 8), (U + 24, 16)], &[9; 16]);
+        b.record(DRR_FREE, &[(U, 8), (U + 8, 16), (U + 16, !0)], &[]);
+        b.record(DRR_END, &[(U + 32, 7)], &[]);
+        b.out
+    }
+
+    #[test]
+    fn fletcher4() {
+        let mut checksum = Fletcher4::new();
+        checksum.update(&[1, 0, 0, 0, 2, 0, 0, 0, 0xff], false);
+        assert_eq!(checksum.value(), [3, 4, 5, 6]);
+        let mut big = Fletcher4::new();
+        big.update(&[0, 0, 0, 1, 0, 0, 0, 2], true);
+        assert_eq!(big.value(), checksum.value());
+    }
+
+    #[test]
+    fn reads_records() {
+        for &big_endian in &[false, true] {
+            let stream = sample(big_endian);
+            let records: Vec<Record> = StreamReader::new(&stream[..]).map(|r| r.unwrap()).collect();
+            assert_eq!(records.len(), 5);
+            match records[0] {
+                Record::Begin(ref begin) => {
+                    assert_eq!(begin.toname, "tank/fs@snap");
+                    assert_eq!(begin.header_type, HeaderType::Substream);
+                    assert_eq!(begin.features, 4);
+                    assert_eq!(begin.toguid, 7);
+                },
+                ref r => panic!("unexpected record {:?}", r)
+            }
+            match records[1] {
+                Record::Object { object: 8, bonus_len: 5, ref bonus, .. } => assert_eq!(bonus.len(), 8),
+                ref r => panic!("unexpected record {:?}", r)
+            }
+            match records[2] {
+                Record::Write { logical_size: 16, ref data, .. } => assert_eq!(data, &vec![9; 16]),
+                ref r => panic!("unexpected record {:?}", r)
+            }
+            match records[3] {
+                Record::Free { offset: 16, length, .. } => assert_eq!(length, !0),
+                ref r => panic!("unexpected record {:?}", r)
+            }
+            match records[4] {
+                Record::End { toguid: 7, .. } => (),
+                ref r => panic!("unexpected record {:?}", r)
+            }
+        }
+    }
+
+    #[test]
+    fn reads_concatenated_substreams() {
+        let mut stream = sample(false);
+        stream.extend(sample(true));
+        let mut reader = StreamReader::new(&stream[..]);
+        assert_eq!(reader.by_ref().filter(|r| r.is_ok()).count(), 10);
+        assert_eq!(reader.offset(), stream.len() as u64);
+    }
+
+    #[test]
+    fn reads_compound_streams() {
+        let mut description = List::new(Uniqueness::Name);
+        description.add(Pair::new("tosnap", PairValue::String("snap".to_owned())));
+        let packed = description.pack(Encoding::Xdr).unwrap();
+
+        let mut b = Builder::new(false);
+        b.named_record(DRR_BEGIN, &[(U, DMU_BACKUP_MAGIC), (U + 8, 2)], &[], "tank@snap", &packed);
+        b.record(DRR_END, &[], &[]);
+        let mut stream = b.out;
+        stream.extend(sample(false));
+        let mut b = Builder::new(false);
+        b.record(DRR_END, &[], &[]);
+        stream.extend(b.out);
+
+        let records: Vec<Record> = StreamReader::new(&stream[..]).map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 8);
+        match records[0] {
+            Record::Begin(ref begin) => {
+                assert_eq!(begin.header_type, HeaderType::Compound);
+                assert_eq!(begin.description().unwrap().and_then(|p| p.get("tosnap").cloned()),
+                           Some(PairValue::String("snap".to_owned())));
+            },
+            ref r => panic!("unexpected record {:?}", r)
+        }
+    }
+
+    #[test]
+    fn detects_corrupt_compound_payloads() {
+        let mut description = List::new(Uniqueness::Name);
+        description.add(Pair::new("tosnap", PairValue::String("snap".to_owned())));
+        let packed = description.pack(Encoding::Xdr).unwrap();
+        let mut b = Builder::new(false);
+        b.named_record(DRR_BEGIN, &[(U, DMU_BACKUP_MAGIC), (U + 8, 2)], &[], "tank@snap", &packed);
+        b.record(DRR_END, &[], &[]);
+
+        for at in 0..packed.len() {
+            let mut stream = b.out.clone();
+            stream[RECORD_LEN + at] ^= 0x80;
+            let results: Vec<Result<Record>> = StreamReader::new(&stream[..]).collect();
+            if let Ok(Record::Begin(ref begin)) = results[0] {
+                // Decoding must fail cleanly or give some list, never panic.
+                let _ = begin.description();
+            }
+            match results.last() {
+                Some(&Err(Error::StreamChecksum(_))) => (),
+                r => panic!("unexpected result {:?} with byte {} flipped", r, at)
+            }
+        }
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut stream = sample(false);
+        // A byte of the WRITE payload.
+        let at = 3 * RECORD_LEN + 8 + 4;
+        stream[at] ^= 1;
+        let results: Vec<Result<Record>> = StreamReader::new(&stream[..]).collect();
+        assert_eq!(results.len(), 4);
+        match results[3] {
+            Err(Error::StreamChecksum(offset)) => assert_eq!(offset, 3 * RECORD_LEN as u64 + 8 + 16),
+            ref r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_streams() {
+        let stream = sample(false);
+        let last = StreamReader::new(&stream[..stream.len() - 10]).last().unwrap();
+        match last {
+            Err(Error::InvalidStream(_)) => (),
+            r => panic!("unexpected result {:?}", r)
+        }
+        let mut stream = sample(false);
+        stream[U] ^= 1;
+        match StreamReader::new(&stream[..]).next() {
+            Some(Err(Error::InvalidStream(_))) => (),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        for &big_endian in &[false, true] {
+            let stream = sample(big_endian);
+            let mut writer = StreamWriter::new(Vec::new(), big_endian);
+            for record in StreamReader::new(&stream[..]) {
+                writer.write(&record.unwrap()).unwrap();
+            }
+            assert_eq!(writer.into_inner().unwrap(), stream);
+        }
+    }
+
+    #[test]
+    fn rewrites_records() {
+        let stream = sample(true);
+        let out = rewrite(&stream[..], Vec::new(), |record| match record {
+            Record::Free { .. } => None,
+            Record::Begin(mut begin) => {
+                begin.toname = "backup/fs@snap".to_owned();
+                Some(Record::Begin(begin))
+            },
+            record => Some(record)
+        }).unwrap();
+        let records: Vec<Record> = StreamReader::new(&out[..]).map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 4);
+        match records[0] {
+            Record::Begin(ref begin) => assert_eq!(begin.toname, "backup/fs@snap"),
+            ref r => panic!("unexpected record {:?}", r)
+        }
+    }
+
+    #[test]
+    fn rejects_inconsistent_payloads() {
+        let record = Record::Spill { object: 1, length: 16, toguid: 7, data: vec![0; 8] };
+        match StreamWriter::new(Vec::new(), false).write(&record) {
+            Err(Error::InvalidStream(_)) => (),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[derive(Clone)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn splits_compound_streams() {
+        let mut b = Builder::new(false);
+        b.named_record(DRR_BEGIN, &[(U, DMU_BACKUP_MAGIC), (U + 8, 2)], &[], "tank@snap", &[]);
+        b.record(DRR_END, &[], &[]);
+        let mut stream = b.out;
+        stream.extend(sample(false));
+        stream.extend(sample(false));
+        let mut b = Builder::new(false);
+        b.record(DRR_END, &[], &[]);
+        stream.extend(b.out);
+
+        let outputs = RefCell::new(Vec::new());
+        let count = split_substreams(&stream[..], |_| {
+            let output = Shared(Rc::new(RefCell::new(Vec::new())));
+            outputs.borrow_mut().push(output.clone());
+            Ok(output)
+        }).unwrap();
+        assert_eq!(count, 2);
+        for output in outputs.borrow().iter() {
+            let output = output.0.borrow();
+            assert_eq!(&output[..], &sample(false)[..]);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::path::PathBuf;
+use std::ptr;
+
+use nvpair::{List, NvList, Pair, PairValue, Uniqueness};
+use zfs_sys;
+
+use super::{Pool, PoolProp};
+use super::super::{to_c_string, Error, Handle, PropValue, Result};
+use super::super::dataset::{DatasetProps, DatasetType};
+
+/// A device or group of devices, like the vdev arguments of
+/// `zpool create`. Groups consist of `Disk` and `File` devices.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Vdev {
+    /// A disk or partition by absolute path. Whole disks are used as
+    /// they are, not partitioned like `zpool create` does.
+    Disk(PathBuf),
+    /// An existing file by absolute path, at least 64M large.
+    File(PathBuf),
+    Mirror(Vec<Vdev>),
+    RaidZ1(Vec<Vdev>),
+    RaidZ2(Vec<Vdev>),
+    RaidZ3(Vec<Vdev>),
+    /// Distributed parity RAID with `data` data devices per group and
+    /// `spares` distributed spares. Needs a libzfs with dRAID.
+    Draid { parity: u64, data: u64, spares: u64, children: Vec<Vdev> },
+}
+
+impl Vdev {
+    fn is_leaf(&self) -> bool {
+        match *self {
+            Vdev::Disk(_) | Vdev::File(_) => true,
+            _ => false
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| Err(Error::InvalidArgument(reason));
+        let (kind, min, children) = match *self {
+            Vdev::Disk(ref path) | Vdev::File(ref path) => {
+                if !path.is_absolute() {
+                    return invalid(format!("'{}' is not an absolute path", path.display()));
+                }
+                return Ok(());
+            },
+            Vdev::Mirror(ref children) => ("mirror", 2, children),
+            Vdev::RaidZ1(ref children) => ("raidz1", 2, children),
+            Vdev::RaidZ2(ref children) => ("raidz2", 3, children),
+            Vdev::RaidZ3(ref children) => ("raidz3", 4, children),
+            Vdev::Draid { parity, data, spares, ref children } => {
+                if parity < 1 || parity > 3 || data < 1 {
+                    return invalid("draid needs a parity of 1 to 3 and at least one data device".to_owned());
+                }
+                ("draid", parity + data + spares, children)
+            },
+        };
+        if (children.len() as u64) < min {
+            return invalid(format!("{} needs at least {} devices", kind, min));
+        }
+        for child in children {
+            if !child.is_leaf() {
+                return invalid(format!("{} can only contain disks and files", kind));
+            }
+            try!(child.validate());
+        }
+        Ok(())
+    }
+
+    /// The vdev nvlist `zpool_create` expects, like `zpool create` builds.
+    fn to_list(&self) -> List<'static> {
+        let mut list = List::new(Uniqueness::Name);
+        let children = match *self {
+            Vdev::Disk(ref path) => {
+                list.add(Pair::new("type", PairValue::String("disk".to_owned())));
+                list.add(Pair::new("path", PairValue::String(path.to_string_lossy().into_owned())));
+                list.add(Pair::new("whole_disk", PairValue::Uint64(0)));
+                return list;
+            },
+            Vdev::File(ref path) => {
+                list.add(Pair::new("type", PairValue::String("file".to_owned())));
+                list.add(Pair::new("path", PairValue::String(path.to_string_lossy().into_owned())));
+                return list;
+            },
+            Vdev::Mirror(ref children) => {
+                list.add(Pair::new("type", PairValue::String("mirror".to_owned())));
+                children
+            },
+            Vdev::RaidZ1(ref children) | Vdev::RaidZ2(ref children) | Vdev::RaidZ3(ref children) => {
+                let parity = match *self {
+                    Vdev::RaidZ1(_) => 1,
+                    Vdev::RaidZ2(_) => 2,
+                    _ => 3
+                };
+                list.add(Pair::new("type", PairValue::String("raidz".to_owned())));
+                list.add(Pair::new("nparity", PairValue::Uint64(parity)));
+                children
+            },
+            Vdev::Draid { parity, data, spares, ref children } => {
+                // Like `zpool create`, use the fewest groups that fill the
+                // non-spare space evenly.
+                let width = children.len() as u64 - spares;
+                let mut groups = 1;
+                while groups * (data + parity) % width != 0 {
+                    groups += 1;
+                }
+                list.add(Pair::new("type", PairValue::String("draid".to_owned())));
+                list.add(Pair::new("nparity", PairValue::Uint64(parity)));
+                list.add(Pair::new("draid_ndata", PairValue::Uint64(data)));
+                list.add(Pair::new("draid_nspares", PairValue::Uint64(spares)));
+                list.add(Pair::new("draid_ngroups", PairValue::Uint64(groups)));
+                children
+            },
+        };
+        list.add(Pair::new("children", PairValue::NvListArray(children.iter().map(Vdev::to_list).collect())));
+        list
+    }
+}
+
+/// The layout of a new pool: the vdevs data is striped over, and the
+/// vdevs with a special purpose.
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct Topology {
+    data: Vec<Vdev>,
+    logs: Vec<Vdev>,
+    cache: Vec<Vdev>,
+    special: Vec<Vdev>,
+    dedup: Vec<Vdev>,
+    spares: Vec<Vdev>,
+}
+
+impl Topology {
+    pub fn new() -> Topology {
+        Topology::default()
+    }
+
+    /// Adds a vdev that holds data.
+    pub fn vdev(mut self, vdev: Vdev) -> Topology {
+        self.data.push(vdev);
+        self
+    }
+
+    /// Adds an intent log device or mirror, like `log`.
+    pub fn log(mut self, vdev: Vdev) -> Topology {
+        self.logs.push(vdev);
+        self
+    }
+
+    /// Adds a cache device, like `cache`.
+    pub fn cache(mut self, vdev: Vdev) -> Topology {
+        self.cache.push(vdev);
+        self
+    }
+
+    /// Adds a vdev for metadata and small blocks, like `special`. Needs a
+    /// libzfs with allocation classes.
+    pub fn special(mut self, vdev: Vdev) -> Topology {
+        self.special.push(vdev);
+        self
+    }
+
+    /// Adds a vdev for the dedup table, like `dedup`. Needs a libzfs with
+    /// allocation classes.
+    pub fn dedup(mut self, vdev: Vdev) -> Topology {
+        self.dedup.push(vdev);
+        self
+    }
+
+    /// Adds a hot spare, like `spare`.
+    pub fn spare(mut self, vdev: Vdev) -> Topology {
+        self.spares.push(vdev);
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.data.is_empty() {
+            return Err(Error::InvalidArgument("a pool needs at least one data vdev".to_owned()));
+        }
+        let classes: [(&str, &[Vdev]); 6] = [
+            ("data", &self.data), ("log", &self.logs), ("cache", &self.cache),
+            ("special", &self.special), ("dedup", &self.dedup), ("spare", &self.spares),
+        ];
+        for &(class, vdevs) in &classes {
+            for vdev in vdevs {
+                let allowed = match (class, vdev) {
+                    ("data", _) => true,
+                    ("cache", v) | ("spare", v) => v.is_leaf(),
+                    (_, &Vdev::Draid { .. }) => false,
+                    ("log", &Vdev::Mirror(_)) => true,
+                    ("log", v) => v.is_leaf(),
+                    _ => true
+                };
+                if !allowed {
+                    return Err(Error::InvalidArgument(format!("{:?} cannot be a {} vdev", vdev, class)));
+                }
+                try!(vdev.validate());
+            }
+        }
+        Ok(())
+    }
+
+    /// The `nvroot` nvlist `zpool_create` expects.
+    fn to_list(&self) -> List<'static> {
+        let top_level = |vdev: &Vdev, is_log: bool, bias: Option<&str>| {
+            let mut list = vdev.to_list();
+            list.add(Pair::new("is_log", PairValue::Uint64(is_log as u64)));
+            if let Some(bias) = bias {
+                list.add(Pair::new("alloc_bias", PairValue::String(bias.to_owned())));
+            }
+            list
+        };
+        let mut children: Vec<List> = self.data.iter().map(|v| top_level(v, false, None)).collect();
+        children.extend(self.logs.iter().map(|v| top_level(v, true, None)));
+        children.extend(self.special.iter().map(|v| top_level(v, false, Some("special"))));
+        children.extend(self.dedup.iter().map(|v| top_level(v, false, Some("dedup"))));
+
+        let mut root = List::new(Uniqueness::Name);
+        root.add(Pair::new("type", PairValue::String("root".to_owned())));
+        root.add(Pair::new("children", PairValue::NvListArray(children)));
+        if !self.cache.is_empty() {
+            root.add(Pair::new("l2cache", PairValue::NvListArray(self.cache.iter().map(Vdev::to_list).collect())));
+        }
+        if !self.spares.is_empty() {
+            root.add(Pair::new("spares", PairValue::NvListArray(self.spares.iter().map(Vdev::to_list).collect())));
+        }
+        root
+    }
+}
+
+/// Pool properties and features for `Handle::create_pool`, like the `-o`
+/// options of `zpool create`.
+#[derive(Debug,Clone,Default)]
+pub struct PoolProps {
+    props: Vec<(String, String)>,
+}
+
+impl PoolProps {
+    pub fn new() -> PoolProps {
+        PoolProps::default()
+    }
+
+    /// Sets a native property, replacing an earlier value.
+    pub fn property<V: Into<PropValue>>(self, prop: PoolProp, value: V) -> PoolProps {
+        self.named(prop.name(), value.into().to_string())
+    }
+
+    /// Enables a feature by short name, like `-o feature@name=enabled`.
+    pub fn feature(self, name: &str) -> PoolProps {
+        self.named(&format!("feature@{}", name), "enabled".to_owned())
+    }
+
+    fn named(mut self, name: &str, value: String) -> PoolProps {
+        self.props.retain(|&(ref n, _)| n != name);
+        self.props.push((name.to_owned(), value));
+        self
+    }
+
+    fn to_list(&self) -> List<'static> {
+        let mut list = List::new(Uniqueness::Name);
+        for &(ref name, ref value) in &self.props {
+            list.add(Pair::new(name.clone(), PairValue::String(value.clone())));
+        }
+        list
+    }
+}
+
+impl Handle {
+    /// Creates a pool and its root filesystem, like `zpool create`.
+    /// Unlike `zpool create` no features are enabled unless requested
+    /// in `pool_props`.
+    pub fn create_pool(&self, name: &str, topology: &Topology, pool_props: &PoolProps,
+                       fs_props: &DatasetProps) -> Result<Pool> {
+        try!(topology.validate());
+        let c_name = try!(to_c_string(name));
+        let nvroot = try!(NvList::from_list(&topology.to_list()).map_err(Error::Nvpair));
+        let props = try!(NvList::from_list(&pool_props.to_list()).map_err(Error::Nvpair));
+        let fs_props = try!(fs_props.to_nvlist(DatasetType::Filesystem));
+        let raw_props = if pool_props.props.is_empty() { ptr::null_mut() } else { props.as_ptr() };
+        let raw_fs_props = if fs_props.is_empty() { ptr::null_mut() } else { fs_props.as_ptr() };
+        if unsafe { zfs_sys::zpool_create(self.handle, c_name.as_ptr(), nvroot.as_ptr(), raw_props, raw_fs_props) } != 0 {
+            return Err(self.last_error());
+        }
+        self.pool(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use nvpair::{List, PairValue};
+    use super::{Topology, Vdev};
+
+    fn file(name: &str) -> Vdev {
+        Vdev::File(PathBuf::from(format!("/tmp/{}", name)))
+    }
+
+    fn get<'a>(list: &'a List<'static>, name: &str) -> &'a PairValue<'static> {
+        list.pairs().iter().find(|p| p.name() == name).map(|p| p.value()).unwrap()
+    }
+
+    fn children<'a>(list: &'a List<'static>, name: &str) -> &'a [List<'static>] {
+        match *get(list, name) {
+            PairValue::NvListArray(ref lists) => lists,
+            ref v => panic!("unexpected value {:?}", v)
+        }
+    }
+
+    #[test]
+    fn nvroot() {
+        let topology = Topology::new()
+            .vdev(Vdev::Mirror(vec![file("a"), file("b")]))
+            .vdev(Vdev::RaidZ2(vec![file("c"), file("d"), file("e")]))
+            .log(file("log"))
+            .special(Vdev::Mirror(vec![file("s1"), file("s2")]))
+            .cache(Vdev::Disk(PathBuf::from("/dev/sdx")))
+            .spare(file("spare"));
+        assert!(topology.validate().is_ok());
+        let root = topology.to_list();
+        assert_eq!(get(&root, "type"), &PairValue::String("root".to_owned()));
+
+        let top = children(&root, "children");
+        assert_eq!(top.len(), 4);
+        assert_eq!(get(&top[0], "type"), &PairValue::String("mirror".to_owned()));
+        assert_eq!(get(&children(&top[0], "children")[1], "path"), &PairValue::String("/tmp/b".to_owned()));
+        assert_eq!(get(&top[1], "nparity"), &PairValue::Uint64(2));
+        assert_eq!(get(&top[2], "is_log"), &PairValue::Uint64(1));
+        assert_eq!(get(&top[3], "alloc_bias"), &PairValue::String("special".to_owned()));
+        assert_eq!(get(&children(&root, "l2cache")[0], "type"), &PairValue::String("disk".to_owned()));
+        assert_eq!(get(&children(&root, "spares")[0], "type"), &PairValue::String("file".to_owned()));
+    }
+
+    #[test]
+    fn draid_groups() {
+        let disks: Vec<Vdev> = (0..11).map(|i| file(&i.to_string())).collect();
+        let draid = Vdev::Draid { parity: 2, data: 4, spares: 1, children: disks };
+        assert!(draid.validate().is_ok());
+        // 6 wide groups over 10 devices repeat after 5 groups.
+        assert_eq!(get(&draid.to_list(), "draid_ngroups"), &PairValue::Uint64(5));
+    }
+
+    #[test]
+    fn invalid_topologies() {
+        assert!(Topology::new().validate().is_err());
+        assert!(Topology::new().vdev(Vdev::File(PathBuf::from("relative"))).validate().is_err());
+        assert!(Topology::new().vdev(Vdev::Mirror(vec![file("a")])).validate().is_err());
+        assert!(Topology::new().vdev(Vdev::RaidZ3(vec![file("a"), file("b"), file("c")])).validate().is_err());
+        let nested = Vdev::Mirror(vec![Vdev::Mirror(vec![file("a"), file("b")]), file("c")]);
+        assert!(Topology::new().vdev(nested).validate().is_err());
+        let cache = Vdev::Mirror(vec![file("a"), file("b")]);
+        assert!(Topology::new().vdev(file("c")).cache(cache).validate().is_err());
+        let log = Vdev::RaidZ1(vec![file("a"), file("b")]);
+        assert!(Topology::new().vdev(file("c")).log(log).validate().is_err());
+        let draid = Vdev::Draid { parity: 1, data: 4, spares: 1, children: vec![file("a"), file("b")] };
+        assert!(Topology::new().vdev(draid).validate().is_err());
+    }
+}
@@ -1,18 +1,196 @@
-pub struct PoolIterator {
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::vec;
+
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+use zfs_sys::{zpool_handle_t, zpool_prop_t};
+
+use super::{c_string_lossy, Handle, Result};
+
+mod property;
+pub use self::property::{FeatureState, PoolProp};
+mod create;
+pub use self::create::{PoolProps, Topology, Vdev};
+
+/// Iterates over all imported pools, see `Handle::pools`.
+pub struct PoolIterator<'h> {
+    pools: vec::IntoIter<Pool<'h>>
+}
+
+/// An open libzfs pool handle, closed when dropped.
+pub struct Pool<'h> {
+    handle: &'h Handle,
+    raw: *mut zpool_handle_t
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PoolState {
+    Active,
+    Exported,
+    Destroyed,
+    Spare,
+    L2Cache,
+    Uninitialized,
+    Unavailable,
+    PotentiallyActive,
+    Unknown
+}
+
+impl PoolState {
+    fn from_raw(state: c_int) -> PoolState {
+        use zfs_sys::pool_state_t::*;
+        match state {
+            s if s == POOL_STATE_ACTIVE as c_int => PoolState::Active,
+            s if s == POOL_STATE_EXPORTED as c_int => PoolState::Exported,
+            s if s == POOL_STATE_DESTROYED as c_int => PoolState::Destroyed,
+            s if s == POOL_STATE_SPARE as c_int => PoolState::Spare,
+            s if s == POOL_STATE_L2CACHE as c_int => PoolState::L2Cache,
+            s if s == POOL_STATE_UNINITIALIZED as c_int => PoolState::Uninitialized,
+            s if s == POOL_STATE_UNAVAIL as c_int => PoolState::Unavailable,
+            s if s == POOL_STATE_POTENTIALLY_ACTIVE as c_int => PoolState::PotentiallyActive,
+            _ => PoolState::Unknown
+        }
+    }
+}
+
+/// The health of a pool, as shown by `zpool status`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Health {
+    Online,
+    Degraded,
+    Faulted,
+    Offline,
+    Removed,
+    Unavailable,
+    Split,
+    Unknown
+}
+
+impl Health {
+    fn from_name(name: &str) -> Health {
+        match name {
+            "ONLINE" => Health::Online,
+            "DEGRADED" => Health::Degraded,
+            "FAULTED" => Health::Faulted,
+            "OFFLINE" => Health::Offline,
+            "REMOVED" => Health::Removed,
+            "UNAVAIL" => Health::Unavailable,
+            "SPLIT" => Health::Split,
+            _ => Health::Unknown
+        }
+    }
+}
+
+extern "C" fn collect_pool(zhp: *mut zpool_handle_t, data: *mut c_void) -> c_int {
+    let pools = unsafe { &mut *(data as *mut Vec<*mut zpool_handle_t>) };
+    pools.push(zhp);
+    0
+}
+
+impl Handle {
+    /// Lists all imported pools.
+    pub fn pools(&self) -> Result<PoolIterator> {
+        let mut raw: Vec<*mut zpool_handle_t> = Vec::new();
+        let rc = unsafe {
+            zfs_sys::zpool_iter(self.handle, collect_pool, &mut raw as *mut _ as *mut c_void)
+        };
+        // Take ownership first, so handles collected before a failure are closed.
+        let pools: Vec<Pool> = raw.into_iter().map(|raw| Pool { handle: self, raw: raw }).collect();
+        if rc != 0 {
+            return Err(self.last_error());
+        }
+        Ok(PoolIterator { pools: pools.into_iter() })
+    }
+
+    /// Opens an imported pool by name. Pools that are imported but
+    /// unavailable can be opened too, check `Pool::state`.
+    pub fn pool(&self, name: &str) -> Result<Pool> {
+        let name = try!(super::to_c_string(name));
+        let raw = unsafe { zfs_sys::zpool_open_canfail(self.handle, name.as_ptr()) };
+        if raw.is_null() {
+            return Err(self.last_error());
+        }
+        Ok(Pool { handle: self, raw: raw })
+    }
+}
+
+impl<'h> Iterator for PoolIterator<'h> {
+    type Item = Pool<'h>;
+
+    fn next(&mut self) -> Option<Pool<'h>> {
+        self.pools.next()
+    }
+}
+
+impl<'h> Pool<'h> {
+    pub fn name(&self) -> String {
+        c_string_lossy(unsafe { zfs_sys::zpool_get_name(self.raw) })
+    }
+
+    pub fn guid(&self) -> u64 {
+        unsafe { zfs_sys::zpool_get_prop_int(self.raw, zpool_prop_t::ZPOOL_PROP_GUID, ptr::null_mut()) }
+    }
+
+    pub fn state(&self) -> PoolState {
+        PoolState::from_raw(unsafe { zfs_sys::zpool_get_state(self.raw) })
+    }
+
+    pub fn health(&self) -> Health {
+        // libzfs derives the health name from the root vdev state and its
+        // aux state, so let it do the work.
+        let mut buf = [0 as c_char; zfs_sys::ZPOOL_MAXPROPLEN];
+        let rc = unsafe {
+            zfs_sys::zpool_get_prop(self.raw, zpool_prop_t::ZPOOL_PROP_HEALTH, buf.as_mut_ptr(), buf.len(),
+                                    ptr::null_mut(), boolean_t::B_FALSE)
+        };
+        if rc != 0 {
+            return Health::Unknown;
+        }
+        Health::from_name(&c_string_lossy(buf.as_ptr()))
+    }
+
+    pub fn handle(&self) -> &'h Handle {
+        self.handle
+    }
+
+    pub fn as_ptr(&self) -> *mut zpool_handle_t {
+        self.raw
+    }
 }
 
-#[derive(Debug,PartialEq)]
-pub struct Pool {
+impl<'h> fmt::Debug for Pool<'h> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("name", &self.name())
+            .field("guid", &self.guid())
+            .finish()
+    }
 }
 
-pub fn iter() -> PoolIterator {
-    PoolIterator { }
+impl<'h> Drop for Pool<'h> {
+    fn drop(&mut self) {
+        unsafe { zfs_sys::zpool_close(self.raw) };
+    }
 }
 
-impl Iterator for PoolIterator {
-    type Item = Pool;
+#[cfg(test)]
+mod test {
+    use super::{Health, PoolState};
+    use zfs_sys::pool_state_t;
+
+    #[test]
+    fn state_from_raw() {
+        assert_eq!(PoolState::from_raw(pool_state_t::POOL_STATE_ACTIVE as i32), PoolState::Active);
+        assert_eq!(PoolState::from_raw(pool_state_t::POOL_STATE_UNAVAIL as i32), PoolState::Unavailable);
+        assert_eq!(PoolState::from_raw(42), PoolState::Unknown);
+    }
 
-    fn next(&mut self) -> Option<Pool> {
-        None
+    #[test]
+    fn health_from_name() {
+        assert_eq!(Health::from_name("ONLINE"), Health::Online);
+        assert_eq!(Health::from_name("UNAVAIL"), Health::Unavailable);
+        assert_eq!(Health::from_name("-"), Health::Unknown);
     }
 }
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+
+use nvpair::NvListRef;
+use nvpair::PairValue;
+use nvpair::ffi::boolean_t;
+use zfs_sys;
+use zfs_sys::{zpool_prop_t, zprop_source_t};
+
+use super::{Pool, PoolState};
+use super::super::{c_string_lossy, to_c_string, Error, PropSource, PropValue, Property, Result};
+use super::super::property::PropKind;
+
+/// The native pool properties, mirroring `zpool_prop_t`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub enum PoolProp {
+    Name,
+    Size,
+    Capacity,
+    Altroot,
+    Health,
+    Guid,
+    Version,
+    Bootfs,
+    Delegation,
+    Autoreplace,
+    Cachefile,
+    Failuremode,
+    Listsnapshots,
+    Autoexpand,
+    Dedupditto,
+    Dedupratio,
+    Free,
+    Allocated,
+    Readonly,
+    Ashift,
+    Comment,
+    Expandsize,
+    Freeing,
+    Fragmentation,
+    Leaked,
+    Maxblocksize,
+    Maxdnodesize,
+    Multihost,
+}
+
+const ALL: [PoolProp; 28] = [
+    PoolProp::Name, PoolProp::Size, PoolProp::Capacity, PoolProp::Altroot, PoolProp::Health,
+    PoolProp::Guid, PoolProp::Version, PoolProp::Bootfs, PoolProp::Delegation, PoolProp::Autoreplace,
+    PoolProp::Cachefile, PoolProp::Failuremode, PoolProp::Listsnapshots, PoolProp::Autoexpand,
+    PoolProp::Dedupditto, PoolProp::Dedupratio, PoolProp::Free, PoolProp::Allocated, PoolProp::Readonly,
+    PoolProp::Ashift, PoolProp::Comment, PoolProp::Expandsize, PoolProp::Freeing,
+    PoolProp::Fragmentation, PoolProp::Leaked, PoolProp::Maxblocksize, PoolProp::Maxdnodesize,
+    PoolProp::Multihost,
+];
+
+impl PoolProp {
+    pub fn all() -> &'static [PoolProp] {
+        &ALL
+    }
+
+    /// The name used by `zpool get`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PoolProp::Name => "name",
+            PoolProp::Size => "size",
+            PoolProp::Capacity => "capacity",
+            PoolProp::Altroot => "altroot",
+            PoolProp::Health => "health",
+            PoolProp::Guid => "guid",
+            PoolProp::Version => "version",
+            PoolProp::Bootfs => "bootfs",
+            PoolProp::Delegation => "delegation",
+            PoolProp::Autoreplace => "autoreplace",
+            PoolProp::Cachefile => "cachefile",
+            PoolProp::Failuremode => "failmode",
+            PoolProp::Listsnapshots => "listsnapshots",
+            PoolProp::Autoexpand => "autoexpand",
+            PoolProp::Dedupditto => "dedupditto",
+            PoolProp::Dedupratio => "dedupratio",
+            PoolProp::Free => "free",
+            PoolProp::Allocated => "allocated",
+            PoolProp::Readonly => "readonly",
+            PoolProp::Ashift => "ashift",
+            PoolProp::Comment => "comment",
+            PoolProp::Expandsize => "expandsize",
+            PoolProp::Freeing => "freeing",
+            PoolProp::Fragmentation => "fragmentation",
+            PoolProp::Leaked => "leaked",
+            PoolProp::Maxblocksize => "maxblocksize",
+            PoolProp::Maxdnodesize => "maxdnodesize",
+            PoolProp::Multihost => "multihost",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PoolProp> {
+        ALL.iter().find(|p| p.name() == name).cloned()
+    }
+
+    pub fn to_raw(&self) -> zpool_prop_t {
+        use zfs_sys::zpool_prop_t::*;
+        match *self {
+            PoolProp::Name => ZPOOL_PROP_NAME,
+            PoolProp::Size => ZPOOL_PROP_SIZE,
+            PoolProp::Capacity => ZPOOL_PROP_CAPACITY,
+            PoolProp::Altroot => ZPOOL_PROP_ALTROOT,
+            PoolProp::Health => ZPOOL_PROP_HEALTH,
+            PoolProp::Guid => ZPOOL_PROP_GUID,
+            PoolProp::Version => ZPOOL_PROP_VERSION,
+            PoolProp::Bootfs => ZPOOL_PROP_BOOTFS,
+            PoolProp::Delegation => ZPOOL_PROP_DELEGATION,
+            PoolProp::Autoreplace => ZPOOL_PROP_AUTOREPLACE,
+            PoolProp::Cachefile => ZPOOL_PROP_CACHEFILE,
+            PoolProp::Failuremode => ZPOOL_PROP_FAILUREMODE,
+            PoolProp::Listsnapshots => ZPOOL_PROP_LISTSNAPS,
+            PoolProp::Autoexpand => ZPOOL_PROP_AUTOEXPAND,
+            PoolProp::Dedupditto => ZPOOL_PROP_DEDUPDITTO,
+            PoolProp::Dedupratio => ZPOOL_PROP_DEDUPRATIO,
+            PoolProp::Free => ZPOOL_PROP_FREE,
+            PoolProp::Allocated => ZPOOL_PROP_ALLOCATED,
+            PoolProp::Readonly => ZPOOL_PROP_READONLY,
+            PoolProp::Ashift => ZPOOL_PROP_ASHIFT,
+            PoolProp::Comment => ZPOOL_PROP_COMMENT,
+            PoolProp::Expandsize => ZPOOL_PROP_EXPANDSZ,
+            PoolProp::Freeing => ZPOOL_PROP_FREEING,
+            PoolProp::Fragmentation => ZPOOL_PROP_FRAGMENTATION,
+            PoolProp::Leaked => ZPOOL_PROP_LEAKED,
+            PoolProp::Maxblocksize => ZPOOL_PROP_MAXBLOCKSIZE,
+            PoolProp::Maxdnodesize => ZPOOL_PROP_MAXDNODESIZE,
+            PoolProp::Multihost => ZPOOL_PROP_MULTIHOST,
+        }
+    }
+
+    fn kind(&self) -> PropKind {
+        match *self {
+            PoolProp::Size | PoolProp::Free | PoolProp::Allocated | PoolProp::Expandsize |
+            PoolProp::Freeing | PoolProp::Leaked | PoolProp::Maxblocksize |
+            PoolProp::Maxdnodesize => PropKind::Bytes,
+            PoolProp::Dedupratio => PropKind::Ratio,
+            PoolProp::Delegation | PoolProp::Autoreplace | PoolProp::Listsnapshots |
+            PoolProp::Autoexpand | PoolProp::Readonly | PoolProp::Multihost => PropKind::Bool,
+            PoolProp::Failuremode => PropKind::Index,
+            PoolProp::Name | PoolProp::Altroot | PoolProp::Health | PoolProp::Bootfs |
+            PoolProp::Cachefile | PoolProp::Comment => PropKind::String,
+            _ => PropKind::Number,
+        }
+    }
+
+    /// Whether libzfs still reports the property of a pool that is
+    /// unavailable; it renders all others as `-`.
+    fn reported_when_unavailable(&self) -> bool {
+        match *self {
+            PoolProp::Name | PoolProp::Health | PoolProp::Guid | PoolProp::Altroot |
+            PoolProp::Cachefile | PoolProp::Comment => true,
+            _ => false,
+        }
+    }
+}
+
+/// The state of a pool feature, as shown by `zpool get feature@...`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum FeatureState {
+    Disabled,
+    Enabled,
+    Active,
+}
+
+impl<'h> Pool<'h> {
+    /// Reads a native property. Fails with `Error::UnavailableProperty` if
+    /// the pool cannot report it because it is unavailable.
+    pub fn property(&self, prop: PoolProp) -> Result<Property> {
+        if self.state() == PoolState::Unavailable && !prop.reported_when_unavailable() {
+            return Err(Error::UnavailableProperty(prop.name().to_owned()));
+        }
+        let mut source = zprop_source_t::ZPROP_SRC_NONE;
+        let kind = prop.kind();
+        let value = if kind.is_numeric() {
+            // zpool_get_prop_int cannot signal failure, so check that
+            // libzfs can render the property first.
+            try!(self.property_string(prop, &mut source));
+            kind.from_numeric(unsafe { zfs_sys::zpool_get_prop_int(self.raw, prop.to_raw(), &mut source) })
+        } else {
+            kind.from_string(try!(self.property_string(prop, &mut source)))
+        };
+        Ok(Property { value: value, source: PropSource::from_raw(source, String::new()) })
+    }
+
+    fn property_string(&self, prop: PoolProp, source: &mut zprop_source_t) -> Result<String> {
+        let mut buf = [0 as c_char; zfs_sys::ZPOOL_MAXPROPLEN];
+        let rc = unsafe {
+            zfs_sys::zpool_get_prop(self.raw, prop.to_raw(), buf.as_mut_ptr(), buf.len(), source, boolean_t::B_TRUE)
+        };
+        if rc != 0 {
+            return Err(self.handle.last_error());
+        }
+        Ok(c_string_lossy(buf.as_ptr()))
+    }
+
+    /// Reads every native property the pool can report.
+    pub fn properties(&self) -> Result<Vec<(PoolProp, Property)>> {
+        let mut props = Vec::new();
+        for &prop in PoolProp::all() {
+            match self.property(prop) {
+                Ok(value) => props.push((prop, value)),
+                Err(Error::UnavailableProperty(_)) => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(props)
+    }
+
+    /// Sets a native property, like `zpool set`.
+    pub fn set_property<V: Into<PropValue>>(&self, prop: PoolProp, value: V) -> Result<()> {
+        self.set_property_raw(prop.name(), &value.into().to_string())
+    }
+
+    fn set_property_raw(&self, name: &str, value: &str) -> Result<()> {
+        let name = try!(to_c_string(name));
+        let value = try!(to_c_string(value));
+        if unsafe { zfs_sys::zpool_set_prop(self.raw, name.as_ptr(), value.as_ptr()) } != 0 {
+            return Err(self.handle.last_error());
+        }
+        Ok(())
+    }
+
+    /// The enabled and active features, keyed by their short name
+    /// (`async_destroy` for `feature@async_destroy`). Disabled features are
+    /// not included, use `feature` to query one explicitly.
+    pub fn features(&self) -> Result<BTreeMap<String, FeatureState>> {
+        let raw = unsafe { zfs_sys::zpool_get_features(self.raw) };
+        let mut features = BTreeMap::new();
+        if raw.is_null() {
+            return Ok(features);
+        }
+        // The list is keyed by feature guid ("com.delphix:async_destroy")
+        // and holds the reference count of each enabled feature.
+        let list = unsafe { NvListRef::from_raw(raw) };
+        for pair in list.pairs() {
+            let guid = try!(pair.name().map_err(Error::Nvpair));
+            let refcount = match try!(pair.value().map_err(Error::Nvpair)) {
+                PairValue::Uint64(n) => n,
+                _ => continue
+            };
+            let name = match guid.find(':') {
+                Some(i) => guid[i + 1..].to_owned(),
+                None => guid
+            };
+            features.insert(name, if refcount > 0 { FeatureState::Active } else { FeatureState::Enabled });
+        }
+        Ok(features)
+    }
+
+    /// The state of a single feature, by short name.
+    pub fn feature(&self, name: &str) -> Result<FeatureState> {
+        let propname = try!(to_c_string(&format!("feature@{}", name)));
+        let mut buf = [0 as c_char; zfs_sys::ZPOOL_MAXPROPLEN];
+        let rc = unsafe { zfs_sys::zpool_prop_get_feature(self.raw, propname.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+        if rc != 0 {
+            return Err(Error::UnavailableProperty(format!("feature@{}", name)));
+        }
+        match &*c_string_lossy(buf.as_ptr()) {
+            "active" => Ok(FeatureState::Active),
+            "enabled" => Ok(FeatureState::Enabled),
+            _ => Ok(FeatureState::Disabled),
+        }
+    }
+
+    /// Enables a feature, like `zpool set feature@name=enabled`.
+    pub fn enable_feature(&self, name: &str) -> Result<()> {
+        self.set_property_raw(&format!("feature@{}", name), "enabled")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PoolProp;
+
+    #[test]
+    fn names_roundtrip() {
+        for prop in PoolProp::all() {
+            assert_eq!(PoolProp::from_name(prop.name()), Some(*prop));
+        }
+        assert_eq!(PoolProp::from_name("feature@lz4_compress"), None);
+    }
+
+    #[test]
+    fn raw_values_follow_zpool_prop_t() {
+        assert_eq!(PoolProp::Name.to_raw() as i32, 0);
+        assert_eq!(PoolProp::Guid.to_raw() as i32, 5);
+        assert_eq!(PoolProp::Ashift.to_raw() as i32, 19);
+        assert_eq!(PoolProp::Multihost.to_raw() as i32, 28);
+    }
+
+    #[test]
+    fn reported_when_unavailable() {
+        assert!(PoolProp::Health.reported_when_unavailable());
+        assert!(!PoolProp::Size.reported_when_unavailable());
+    }
+}
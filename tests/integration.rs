@@ -1,11 +1,355 @@
 extern crate zfs;
 mod support;
 
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use zfs::dataset::{DatasetProp, DatasetProps, DatasetType, DestroyOptions, ReceiveOptions, SendOptions};
+use zfs::{Compression, PropSource, PropValue};
+use zfs::zpool::{Health, PoolProp, PoolProps, Topology, Vdev};
+use zfs::sendstream::{rewrite, Record, StreamReader};
+
 fn main() {
     test_zpool_create_destroy();
+    test_create_datasets();
+    test_destroy_datasets();
+    test_snapshots();
+    test_rollback_rename_promote();
+    test_clones();
+    test_send();
+    test_receive();
+    test_bookmarks();
+    test_holds();
+    test_create_pool();
 }
 
 fn test_zpool_create_destroy() {
     let handle = zfs::Handle::new().unwrap();
-    assert_eq!(zfs::zpool::iter().next(), None);
+    assert!(handle.pools().unwrap().next().is_none());
+}
+
+fn test_create_datasets() {
+    let _pool = support::TestPool::new("create");
+    let handle = zfs::Handle::new().unwrap();
+
+    let props = DatasetProps::new().compression(Compression::Lz4).parents(true);
+    let fs = handle.create_filesystem("create/a/b", &props).unwrap();
+    assert_eq!(fs.dataset_type(), Some(DatasetType::Filesystem));
+    let compression = fs.get(DatasetProp::Compression).unwrap();
+    assert_eq!(compression.value, PropValue::Compression(Compression::Lz4));
+    assert_eq!(compression.source, PropSource::Local);
+    let props: Vec<DatasetProp> = fs.properties().unwrap().into_iter().map(|(prop, _)| prop).collect();
+    assert!(props.contains(&DatasetProp::Compression));
+    assert!(!props.contains(&DatasetProp::Volsize) && !props.contains(&DatasetProp::Origin));
+    match fs.get(DatasetProp::Volsize).unwrap_err() {
+        zfs::Error::UnavailableProperty(name) => assert_eq!(name, "volsize"),
+        e => panic!("unexpected error {:?}", e)
+    }
+
+    let vol = handle.create_volume("create/vol", 16 << 20, &DatasetProps::new().volblocksize(16384)).unwrap();
+    assert_eq!(vol.get(DatasetProp::Volsize).unwrap().value, PropValue::Bytes(16 << 20));
+
+    let err = handle.create_filesystem("create/missing/c", &DatasetProps::new()).unwrap_err();
+    assert_eq!(err.kind(), Some(zfs::ErrorKind::NoEnt));
+}
+
+fn test_destroy_datasets() {
+    let _pool = support::TestPool::new("destroy");
+    let handle = zfs::Handle::new().unwrap();
+
+    handle.create_filesystem("destroy/fs/child", &DatasetProps::new().parents(true)).unwrap();
+    for snap in &["a", "b", "c", "d"] {
+        support::zfs(&["snapshot", "-r", &format!("destroy/fs@{}", snap)]);
+    }
+
+    let fs = handle.dataset("destroy/fs").unwrap();
+    let recursive = DestroyOptions::new().recursive(true);
+    let plan = fs.plan_destroy_snapshots("a%b,d", &recursive).unwrap();
+    assert_eq!(plan.datasets, vec!["destroy/fs@a", "destroy/fs@b", "destroy/fs@d",
+                                   "destroy/fs/child@a", "destroy/fs/child@b", "destroy/fs/child@d"]);
+    fs.destroy_snapshots("a%b,d", &recursive).unwrap();
+    let left: Vec<String> = fs.snapshots().unwrap().map(|s| s.name()).collect();
+    assert_eq!(left, vec!["destroy/fs@c"]);
+
+    // Like `zfs destroy -r fs@c`, which takes child@c along.
+    let snap = handle.dataset("destroy/fs@c").unwrap();
+    assert_eq!(snap.plan_destroy(&recursive).unwrap().datasets, vec!["destroy/fs@c", "destroy/fs/child@c"]);
+    snap.destroy(&recursive).unwrap();
+    assert_eq!(handle.dataset("destroy/fs/child@c").unwrap_err().kind(), Some(zfs::ErrorKind::NoEnt));
+
+    // A name only the top has does not spare the others in a descendant.
+    support::zfs(&["snapshot", "-r", "destroy/fs@e"]);
+    support::zfs(&["snapshot", "destroy/fs@x"]);
+    fs.destroy_snapshots("e,x", &recursive).unwrap();
+    assert_eq!(handle.dataset("destroy/fs/child@e").unwrap_err().kind(), Some(zfs::ErrorKind::NoEnt));
+
+    let err = fs.plan_destroy(&DestroyOptions::new()).unwrap_err();
+    match err {
+        zfs::Error::HasChildren(names) => assert!(names.contains(&"destroy/fs/child".to_owned())),
+        e => panic!("unexpected error {:?}", e)
+    }
+    assert!(fs.plan_destroy(&recursive).unwrap().datasets.contains(&"destroy/fs".to_owned()));
+    fs.destroy(&recursive).unwrap();
+    assert_eq!(handle.dataset("destroy/fs").unwrap_err().kind(), Some(zfs::ErrorKind::NoEnt));
+}
+
+fn test_snapshots() {
+    let _pool = support::TestPool::new("snap");
+    let handle = zfs::Handle::new().unwrap();
+    handle.create_filesystem("snap/a/b", &DatasetProps::new().parents(true)).unwrap();
+
+    handle.snapshot_recursive(&["snap/a@one"], &[("com.example:by", "test")]).unwrap();
+    let snap = handle.dataset("snap/a/b@one").unwrap();
+    assert_eq!(snap.user_properties().unwrap()["com.example:by"].value, PropValue::from("test"));
+
+    // One failing name fails the whole batch.
+    match handle.snapshot(&["snap/a@two", "snap/a/b@one"], &[]).unwrap_err() {
+        zfs::Error::Batch(errors) => assert!(errors.contains_key("snap/a/b@one")),
+        e => panic!("unexpected error {:?}", e)
+    }
+    assert_eq!(handle.dataset("snap/a@two").unwrap_err().kind(), Some(zfs::ErrorKind::NoEnt));
+}
+
+fn test_rollback_rename_promote() {
+    let _pool = support::TestPool::new("life");
+    let handle = zfs::Handle::new().unwrap();
+    let mut fs = handle.create_filesystem("life/blue", &DatasetProps::new()).unwrap();
+
+    handle.snapshot(&["life/blue@one", "life/blue@two"], &[]).unwrap();
+    handle.snapshot(&["life/blue@three"], &[]).unwrap();
+    let one = handle.dataset("life/blue@one").unwrap();
+    match fs.rollback(&one, false).unwrap_err() {
+        zfs::Error::NewerSnapshots(names) => assert!(names.contains(&"life/blue@three".to_owned())),
+        e => panic!("unexpected error {:?}", e)
+    }
+    fs.rollback(&one, true).unwrap();
+    let left: Vec<String> = fs.snapshots().unwrap().map(|s| s.name()).collect();
+    assert_eq!(left, vec!["life/blue@one"]);
+
+    let mut green = one.clone_to("life/green", &DatasetProps::new()).unwrap();
+    green.promote().unwrap();
+    assert!(handle.dataset("life/green@one").is_ok());
+
+    fs.rename("life/old", false, false).unwrap();
+    assert_eq!(fs.name(), "life/old");
+    green.rename("life/blue", false, false).unwrap();
+
+    let mut snap = handle.dataset("life/blue@one").unwrap();
+    let err = snap.rename("life/old@one", false, false).unwrap_err();
+    assert_eq!(err.kind(), Some(zfs::ErrorKind::CrossTarget));
+    snap.rename("life/blue@live", true, false).unwrap();
+    assert_eq!(snap.name(), "life/blue@live");
+}
+
+fn test_clones() {
+    let _pool = support::TestPool::new("clones");
+    let handle = zfs::Handle::new().unwrap();
+    handle.create_filesystem("clones/golden", &DatasetProps::new()).unwrap();
+    handle.snapshot(&["clones/golden@v1"], &[]).unwrap();
+    let golden = handle.dataset("clones/golden@v1").unwrap();
+
+    let props = DatasetProps::new().compression(Compression::Lz4).user_property("ci:job", "42").parents(true);
+    let env = golden.clone_to("clones/ci/env-42", &props).unwrap();
+    assert_eq!(env.name(), "clones/ci/env-42");
+    assert_eq!(env.get(DatasetProp::Origin).unwrap().value, PropValue::from("clones/golden@v1"));
+    assert_eq!(env.get(DatasetProp::Compression).unwrap().value, PropValue::Compression(Compression::Lz4));
+
+    let err = golden.clone_to("elsewhere/env", &DatasetProps::new()).unwrap_err();
+    assert_eq!(err.kind(), Some(zfs::ErrorKind::CrossTarget));
+}
+
+fn test_send() {
+    let _pool = support::TestPool::new("send");
+    let handle = zfs::Handle::new().unwrap();
+    handle.create_filesystem("send/fs", &DatasetProps::new()).unwrap();
+    handle.snapshot(&["send/fs@one"], &[]).unwrap();
+    handle.snapshot(&["send/fs@two"], &[]).unwrap();
+
+    let mut full = Vec::new();
+    let mut reported = 0;
+    let snap = handle.dataset("send/fs@one").unwrap();
+    let written = snap.send_with_progress(&mut full, &SendOptions::new(), |n| reported = n).unwrap();
+    assert_eq!(written, full.len() as u64);
+    assert_eq!(reported, written);
+    let estimate = snap.send_space(&SendOptions::new()).unwrap();
+    assert!(estimate > 0 && estimate <= 2 * written, "estimated {} for {} bytes", estimate, written);
+
+    let mut incremental = Vec::new();
+    let options = SendOptions::new().from("send/fs@one").large_blocks(true).embed_data(true).compressed(true);
+    let two = handle.dataset("send/fs@two").unwrap();
+    two.send(&mut incremental, &options).unwrap();
+    assert!(!incremental.is_empty());
+    assert!(two.send_space(&options).unwrap() <= estimate);
+
+    let records: Vec<Record> = StreamReader::new(&full[..]).map(|r| r.unwrap()).collect();
+    match records[0] {
+        Record::Begin(ref begin) => assert_eq!(begin.toname, "send/fs@one"),
+        ref r => panic!("unexpected record {:?}", r)
+    }
+    match records[records.len() - 1] {
+        Record::End { .. } => (),
+        ref r => panic!("unexpected record {:?}", r)
+    }
+
+    let fs = handle.dataset("send/fs").unwrap();
+    assert!(fs.send(Vec::new(), &SendOptions::new()).is_err());
+}
+
+fn test_receive() {
+    let _pool = support::TestPool::new("recv");
+    let handle = zfs::Handle::new().unwrap();
+    handle.create_filesystem("recv/src", &DatasetProps::new()).unwrap();
+    handle.snapshot(&["recv/src@one"], &[]).unwrap();
+    handle.snapshot(&["recv/src@two"], &[]).unwrap();
+
+    let mut full = Vec::new();
+    handle.dataset("recv/src@one").unwrap().send(&mut full, &SendOptions::new()).unwrap();
+    let options = ReceiveOptions::new().property(DatasetProp::Atime, false).user_property("backup:from", "src");
+    assert_eq!(handle.receive("recv/dst", &full[..], &options).unwrap(), "recv/dst@one");
+    let dst = handle.dataset("recv/dst").unwrap();
+    assert_eq!(dst.get(DatasetProp::Atime).unwrap().value, PropValue::Bool(false));
+
+    let options = ReceiveOptions::new().property(DatasetProp::Casesensitivity, "insensitive");
+    assert!(handle.receive("recv/insensitive", &full[..], &options).is_err());
+    assert!(handle.dataset("recv/insensitive").is_err());
+    let options = ReceiveOptions::new().property(DatasetProp::Mountpoint, "relative");
+    assert!(handle.receive("recv/relative", &full[..], &options).is_err());
+    assert!(handle.dataset("recv/relative").is_err());
+    // A quota below what the received data uses is only refused once it is in.
+    let options = ReceiveOptions::new().property(DatasetProp::Quota, 1024u64);
+    match handle.receive("recv/quota", &full[..], &options).unwrap_err() {
+        zfs::Error::Received(name, _) => assert_eq!(name, "recv/quota@one"),
+        e => panic!("unexpected error {:?}", e)
+    }
+
+    let mut incremental = Vec::new();
+    let from = SendOptions::new().from("recv/src@one");
+    handle.dataset("recv/src@two").unwrap().send(&mut incremental, &from).unwrap();
+    let received = handle.receive("recv/dst@two", &incremental[..], &ReceiveOptions::new().force(true)).unwrap();
+    assert_eq!(received, "recv/dst@two");
+
+    let rewritten = rewrite(&full[..], Vec::new(), |record| match record {
+        Record::Free { .. } => None,
+        Record::Begin(mut begin) => {
+            begin.toname = "recv/src@renamed".to_owned();
+            Some(Record::Begin(begin))
+        },
+        record => Some(record)
+    }).unwrap();
+    let received = handle.receive("recv/rewritten", &rewritten[..], &ReceiveOptions::new()).unwrap();
+    assert_eq!(received, "recv/rewritten@renamed");
+
+    let truncated = &full[..full.len() / 2];
+    match handle.receive("recv/partial", truncated, &ReceiveOptions::new().resumable(true)).unwrap_err() {
+        zfs::Error::Resumable(_, token) => assert!(!token.is_empty()),
+        e => panic!("unexpected error {:?}", e)
+    }
+
+    let failing = FailingReader(&full[..full.len() / 2]);
+    match handle.receive("recv/failed", failing, &ReceiveOptions::new()).unwrap_err() {
+        zfs::Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::Other),
+        e => panic!("unexpected error {:?}", e)
+    }
+}
+
+/// A reader that fails once its data is used up.
+struct FailingReader<'a>(&'a [u8]);
+
+impl<'a> Read for FailingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::Other, "source went away"));
+        }
+        self.0.read(buf)
+    }
+}
+
+fn test_bookmarks() {
+    let _pool = support::TestPool::new("mark");
+    let handle = zfs::Handle::new().unwrap();
+    handle.create_filesystem("mark/fs", &DatasetProps::new()).unwrap();
+    handle.snapshot(&["mark/fs@one"], &[]).unwrap();
+    handle.snapshot(&["mark/fs@two"], &[]).unwrap();
+
+    let one = handle.dataset("mark/fs@one").unwrap();
+    assert_eq!(one.bookmark("one").unwrap(), "mark/fs#one");
+    assert_eq!(handle.dataset("mark/fs@two").unwrap().bookmark("two").unwrap(), "mark/fs#two");
+    let fs = handle.dataset("mark/fs").unwrap();
+    let marks = fs.bookmarks().unwrap();
+    let names: Vec<&str> = marks.iter().map(|b| &*b.name).collect();
+    assert_eq!(names, vec!["mark/fs#one", "mark/fs#two"]);
+    assert_eq!(marks[0].guid, one.get(DatasetProp::Guid).unwrap().value.as_u64().unwrap());
+    assert!(marks[0].createtxg < marks[1].createtxg);
+
+    // The source snapshot can go once it is bookmarked.
+    drop(one);
+    fs.destroy_snapshots("one", &DestroyOptions::new()).unwrap();
+    let mut incremental = Vec::new();
+    let two = handle.dataset("mark/fs@two").unwrap();
+    two.send(&mut incremental, &SendOptions::new().from("mark/fs#one")).unwrap();
+    assert!(!incremental.is_empty());
+
+    assert!(handle.destroy_bookmarks(&["mark/fs@two"]).is_err());
+    handle.destroy_bookmarks(&["mark/fs#one", "mark/fs#two"]).unwrap();
+    assert!(fs.bookmarks().unwrap().is_empty());
+}
+
+fn test_holds() {
+    let _pool = support::TestPool::new("hold");
+    let handle = zfs::Handle::new().unwrap();
+    handle.create_filesystem("hold/fs", &DatasetProps::new()).unwrap();
+    handle.snapshot(&["hold/fs@one", "hold/fs@two"], &[]).unwrap();
+
+    let one = handle.dataset("hold/fs@one").unwrap();
+    one.hold("keep").unwrap();
+    assert!(one.holds().unwrap().contains_key("keep"));
+    assert_eq!(one.hold("keep").unwrap_err().kind(), Some(zfs::ErrorKind::ReftagHold));
+    one.release("keep").unwrap();
+    assert_eq!(one.release("keep").unwrap_err().kind(), Some(zfs::ErrorKind::ReftagRelease));
+
+    {
+        let guard = handle.hold_guard(&["hold/fs@one", "hold/fs@two"], "transfer").unwrap();
+        assert_eq!(guard.tag(), "transfer");
+        let two = handle.dataset("hold/fs@two").unwrap();
+        assert!(two.holds().unwrap().contains_key("transfer"));
+        assert!(handle.dataset("hold/fs").unwrap().destroy_snapshots("two", &DestroyOptions::new()).is_err());
+    }
+    assert!(one.holds().unwrap().is_empty());
+    assert!(handle.dataset("hold/fs@two").unwrap().holds().unwrap().is_empty());
+
+    let err = handle.hold_guard(&["hold/fs@one", "hold/fs@missing"], "transfer").unwrap_err();
+    assert_eq!(err.kind(), Some(zfs::ErrorKind::NoEnt));
+    assert!(one.holds().unwrap().is_empty());
+}
+
+fn test_create_pool() {
+    let files: Vec<String> = (0..3).map(|i| format!("/tmp/newpool-{}.img", i)).collect();
+    for file in &files {
+        support::sparse_file(file);
+    }
+    let handle = zfs::Handle::new().unwrap();
+    let vdev = |i: usize| Vdev::File(PathBuf::from(&files[i]));
+
+    let topology = Topology::new().vdev(Vdev::Mirror(vec![vdev(0), vdev(1)])).spare(vdev(2));
+    let pool_props = PoolProps::new().property(PoolProp::Comment, "test").feature("lz4_compress");
+    let fs_props = DatasetProps::new().compression(Compression::Lz4);
+    {
+        let pool = handle.create_pool("newpool", &topology, &pool_props, &fs_props).unwrap();
+        assert_eq!(pool.health(), Health::Online);
+        assert_eq!(pool.property(PoolProp::Comment).unwrap().value, PropValue::from("test"));
+        assert!(pool.properties().unwrap().iter().any(|&(prop, _)| prop == PoolProp::Size));
+        let root = handle.dataset("newpool").unwrap();
+        assert_eq!(root.get(DatasetProp::Compression).unwrap().value, PropValue::Compression(Compression::Lz4));
+    }
+    support::zpool(&["destroy", "newpool"]);
+
+    let err = handle.create_pool("newpool", &Topology::new(), &PoolProps::new(), &DatasetProps::new()).unwrap_err();
+    match err {
+        zfs::Error::InvalidArgument(_) => (),
+        e => panic!("unexpected error {:?}", e)
+    }
+    for file in &files {
+        fs::remove_file(file).unwrap();
+    }
 }
@@ -0,0 +1,44 @@
+use std::fs;
+use std::process::Command;
+
+/// A pool backed by a sparse file, destroyed when dropped.
+pub struct TestPool {
+    pub name: String,
+    file: String,
+}
+
+fn run(cmd: &mut Command) {
+    let status = cmd.status().unwrap();
+    assert!(status.success(), "{:?} failed with {}", cmd, status);
+}
+
+/// Runs the `zfs` command, for setting up what the crate cannot do yet.
+pub fn zfs(args: &[&str]) {
+    run(Command::new("zfs").args(args));
+}
+
+/// Runs the `zpool` command.
+pub fn zpool(args: &[&str]) {
+    run(Command::new("zpool").args(args));
+}
+
+/// Creates a sparse file of 256M to back a vdev.
+pub fn sparse_file(path: &str) {
+    run(Command::new("truncate").args(&["-s", "256M", path]));
+}
+
+impl TestPool {
+    pub fn new(name: &str) -> TestPool {
+        let file = format!("/tmp/{}.img", name);
+        sparse_file(&file);
+        zpool(&["create", name, &file]);
+        TestPool { name: name.to_owned(), file: file }
+    }
+}
+
+impl Drop for TestPool {
+    fn drop(&mut self) {
+        zpool(&["destroy", &self.name]);
+        fs::remove_file(&self.file).unwrap();
+    }
+}
@@ -6,9 +6,11 @@
 extern crate nvpair;
 
 use std::os::raw::{c_void, c_char, c_int, c_uint};
+use nvpair::ffi::{boolean_t, nvlist_t};
 
 #[repr(C)]
-enum zfs_error_t {
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum zfs_error_t {
 	EZFS_SUCCESS = 0,	/* no error -- success */
 	EZFS_NOMEM = 2000,	/* out of memory */
 	EZFS_BADPROP,		/* invalid property value */
@@ -83,15 +85,275 @@ enum zfs_error_t {
 	EZFS_UNKNOWN
 }
 
+impl zfs_error_t {
+    /// Converts a code returned by `libzfs_errno`. Codes this binding does
+    /// not know about, e.g. from a newer libzfs, become `EZFS_UNKNOWN`.
+    pub fn from_raw(code: c_int) -> zfs_error_t {
+        if code == zfs_error_t::EZFS_SUCCESS as c_int {
+            zfs_error_t::EZFS_SUCCESS
+        } else if code >= zfs_error_t::EZFS_NOMEM as c_int && code < zfs_error_t::EZFS_UNKNOWN as c_int {
+            // The codes from EZFS_NOMEM up to EZFS_UNKNOWN are contiguous.
+            unsafe { ::std::mem::transmute(code) }
+        } else {
+            zfs_error_t::EZFS_UNKNOWN
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum pool_state_t {
+    POOL_STATE_ACTIVE = 0,          /* In active use */
+    POOL_STATE_EXPORTED,            /* Explicitly exported */
+    POOL_STATE_DESTROYED,           /* Explicitly destroyed */
+    POOL_STATE_SPARE,               /* Reserved for hot spare use */
+    POOL_STATE_L2CACHE,             /* Level 2 ARC device */
+    POOL_STATE_UNINITIALIZED,       /* Internal spa_t state */
+    POOL_STATE_UNAVAIL,             /* Internal libzfs state */
+    POOL_STATE_POTENTIALLY_ACTIVE   /* Internal libzfs state */
+}
+
+#[repr(C)]
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum zpool_prop_t {
+    ZPOOL_PROP_INVAL = -1,
+    ZPOOL_PROP_NAME,
+    ZPOOL_PROP_SIZE,
+    ZPOOL_PROP_CAPACITY,
+    ZPOOL_PROP_ALTROOT,
+    ZPOOL_PROP_HEALTH,
+    ZPOOL_PROP_GUID,
+    ZPOOL_PROP_VERSION,
+    ZPOOL_PROP_BOOTFS,
+    ZPOOL_PROP_DELEGATION,
+    ZPOOL_PROP_AUTOREPLACE,
+    ZPOOL_PROP_CACHEFILE,
+    ZPOOL_PROP_FAILUREMODE,
+    ZPOOL_PROP_LISTSNAPS,
+    ZPOOL_PROP_AUTOEXPAND,
+    ZPOOL_PROP_DEDUPDITTO,
+    ZPOOL_PROP_DEDUPRATIO,
+    ZPOOL_PROP_FREE,
+    ZPOOL_PROP_ALLOCATED,
+    ZPOOL_PROP_READONLY,
+    ZPOOL_PROP_ASHIFT,
+    ZPOOL_PROP_COMMENT,
+    ZPOOL_PROP_EXPANDSZ,
+    ZPOOL_PROP_FREEING,
+    ZPOOL_PROP_FRAGMENTATION,
+    ZPOOL_PROP_LEAKED,
+    ZPOOL_PROP_MAXBLOCKSIZE,
+    ZPOOL_PROP_TNAME,
+    ZPOOL_PROP_MAXDNODESIZE,
+    ZPOOL_PROP_MULTIHOST,
+    ZPOOL_NUM_PROPS
+}
+
+#[repr(C)]
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum zfs_prop_t {
+    ZPROP_CONT = -2,
+    ZPROP_INVAL = -1,
+    ZFS_PROP_TYPE = 0,
+    ZFS_PROP_CREATION,
+    ZFS_PROP_USED,
+    ZFS_PROP_AVAILABLE,
+    ZFS_PROP_REFERENCED,
+    ZFS_PROP_COMPRESSRATIO,
+    ZFS_PROP_MOUNTED,
+    ZFS_PROP_ORIGIN,
+    ZFS_PROP_QUOTA,
+    ZFS_PROP_RESERVATION,
+    ZFS_PROP_VOLSIZE,
+    ZFS_PROP_VOLBLOCKSIZE,
+    ZFS_PROP_RECORDSIZE,
+    ZFS_PROP_MOUNTPOINT,
+    ZFS_PROP_SHARENFS,
+    ZFS_PROP_CHECKSUM,
+    ZFS_PROP_COMPRESSION,
+    ZFS_PROP_ATIME,
+    ZFS_PROP_DEVICES,
+    ZFS_PROP_EXEC,
+    ZFS_PROP_SETUID,
+    ZFS_PROP_READONLY,
+    ZFS_PROP_ZONED,
+    ZFS_PROP_SNAPDIR,
+    ZFS_PROP_PRIVATE,
+    ZFS_PROP_ACLINHERIT,
+    ZFS_PROP_CREATETXG,
+    ZFS_PROP_NAME,
+    ZFS_PROP_CANMOUNT,
+    ZFS_PROP_ISCSIOPTIONS,
+    ZFS_PROP_XATTR,
+    ZFS_PROP_NUMCLONES,
+    ZFS_PROP_COPIES,
+    ZFS_PROP_VERSION,
+    ZFS_PROP_UTF8ONLY,
+    ZFS_PROP_NORMALIZE,
+    ZFS_PROP_CASE,
+    ZFS_PROP_VSCAN,
+    ZFS_PROP_NBMAND,
+    ZFS_PROP_SHARESMB,
+    ZFS_PROP_REFQUOTA,
+    ZFS_PROP_REFRESERVATION,
+    ZFS_PROP_GUID,
+    ZFS_PROP_PRIMARYCACHE,
+    ZFS_PROP_SECONDARYCACHE,
+    ZFS_PROP_USEDSNAP,
+    ZFS_PROP_USEDDS,
+    ZFS_PROP_USEDCHILD,
+    ZFS_PROP_USEDREFRESERV,
+    ZFS_PROP_USERACCOUNTING,
+    ZFS_PROP_STMF_SHAREINFO,
+    ZFS_PROP_DEFER_DESTROY,
+    ZFS_PROP_USERREFS,
+    ZFS_PROP_LOGBIAS,
+    ZFS_PROP_UNIQUE,
+    ZFS_PROP_OBJSETID,
+    ZFS_PROP_DEDUP,
+    ZFS_PROP_MLSLABEL,
+    ZFS_PROP_SYNC,
+    ZFS_PROP_DNODESIZE,
+    ZFS_PROP_REFRATIO,
+    ZFS_PROP_WRITTEN,
+    ZFS_PROP_CLONES,
+    ZFS_PROP_LOGICALUSED,
+    ZFS_PROP_LOGICALREFERENCED,
+    ZFS_PROP_INCONSISTENT,
+    ZFS_PROP_VOLMODE,
+    ZFS_PROP_FILESYSTEM_LIMIT,
+    ZFS_PROP_SNAPSHOT_LIMIT,
+    ZFS_PROP_FILESYSTEM_COUNT,
+    ZFS_PROP_SNAPSHOT_COUNT,
+    ZFS_PROP_SNAPDEV,
+    ZFS_PROP_ACLTYPE,
+    ZFS_PROP_SELINUX_CONTEXT,
+    ZFS_PROP_SELINUX_FSCONTEXT,
+    ZFS_PROP_SELINUX_DEFCONTEXT,
+    ZFS_PROP_SELINUX_ROOTCONTEXT,
+    ZFS_PROP_RELATIME,
+    ZFS_PROP_REDUNDANT_METADATA,
+    ZFS_PROP_OVERLAY,
+    ZFS_PROP_PREV_SNAP,
+    ZFS_PROP_RECEIVE_RESUME_TOKEN,
+    ZFS_NUM_PROPS
+}
+
+#[repr(C)]
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum zprop_source_t {
+    ZPROP_SRC_NONE = 0x1,
+    ZPROP_SRC_DEFAULT = 0x2,
+    ZPROP_SRC_TEMPORARY = 0x4,
+    ZPROP_SRC_LOCAL = 0x8,
+    ZPROP_SRC_INHERITED = 0x10,
+    ZPROP_SRC_RECEIVED = 0x20
+}
+
+pub const ZPOOL_MAXPROPLEN: usize = 4096;
+pub const ZFS_MAXPROPLEN: usize = 4096;
+
 pub type zfs_handle_t = c_void;
 pub type zpool_handle_t = c_void;
 pub type libzfs_handle_t = c_void;
 
+pub type zpool_iter_f = extern "C" fn(zhp: *mut zpool_handle_t, data: *mut c_void) -> c_int;
+pub type zfs_iter_f = extern "C" fn(zhp: *mut zfs_handle_t, data: *mut c_void) -> c_int;
+
+pub type zfs_type_t = c_int;
+pub const ZFS_TYPE_FILESYSTEM: zfs_type_t = 1 << 0;
+pub const ZFS_TYPE_SNAPSHOT: zfs_type_t = 1 << 1;
+pub const ZFS_TYPE_VOLUME: zfs_type_t = 1 << 2;
+pub const ZFS_TYPE_POOL: zfs_type_t = 1 << 3;
+pub const ZFS_TYPE_BOOKMARK: zfs_type_t = 1 << 4;
+pub const ZFS_TYPE_DATASET: zfs_type_t = ZFS_TYPE_FILESYSTEM | ZFS_TYPE_VOLUME | ZFS_TYPE_SNAPSHOT;
+
 #[link(name="zfs", kind="dylib")]
 extern {
     pub fn libzfs_init() -> *mut libzfs_handle_t;
     pub fn libzfs_fini(handle: *mut libzfs_handle_t);
     pub fn libzfs_errno(handle: *mut libzfs_handle_t) -> c_int;
+    pub fn libzfs_error_action(handle: *mut libzfs_handle_t) -> *const c_char;
+    pub fn libzfs_error_description(handle: *mut libzfs_handle_t) -> *const c_char;
+    pub fn libzfs_print_on_error(handle: *mut libzfs_handle_t, printerr: boolean_t);
+
+    pub fn zpool_iter(handle: *mut libzfs_handle_t, func: zpool_iter_f, data: *mut c_void) -> c_int;
+    pub fn zpool_open(handle: *mut libzfs_handle_t, name: *const c_char) -> *mut zpool_handle_t;
+    pub fn zpool_open_canfail(handle: *mut libzfs_handle_t, name: *const c_char) -> *mut zpool_handle_t;
+    pub fn zpool_close(zhp: *mut zpool_handle_t);
+    pub fn zpool_get_name(zhp: *mut zpool_handle_t) -> *const c_char;
+    pub fn zpool_get_state(zhp: *mut zpool_handle_t) -> c_int;
+    pub fn zpool_get_prop(zhp: *mut zpool_handle_t, prop: zpool_prop_t, buf: *mut c_char, len: usize,
+                          srctype: *mut zprop_source_t, literal: boolean_t) -> c_int;
+    pub fn zpool_get_prop_int(zhp: *mut zpool_handle_t, prop: zpool_prop_t, srctype: *mut zprop_source_t) -> u64;
+    pub fn zpool_set_prop(zhp: *mut zpool_handle_t, propname: *const c_char, propval: *const c_char) -> c_int;
+    pub fn zpool_get_features(zhp: *mut zpool_handle_t) -> *mut nvlist_t;
+    pub fn zpool_prop_get_feature(zhp: *mut zpool_handle_t, propname: *const c_char, buf: *mut c_char, len: usize) -> c_int;
+    pub fn zpool_create(handle: *mut libzfs_handle_t, pool: *const c_char, nvroot: *mut nvlist_t,
+                        props: *mut nvlist_t, fsprops: *mut nvlist_t) -> c_int;
+
+    pub fn zfs_open(handle: *mut libzfs_handle_t, name: *const c_char, types: zfs_type_t) -> *mut zfs_handle_t;
+    pub fn zfs_close(zhp: *mut zfs_handle_t);
+    pub fn zfs_get_name(zhp: *const zfs_handle_t) -> *const c_char;
+    pub fn zfs_get_type(zhp: *const zfs_handle_t) -> zfs_type_t;
+    pub fn zfs_prop_get(zhp: *mut zfs_handle_t, prop: zfs_prop_t, buf: *mut c_char, len: usize,
+                        src: *mut zprop_source_t, statbuf: *mut c_char, statlen: usize, literal: boolean_t) -> c_int;
+    pub fn zfs_prop_get_numeric(zhp: *mut zfs_handle_t, prop: zfs_prop_t, value: *mut u64,
+                                src: *mut zprop_source_t, statbuf: *mut c_char, statlen: usize) -> c_int;
+    pub fn zfs_prop_set(zhp: *mut zfs_handle_t, propname: *const c_char, propval: *const c_char) -> c_int;
+    pub fn zfs_prop_set_list(zhp: *mut zfs_handle_t, props: *mut nvlist_t) -> c_int;
+    pub fn zfs_prop_inherit(zhp: *mut zfs_handle_t, propname: *const c_char, received: boolean_t) -> c_int;
+    pub fn zfs_prop_valid_for_type(prop: zfs_prop_t, types: zfs_type_t, headcheck: boolean_t) -> boolean_t;
+    pub fn zfs_create(handle: *mut libzfs_handle_t, path: *const c_char, typ: zfs_type_t, props: *mut nvlist_t) -> c_int;
+    pub fn zfs_create_ancestors(handle: *mut libzfs_handle_t, path: *const c_char) -> c_int;
+    pub fn zfs_dataset_exists(handle: *mut libzfs_handle_t, path: *const c_char, types: zfs_type_t) -> boolean_t;
+    pub fn zfs_get_user_props(zhp: *mut zfs_handle_t) -> *mut nvlist_t;
+    pub fn zfs_iter_root(handle: *mut libzfs_handle_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+    pub fn zfs_iter_children(zhp: *mut zfs_handle_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+    pub fn zfs_iter_filesystems(zhp: *mut zfs_handle_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+    pub fn zfs_iter_snapshots(zhp: *mut zfs_handle_t, simple: boolean_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+    pub fn zfs_iter_bookmarks(zhp: *mut zfs_handle_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+    pub fn zfs_iter_dependents(zhp: *mut zfs_handle_t, allowrecursion: boolean_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+    pub fn zfs_iter_snapshots_sorted(zhp: *mut zfs_handle_t, func: zfs_iter_f, data: *mut c_void) -> c_int;
+
+    pub fn zfs_unmount(zhp: *mut zfs_handle_t, mountpoint: *const c_char, flags: c_int) -> c_int;
+    pub fn zfs_destroy(zhp: *mut zfs_handle_t, defer: boolean_t) -> c_int;
+    pub fn zfs_rollback(zhp: *mut zfs_handle_t, snap: *mut zfs_handle_t, force: boolean_t) -> c_int;
+    pub fn zfs_rename(zhp: *mut zfs_handle_t, target: *const c_char, recursive: boolean_t, force_unmount: boolean_t) -> c_int;
+    pub fn zfs_clone(zhp: *mut zfs_handle_t, target: *const c_char, props: *mut nvlist_t) -> c_int;
+    pub fn zfs_promote(zhp: *mut zfs_handle_t) -> c_int;
+    pub fn zfs_destroy_snaps_nvl(handle: *mut libzfs_handle_t, snaps: *mut nvlist_t, defer: boolean_t) -> c_int;
+}
+
+// The libzfs_core ABI of ZFS on Linux 0.7. Later releases change some
+// signatures in place, e.g. 0.8 adds a `raw` argument to the lzc_receive
+// functions along with new send flags, so nothing newer is bound here.
+pub type lzc_send_flags = c_int;
+pub const LZC_SEND_FLAG_EMBED_DATA: lzc_send_flags = 1 << 0;
+pub const LZC_SEND_FLAG_LARGE_BLOCK: lzc_send_flags = 1 << 1;
+pub const LZC_SEND_FLAG_COMPRESS: lzc_send_flags = 1 << 2;
+
+#[link(name="zfs_core", kind="dylib")]
+extern {
+    pub fn lzc_snapshot(snaps: *mut nvlist_t, props: *mut nvlist_t, errlist: *mut *mut nvlist_t) -> c_int;
+    pub fn lzc_send(snapname: *const c_char, from: *const c_char, fd: c_int, flags: lzc_send_flags) -> c_int;
+    pub fn lzc_send_space(snapname: *const c_char, from: *const c_char, flags: lzc_send_flags, spacep: *mut u64) -> c_int;
+    pub fn lzc_send_resume(snapname: *const c_char, from: *const c_char, fd: c_int, flags: lzc_send_flags,
+                           resumeobj: u64, resumeoff: u64) -> c_int;
+    pub fn lzc_receive(snapname: *const c_char, props: *mut nvlist_t, origin: *const c_char, force: boolean_t,
+                       fd: c_int) -> c_int;
+    pub fn lzc_receive_resumable(snapname: *const c_char, props: *mut nvlist_t, origin: *const c_char,
+                                 force: boolean_t, fd: c_int) -> c_int;
+    pub fn lzc_receive_with_header(snapname: *const c_char, props: *mut nvlist_t, origin: *const c_char,
+                                   force: boolean_t, resumable: boolean_t, fd: c_int,
+                                   begin_record: *const c_void) -> c_int;
+    pub fn lzc_snaprange_space(firstsnap: *const c_char, lastsnap: *const c_char, usedp: *mut u64) -> c_int;
+    pub fn lzc_bookmark(bookmarks: *mut nvlist_t, errlist: *mut *mut nvlist_t) -> c_int;
+    pub fn lzc_get_bookmarks(fsname: *const c_char, props: *mut nvlist_t, bmarks: *mut *mut nvlist_t) -> c_int;
+    pub fn lzc_destroy_bookmarks(bmarks: *mut nvlist_t, errlist: *mut *mut nvlist_t) -> c_int;
+    pub fn lzc_hold(holds: *mut nvlist_t, cleanup_fd: c_int, errlist: *mut *mut nvlist_t) -> c_int;
+    pub fn lzc_release(holds: *mut nvlist_t, errlist: *mut *mut nvlist_t) -> c_int;
+    pub fn lzc_get_holds(snapname: *const c_char, holdsp: *mut *mut nvlist_t) -> c_int;
 }
 
 #[cfg(test)]
@@ -103,4 +365,14 @@ mod test {
         assert!(handle != ::std::ptr::null_mut());
         unsafe { libzfs_fini(handle) };
     }
+
+    #[test]
+    fn error_from_raw() {
+        assert_eq!(zfs_error_t::from_raw(0), zfs_error_t::EZFS_SUCCESS);
+        assert_eq!(zfs_error_t::from_raw(2000), zfs_error_t::EZFS_NOMEM);
+        assert_eq!(zfs_error_t::from_raw(2009), zfs_error_t::EZFS_NOENT);
+        assert_eq!(zfs_error_t::from_raw(zfs_error_t::EZFS_POOLREADONLY as c_int), zfs_error_t::EZFS_POOLREADONLY);
+        assert_eq!(zfs_error_t::from_raw(9999), zfs_error_t::EZFS_UNKNOWN);
+        assert_eq!(zfs_error_t::from_raw(-1), zfs_error_t::EZFS_UNKNOWN);
+    }
 }